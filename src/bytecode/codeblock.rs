@@ -0,0 +1,101 @@
+//! Compiled function, script or eval code.
+use super::directive::has_use_strict_directive;
+use super::expression_info::*;
+use crate::runtime::jsvalue::JSValue;
+use crate::runtime::Runtime;
+use cgc::api::{Finalizer, Traceable, Tracer};
+
+pub struct CodeBlock {
+    /// Function name, empty for top level and eval code.
+    pub name: String,
+    /// URL or path of the script this code comes from.
+    pub source_url: String,
+    /// Code starts with `"use strict"` directive or is nested in strict code.
+    pub strict: bool,
+    pub expression_info: ExpressionInfo,
+    /// Interned property names of `get_by_id`, `put_by_id` and `del_by_id` instructions.
+    pub identifiers: Vec<JSValue>,
+    /// Constant pool, literals loaded by the code.
+    pub constants: Vec<JSValue>,
+}
+
+impl CodeBlock {
+    pub fn new(name: &str, source_url: &str, strict: bool) -> Self {
+        Self {
+            name: name.to_owned(),
+            source_url: source_url.to_owned(),
+            strict,
+            expression_info: ExpressionInfo::new(),
+            identifiers: vec![],
+            constants: vec![],
+        }
+    }
+
+    /// Creates code block for `source`, which is strict when it is nested in strict code or its
+    /// directive prologue has `"use strict"`. `source` of function code is the function body.
+    pub fn for_source(name: &str, source_url: &str, source: &str, outer_strict: bool) -> Self {
+        Self::new(
+            name,
+            source_url,
+            outer_strict || has_use_strict_directive(source),
+        )
+    }
+
+    /// Interns `name` and returns its index in [CodeBlock::identifiers].
+    pub fn add_identifier(&mut self, rt: &mut Runtime, name: &str) -> u32 {
+        let atom = rt.intern(name);
+        if let Some(index) = self.identifiers.iter().position(|id| *id == atom) {
+            return index as u32;
+        }
+        self.identifiers.push(atom);
+        self.identifiers.len() as u32 - 1
+    }
+
+    /// Adds `value` to the constant pool and returns its index. Equal numbers and strings share
+    /// one entry.
+    pub fn add_constant(&mut self, value: JSValue) -> u32 {
+        let same = |constant: &JSValue| {
+            if value.is_string() && constant.is_string() {
+                *value.as_cell().unchecked_string() == *constant.as_cell().unchecked_string()
+            } else {
+                value.is_number() && constant.is_number() && value == *constant
+            }
+        };
+        if let Some(index) = self.constants.iter().position(same) {
+            return index as u32;
+        }
+        self.constants.push(value);
+        self.constants.len() as u32 - 1
+    }
+
+    /// Returns source range of instruction at `pc`.
+    pub fn expression_range_for(&self, pc: u32) -> Option<ExpressionRange> {
+        self.expression_info.lookup(pc)
+    }
+
+    /// Returns line and column where the expression of instruction at `pc` starts.
+    pub fn line_column_for(&self, pc: u32) -> Option<SourcePosition> {
+        self.expression_range_for(pc).map(|range| range.start)
+    }
+}
+
+impl Traceable for CodeBlock {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.identifiers.trace_with(tracer);
+        self.constants.trace_with(tracer);
+    }
+}
+
+impl Finalizer for CodeBlock {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strictness_from_source() {
+        assert!(CodeBlock::for_source("", "a.js", "'use strict'; x = 1", false).strict);
+        assert!(CodeBlock::for_source("f", "a.js", "return this", true).strict);
+        assert!(!CodeBlock::for_source("", "a.js", "x = 1; 'use strict'", false).strict);
+    }
+}
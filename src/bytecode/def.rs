@@ -86,6 +86,27 @@ macro_rules! op_list {
                 },
                 str: "argument_count"
             },
+            Call {
+                args: {
+                    dst: VirtualRegister,
+                    callee: VirtualRegister,
+                    this: VirtualRegister,
+                    #[doc = "First of `argc` consecutive argument registers"]
+                    argv: VirtualRegister,
+                    argc: u32
+                },
+                str: "call"
+            },
+            Construct {
+                args: {
+                    dst: VirtualRegister,
+                    callee: VirtualRegister,
+                    #[doc = "First of `argc` consecutive argument registers"]
+                    argv: VirtualRegister,
+                    argc: u32
+                },
+                str: "construct"
+            },
             Mov {
                 args: {
                     dst: VirtualRegister,
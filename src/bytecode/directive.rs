@@ -0,0 +1,167 @@
+//! Directive prologue scanning.
+//!
+//! Strictness of a script, eval code or function body is decided by its
+//! [directive prologue](https://tc39.es/ecma262/#sec-directive-prologues-and-the-use-strict-directive):
+//! the string literal statements it starts with. The `"use strict"` directive must be written
+//! exactly like that, without escapes or line continuations, in single or double quotes.
+//!
+//! The scanner only understands what can appear before the first statement that is not a
+//! directive: whitespace, comments and string literals followed by `;`, `}`, a line terminator or
+//! the end of source. A string literal followed by something that continues the expression, like
+//! `"use strict" + x` or `"use strict"\n.length`, is an expression statement and ends the prologue.
+
+fn is_line_terminator(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\u{b}' | '\u{c}' | ' ' | '\u{a0}' | '\u{feff}')
+        || (!c.is_ascii() && c.is_whitespace() && !is_line_terminator(c))
+}
+
+struct Scanner<'a> {
+    source: &'a str,
+    offset: usize,
+}
+
+impl Scanner<'_> {
+    fn rest(&self) -> &str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skips whitespace and comments, returns whether a line terminator was skipped or `None` if
+    /// a block comment is not closed.
+    fn skip_trivia(&mut self) -> Option<bool> {
+        let mut newline = false;
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("//") {
+                let end = rest.find(is_line_terminator).unwrap_or(rest.len());
+                self.offset += end;
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                let end = comment.find("*/")?;
+                newline |= comment[..end].contains(is_line_terminator);
+                self.offset += end + 4;
+            } else {
+                match self.peek() {
+                    Some(c) if is_line_terminator(c) => newline = true,
+                    Some(c) if is_whitespace(c) => (),
+                    _ => return Some(newline),
+                }
+                self.bump();
+            }
+        }
+    }
+
+    /// Scans a string literal, returns its raw text between the quotes.
+    fn string_literal(&mut self) -> Option<&str> {
+        let quote = self.bump()?;
+        let start = self.offset;
+        loop {
+            match self.bump()? {
+                c if c == quote => break,
+                '\\' => {
+                    self.bump()?;
+                }
+                '\n' | '\r' => return None,
+                _ => (),
+            }
+        }
+        Some(&self.source[start..self.offset - 1])
+    }
+
+    /// Returns true if the next token can continue an expression statement started on a previous
+    /// line, in which case no semicolon is inserted before it.
+    fn continues_expression(&self) -> bool {
+        let rest = self.rest();
+        if let Some(c) = rest.chars().next() {
+            if "+-*/%&|^<>=!?,.([`".contains(c) {
+                return !rest.starts_with("++") && !rest.starts_with("--");
+            }
+        }
+        ["in", "instanceof"].iter().any(|keyword| {
+            rest.starts_with(keyword)
+                && !rest[keyword.len()..]
+                    .starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+        })
+    }
+}
+
+/// Returns true if the directive prologue of `source` contains a `"use strict"` directive.
+pub fn has_use_strict_directive(source: &str) -> bool {
+    let mut scanner = Scanner { source, offset: 0 };
+    let mut strict = false;
+    loop {
+        if scanner.skip_trivia().is_none() {
+            return false;
+        }
+        if !matches!(scanner.peek(), Some('"') | Some('\'')) {
+            return strict;
+        }
+        let directive = match scanner.string_literal() {
+            Some(directive) => directive == "use strict",
+            None => return false,
+        };
+        let newline = match scanner.skip_trivia() {
+            Some(newline) => newline,
+            None => return false,
+        };
+        match scanner.peek() {
+            Some(';') => {
+                scanner.bump();
+            }
+            None | Some('}') => return strict || directive,
+            Some(_) if newline && !scanner.continues_expression() => (),
+            Some(_) => return strict,
+        }
+        strict |= directive;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn use_strict_directive() {
+        let strict = [
+            "'use strict'",
+            "\"use strict\";",
+            "  // comment\n/* block */ 'use strict'; x = 1",
+            "'use asm'; \"use strict\"\nfoo()",
+            "'a'\n'use strict'\nvar x;",
+            "\"use strict\"\n++x",
+            "'use strict' /* trailing */ }",
+        ];
+        for source in strict.iter() {
+            assert!(has_use_strict_directive(source), "{:?}", source);
+        }
+        let sloppy = [
+            "",
+            "x = 1; 'use strict'",
+            "'use\\x20strict'",
+            "'use strict' + x",
+            "'use strict'\n.length",
+            "'use strict'\n+ 1",
+            "'use strict' in x",
+            "\"use strict\"\ninstanceof Foo",
+            "'use strict ';",
+            "'use strict",
+            "/* 'use strict'",
+            "(\"use strict\")",
+        ];
+        for source in sloppy.iter() {
+            assert!(!has_use_strict_directive(source), "{:?}", source);
+        }
+    }
+}
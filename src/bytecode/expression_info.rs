@@ -0,0 +1,340 @@
+//! Expression info: mapping from instruction offsets to source ranges.
+//!
+//! The bytecode generator calls [ExpressionInfo::add] before emitting each instruction that can
+//! throw or that the debugger may stop at. Error stack traces and the debugger then use
+//! [ExpressionInfo::lookup] to find where in the source an instruction came from.
+//!
+//! A table may have an entry for almost every instruction so entries are not stored as structs.
+//! Each entry is encoded as the difference from the previous one using LEB128 varints, which is
+//! usually 5 bytes per entry. Every [CHECKPOINT_INTERVAL] entries a full entry is kept as a
+//! checkpoint, so lookup is a binary search over checkpoints followed by decoding at most
+//! `CHECKPOINT_INTERVAL - 1` entries.
+
+/// Number of entries between two checkpoints.
+pub const CHECKPOINT_INTERVAL: usize = 32;
+
+/// Position in source code, both line and column start at 1.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SourcePosition {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl SourcePosition {
+    pub fn new(line: u32, column: u32) -> Self {
+        Self { line, column }
+    }
+}
+
+/// Source range of an expression, `end` is exclusive.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ExpressionRange {
+    pub instruction_offset: u32,
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+struct Checkpoint {
+    entry: ExpressionRange,
+    /// Offset in `data` where entry following this checkpoint starts.
+    data_offset: u32,
+}
+
+#[derive(Default)]
+pub struct ExpressionInfo {
+    data: Vec<u8>,
+    checkpoints: Vec<Checkpoint>,
+    last: ExpressionRange,
+    len: usize,
+}
+
+fn write_unsigned(data: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+fn write_signed(data: &mut Vec<u8>, value: i64) {
+    // Zigzag encoding keeps small negative deltas small.
+    write_unsigned(data, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn read_unsigned(data: &[u8], pos: &mut usize) -> u64 {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return result;
+        }
+        shift += 7;
+    }
+}
+
+fn read_signed(data: &[u8], pos: &mut usize) -> i64 {
+    let value = read_unsigned(data, pos);
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+impl ExpressionInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Approximate memory used by the table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.data.capacity() + self.checkpoints.capacity() * std::mem::size_of::<Checkpoint>()
+    }
+
+    /// Records that instruction at `instruction_offset` was generated from source range `start..end`.
+    ///
+    /// Offsets must be added in non-decreasing order.
+    pub fn add(&mut self, instruction_offset: u32, start: SourcePosition, end: SourcePosition) {
+        let entry = ExpressionRange {
+            instruction_offset,
+            start,
+            end,
+        };
+        assert!(
+            instruction_offset >= self.last.instruction_offset,
+            "expression info must be added in instruction order"
+        );
+        if self.len.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.checkpoints.push(Checkpoint {
+                entry,
+                data_offset: self.data.len() as u32,
+            });
+        } else {
+            let data = &mut self.data;
+            write_unsigned(
+                data,
+                (instruction_offset - self.last.instruction_offset) as u64,
+            );
+            write_signed(data, start.line as i64 - self.last.start.line as i64);
+            write_signed(data, start.column as i64 - self.last.start.column as i64);
+            write_signed(data, end.line as i64 - start.line as i64);
+            write_signed(data, end.column as i64 - start.column as i64);
+        }
+        self.last = entry;
+        self.len += 1;
+    }
+
+    fn decode(&self, pos: &mut usize, prev: &ExpressionRange) -> ExpressionRange {
+        let data = &self.data;
+        let instruction_offset = prev.instruction_offset + read_unsigned(data, pos) as u32;
+        let line = (prev.start.line as i64 + read_signed(data, pos)) as u32;
+        let column = (prev.start.column as i64 + read_signed(data, pos)) as u32;
+        let end_line = (line as i64 + read_signed(data, pos)) as u32;
+        let end_column = (column as i64 + read_signed(data, pos)) as u32;
+        ExpressionRange {
+            instruction_offset,
+            start: SourcePosition::new(line, column),
+            end: SourcePosition::new(end_line, end_column),
+        }
+    }
+
+    /// Returns range of the last entry recorded at or before `instruction_offset`.
+    pub fn lookup(&self, instruction_offset: u32) -> Option<ExpressionRange> {
+        let index = self
+            .checkpoints
+            .partition_point(|cp| cp.entry.instruction_offset <= instruction_offset);
+        if index == 0 {
+            return None;
+        }
+        let checkpoint = &self.checkpoints[index - 1];
+        let decoded = if index == self.checkpoints.len() {
+            self.len - (index - 1) * CHECKPOINT_INTERVAL
+        } else {
+            CHECKPOINT_INTERVAL
+        };
+        let mut result = checkpoint.entry;
+        let mut pos = checkpoint.data_offset as usize;
+        for _ in 1..decoded {
+            let next = self.decode(&mut pos, &result);
+            if next.instruction_offset > instruction_offset {
+                break;
+            }
+            result = next;
+        }
+        Some(result)
+    }
+
+    /// Iterates over all entries in instruction order.
+    pub fn iter(&self) -> impl Iterator<Item = ExpressionRange> + '_ {
+        let mut pos = 0;
+        let mut prev = ExpressionRange::default();
+        (0..self.len).map(move |i| {
+            if i.is_multiple_of(CHECKPOINT_INTERVAL) {
+                let checkpoint = &self.checkpoints[i / CHECKPOINT_INTERVAL];
+                pos = checkpoint.data_offset as usize;
+                prev = checkpoint.entry;
+            } else {
+                prev = self.decode(&mut pos, &prev);
+            }
+            prev
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(
+        offset: u32,
+        line: u32,
+        column: u32,
+        end_line: u32,
+        end_column: u32,
+    ) -> ExpressionRange {
+        ExpressionRange {
+            instruction_offset: offset,
+            start: SourcePosition::new(line, column),
+            end: SourcePosition::new(end_line, end_column),
+        }
+    }
+
+    fn table(entries: &[ExpressionRange]) -> ExpressionInfo {
+        let mut info = ExpressionInfo::new();
+        for entry in entries {
+            info.add(entry.instruction_offset, entry.start, entry.end);
+        }
+        info
+    }
+
+    #[test]
+    fn leb128_round_trip() {
+        let unsigned = [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (u32::MAX as u64, 5),
+            (u64::MAX, 10),
+        ];
+        for (value, size) in unsigned.iter() {
+            let mut data = vec![];
+            write_unsigned(&mut data, *value);
+            assert_eq!(data.len(), *size, "{}", value);
+            let mut pos = 0;
+            assert_eq!(read_unsigned(&data, &mut pos), *value);
+            assert_eq!(pos, data.len());
+        }
+    }
+
+    #[test]
+    fn zigzag_round_trip() {
+        // Small magnitudes of either sign take one byte.
+        let signed = [
+            (0, 0),
+            (-1, 1),
+            (1, 2),
+            (-2, 3),
+            (63, 126),
+            (-64, 127),
+            (64, 128),
+        ];
+        for (value, encoded) in signed.iter() {
+            let mut data = vec![];
+            write_signed(&mut data, *value);
+            let mut pos = 0;
+            assert_eq!(read_unsigned(&data, &mut pos), *encoded, "{}", value);
+        }
+        let mut data = vec![];
+        let values = [
+            i64::MIN,
+            i64::MAX,
+            -(u32::MAX as i64),
+            u32::MAX as i64,
+            -300,
+        ];
+        for value in values.iter() {
+            write_signed(&mut data, *value);
+        }
+        let mut pos = 0;
+        for value in values.iter() {
+            assert_eq!(read_signed(&data, &mut pos), *value);
+        }
+        assert_eq!(pos, data.len());
+    }
+
+    #[test]
+    fn round_trip_across_checkpoints() {
+        // Lines and columns move backwards too, e.g. for the callee of a multi-line call.
+        let entries: Vec<_> = (0..100u32)
+            .map(|i| {
+                let line = if i % 3 == 0 { 1000 - i } else { 1000 + i * 7 };
+                let column = if i % 2 == 0 { 1 } else { 80 + i };
+                let end_line = line + i % 4;
+                let end_column = if end_line > line { 1 } else { column + 5 };
+                range(i * 3 + i / 10, line, column, end_line, end_column)
+            })
+            .collect();
+        let info = table(&entries);
+        assert_eq!(info.len(), 100);
+        assert_eq!(info.checkpoints.len(), 4);
+        assert_eq!(info.iter().collect::<Vec<_>>(), entries);
+        for (index, entry) in entries.iter().enumerate() {
+            assert_eq!(info.lookup(entry.instruction_offset), Some(*entry));
+            if let Some(next) = entries.get(index + 1) {
+                // Instructions without their own entry map to the previous one.
+                for offset in entry.instruction_offset..next.instruction_offset {
+                    assert_eq!(info.lookup(offset), Some(*entry));
+                }
+            }
+        }
+        for index in [31, 32, 33, 63, 64, 95, 96, 99].iter() {
+            let entry = entries[*index];
+            assert_eq!(info.lookup(entry.instruction_offset), Some(entry));
+        }
+    }
+
+    #[test]
+    fn out_of_range_offsets() {
+        assert_eq!(ExpressionInfo::new().lookup(0), None);
+        let entries: Vec<_> = (0..40u32)
+            .map(|i| range(10 + i * 2, i + 1, 1, i + 1, 9))
+            .collect();
+        let info = table(&entries);
+        assert_eq!(info.lookup(0), None);
+        assert_eq!(info.lookup(9), None);
+        assert_eq!(info.lookup(10), Some(entries[0]));
+        assert_eq!(info.lookup(1000), Some(entries[39]));
+        assert_eq!(info.lookup(u32::MAX), Some(entries[39]));
+    }
+
+    #[test]
+    fn repeated_offsets() {
+        // Several entries for one instruction, the last one wins, also across a checkpoint.
+        let mut entries: Vec<_> = (0..31u32).map(|i| range(i, 1, i + 1, 1, i + 2)).collect();
+        entries.push(range(30, 2, 1, 2, 5));
+        entries.push(range(30, 3, 1, 3, 5));
+        let info = table(&entries);
+        assert_eq!(info.lookup(30), Some(entries[32]));
+        assert_eq!(info.lookup(29), Some(entries[29]));
+    }
+
+    #[test]
+    #[should_panic(expected = "expression info must be added in instruction order")]
+    fn offsets_must_not_decrease() {
+        table(&[range(5, 1, 1, 1, 2), range(4, 1, 1, 1, 2)]);
+    }
+}
@@ -1,4 +1,9 @@
+pub mod codeblock;
+// The opcode list is not expanded anywhere until the interpreter is rewritten.
+#[allow(unused_imports, unused_macros)]
 pub mod def;
+pub mod directive;
+pub mod expression_info;
 pub mod virtualregister;
 
 // Register numbers used in bytecode operations have different meaning according to their ranges:
@@ -3,7 +3,8 @@ extern crate cgc_single_threaded as cgc;
 #[macro_export]
 macro_rules! offset_of {
     ($ty: ty, $field: ident) => {
-        unsafe { &(*(0 as *const $ty)).$field as *const _ as usize }
+        std::mem::offset_of!($ty, $field)
     };
 }
+pub mod bytecode;
 pub mod runtime;
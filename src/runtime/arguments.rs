@@ -0,0 +1,375 @@
+//! `arguments` objects.
+//!
+//! Sloppy functions with simple parameter lists get a mapped arguments object: its indexed
+//! properties alias the parameter bindings, so `arguments[0] = 1` changes the first parameter and
+//! vice versa. Strict functions get an unmapped object that is a plain snapshot of the arguments,
+//! reading or writing its `callee` throws TypeError.
+use super::cell::*;
+use super::jsobject::*;
+use super::jsproperty::*;
+use super::jsvalue::*;
+use super::vtable::*;
+use super::Runtime;
+
+/// Parameter map of mapped arguments object.
+pub struct ArgumentsMap {
+    /// Function environment holding parameters.
+    pub env: JSValue,
+    /// Slot of parameter for every argument index, `None` once index is unmapped.
+    pub slots: Vec<Option<u32>>,
+}
+
+/// VTable of mapped arguments objects.
+pub static ARGUMENTS_VTABLE: VTable = VTable {
+    get_own_property: arguments_get_own_property,
+    define_own_property: arguments_define_own_property,
+    delete: arguments_delete,
+    ..OBJECT_VTABLE
+};
+
+fn mapped_slot(object: JSValue, name: &str) -> Option<(JSValue, u32)> {
+    let index = name.parse::<u32>().ok()?;
+    if index.to_string() != name {
+        return None;
+    }
+    match &object.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::Arguments(map) => {
+            let slot = (*map.slots.get(index as usize)?)?;
+            Some((map.env, slot))
+        }
+        _ => None,
+    }
+}
+
+fn unmap(object: JSValue, name: &str) {
+    let index = name.parse::<usize>().unwrap();
+    if let JSObjectKind::Arguments(map) = &mut object.as_cell().unchecked_object_mut().kind {
+        map.slots[index] = None;
+    }
+}
+
+pub fn arguments_get_own_property(
+    rt: &mut Runtime,
+    object: JSValue,
+    name: &str,
+) -> Option<JSProperty> {
+    let mut desc = object_get_own_property(rt, object, name)?;
+    if let Some((env, slot)) = mapped_slot(object, name) {
+        desc.value = Some(env.as_cell().unchecked_environment_mut().get_slot(slot));
+    }
+    Some(desc)
+}
+
+pub fn arguments_define_own_property(
+    rt: &mut Runtime,
+    object: JSValue,
+    name: &str,
+    desc: JSProperty,
+    throw: bool,
+) -> Result<bool, JSValue> {
+    let mapped = mapped_slot(object, name);
+    let mut new_desc = desc.clone();
+    if let Some((env, slot)) = mapped {
+        if desc.is_data() && desc.value.is_none() && desc.writable == Some(false) {
+            new_desc.value = Some(env.as_cell().unchecked_environment_mut().get_slot(slot));
+        }
+    }
+    if !object_define_own_property(rt, object, name, new_desc, throw)? {
+        return Ok(false);
+    }
+    if let Some((env, slot)) = mapped {
+        if desc.is_accessor() {
+            unmap(object, name);
+        } else {
+            if let Some(value) = desc.value {
+                env.as_cell()
+                    .unchecked_environment_mut()
+                    .set_slot(slot, value);
+                rt.write_barrier(env, value);
+            }
+            if desc.writable == Some(false) {
+                unmap(object, name);
+            }
+        }
+    }
+    Ok(true)
+}
+
+pub fn arguments_delete(
+    rt: &mut Runtime,
+    object: JSValue,
+    name: &str,
+    throw: bool,
+) -> Result<bool, JSValue> {
+    let mapped = mapped_slot(object, name).is_some();
+    let result = object_delete(rt, object, name, throw)?;
+    if result && mapped {
+        unmap(object, name);
+    }
+    Ok(result)
+}
+
+fn define_arguments(obj: &mut JSObject, args: &[JSValue]) {
+    for (index, arg) in args.iter().enumerate() {
+        obj._write(
+            &index.to_string(),
+            JSProperty::new()
+                .value(*arg)
+                .writable(true)
+                .enumerable(true)
+                .configurable(true),
+        );
+    }
+    obj._write(
+        "length",
+        JSProperty::new()
+            .value(JSValue::new_int(args.len() as i32))
+            .writable(true)
+            .enumerable(false)
+            .configurable(true),
+    );
+}
+
+/// Creates [%ThrowTypeError%](https://tc39.es/ecma262/#sec-%throwtypeerror%), the getter and
+/// setter of `callee` of unmapped arguments objects. It is frozen and shared by all of them.
+pub(crate) fn new_throw_type_error(rt: &mut Runtime) -> JSValue {
+    let function = rt.new_native_function("", 0, false, |rt, _, _| {
+        let msg = "'caller', 'callee', and 'arguments' properties may not be accessed on strict \
+                   mode functions or the arguments objects for calls to them";
+        Err(rt.new_type_error(msg))
+    });
+    let mut cell = function.as_cell();
+    let obj = cell.unchecked_object_mut();
+    for name in ["length", "name"].iter() {
+        let desc = obj._read(name).unwrap().configurable(false);
+        obj._write(name, desc);
+    }
+    obj.extensible = false;
+    function
+}
+
+/// [CreateUnmappedArgumentsObject](https://tc39.es/ecma262/#sec-createunmappedargumentsobject)
+pub fn create_unmapped_arguments_object(rt: &mut Runtime, args: &[JSValue]) -> JSValue {
+    let proto = rt.object;
+    let thrower = rt.throw_type_error;
+    let mut obj = JSObject::new(JSObjectKind::Normal, Some(proto), "Arguments");
+    define_arguments(&mut obj, args);
+    obj._write(
+        "callee",
+        JSProperty::new()
+            .getter(thrower)
+            .setter(thrower)
+            .enumerable(false)
+            .configurable(false),
+    );
+    rt.allocate(Cell::Object(Box::new(obj)))
+}
+
+/// [CreateMappedArgumentsObject](https://tc39.es/ecma262/#sec-createmappedargumentsobject)
+///
+/// `parameter_slots[i]` is slot of `i`th formal parameter in `env`, for duplicated parameter
+/// names only the last one should be passed with its slot.
+pub fn create_mapped_arguments_object(
+    rt: &mut Runtime,
+    callee: JSValue,
+    env: JSValue,
+    parameter_slots: &[Option<u32>],
+    args: &[JSValue],
+) -> JSValue {
+    let proto = rt.object;
+    let slots = (0..args.len())
+        .map(|i| parameter_slots.get(i).copied().flatten())
+        .collect();
+    let mut obj = JSObject::new(
+        JSObjectKind::Arguments(ArgumentsMap { env, slots }),
+        Some(proto),
+        "Arguments",
+    );
+    obj.class_object = &ARGUMENTS_VTABLE;
+    define_arguments(&mut obj, args);
+    obj._write(
+        "callee",
+        JSProperty::new()
+            .value(callee)
+            .writable(true)
+            .enumerable(false)
+            .configurable(true),
+    );
+    rt.allocate(Cell::Object(Box::new(obj)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::environment::new_function_environment;
+    use crate::runtime::error::JSError;
+    use crate::runtime::rooting::*;
+
+    fn int(rt: &mut Runtime, object: JSValue, name: &str) -> i32 {
+        object_get(rt, object, name).ok().unwrap().as_int32()
+    }
+
+    fn slot(env: JSValue, slot: u32) -> i32 {
+        env.as_cell()
+            .unchecked_environment_mut()
+            .get_slot(slot)
+            .as_int32()
+    }
+
+    fn type_error<T>(rt: &mut Runtime, result: Result<T, JSValue>) -> String {
+        let error = JSError::from_value(rt, result.err().unwrap());
+        assert_eq!(error.name, "TypeError");
+        error.message
+    }
+
+    /// `function f(a, b) { return arguments }` called as `f(1, 2, 3)`, returns the function
+    /// environment and the arguments object.
+    fn mapped_call(rt: &mut Runtime) -> (JSValue, JSValue) {
+        let scope = HandleScope::new(rt);
+        let undefined = JSValue::undefined();
+        let callee =
+            scope.root(rt.new_native_function("f", 2, false, |_, _, _| Ok(JSValue::undefined())));
+        let env = scope.root(new_function_environment(
+            rt, None, 2, undefined, undefined, undefined, false,
+        ));
+        let args = [1, 2, 3]
+            .iter()
+            .map(|i| JSValue::new_int(*i))
+            .collect::<Vec<_>>();
+        for (index, name) in ["a", "b"].iter().enumerate() {
+            let mut cell = env.get().as_cell();
+            let environment = cell.unchecked_environment_mut();
+            environment.create_mutable_binding(rt, name, false).ok();
+            environment.set_slot(index as u32, args[index]);
+        }
+        let arguments =
+            create_mapped_arguments_object(rt, callee.get(), env.get(), &[Some(0), Some(1)], &args);
+        (env.get(), arguments)
+    }
+
+    #[test]
+    fn mapped_arguments_alias_parameters() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let (env, arguments) = mapped_call(&mut rt);
+        let (env, arguments) = (scope.root(env), scope.root(arguments));
+        assert_eq!(int(&mut rt, arguments.get(), "length"), 3);
+        assert_eq!(int(&mut rt, arguments.get(), "1"), 2);
+
+        // a = 10
+        let mut cell = env.get().as_cell();
+        cell.unchecked_environment_mut()
+            .set_slot(0, JSValue::new_int(10));
+        assert_eq!(int(&mut rt, arguments.get(), "0"), 10);
+
+        // arguments[1] = 20
+        object_put(&mut rt, arguments.get(), "1", JSValue::new_int(20), true)
+            .ok()
+            .unwrap();
+        assert_eq!(slot(env.get(), 1), 20);
+
+        // arguments[2] has no parameter.
+        object_put(&mut rt, arguments.get(), "2", JSValue::new_int(30), true)
+            .ok()
+            .unwrap();
+        assert_eq!(int(&mut rt, arguments.get(), "2"), 30);
+        assert_eq!(slot(env.get(), 0), 10);
+        assert_eq!(slot(env.get(), 1), 20);
+
+        let callee = object_get(&mut rt, arguments.get(), "callee").ok().unwrap();
+        assert!(callee.is_object());
+    }
+
+    #[test]
+    fn delete_and_define_unmap_arguments() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let (env, arguments) = mapped_call(&mut rt);
+        let (env, arguments) = (scope.root(env), scope.root(arguments));
+
+        // delete arguments[0]; arguments[0] = 5
+        let deleted = arguments_delete(&mut rt, arguments.get(), "0", true);
+        assert_eq!(deleted.ok(), Some(true));
+        assert!(!object_has_own_property(&mut rt, arguments.get(), "0"));
+        object_put(&mut rt, arguments.get(), "0", JSValue::new_int(5), true)
+            .ok()
+            .unwrap();
+        assert_eq!(slot(env.get(), 0), 1);
+        assert_eq!(int(&mut rt, arguments.get(), "0"), 5);
+
+        // Object.defineProperty(arguments, 1, { value: 7, writable: false })
+        let desc = JSProperty::new().value(JSValue::new_int(7)).writable(false);
+        arguments_define_own_property(&mut rt, arguments.get(), "1", desc, true)
+            .ok()
+            .unwrap();
+        assert_eq!(slot(env.get(), 1), 7);
+        let mut cell = env.get().as_cell();
+        cell.unchecked_environment_mut()
+            .set_slot(1, JSValue::new_int(8));
+        assert_eq!(int(&mut rt, arguments.get(), "1"), 7);
+
+        // Redefining as an accessor unmaps too.
+        let (env, arguments) = mapped_call(&mut rt);
+        let (env, arguments) = (scope.root(env), scope.root(arguments));
+        let desc = JSProperty::new()
+            .getter(JSValue::undefined())
+            .configurable(true);
+        arguments_define_own_property(&mut rt, arguments.get(), "0", desc, true)
+            .ok()
+            .unwrap();
+        let mut cell = env.get().as_cell();
+        cell.unchecked_environment_mut()
+            .set_slot(0, JSValue::new_int(9));
+        let value = object_get(&mut rt, arguments.get(), "0").ok().unwrap();
+        assert!(value.is_undefined());
+    }
+
+    #[test]
+    fn unmapped_arguments_in_strict_code() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let args = [JSValue::new_int(1)];
+        let arguments = scope.root(create_unmapped_arguments_object(&mut rt, &args));
+        assert_eq!(int(&mut rt, arguments.get(), "0"), 1);
+
+        let callee_msg = "'caller', 'callee', and 'arguments' properties may not be accessed on \
+                          strict mode functions or the arguments objects for calls to them";
+        let result = object_get(&mut rt, arguments.get(), "callee");
+        assert_eq!(type_error(&mut rt, result), callee_msg);
+        let value = JSValue::new_int(2);
+        let result = object_put(&mut rt, arguments.get(), "callee", value, true);
+        assert_eq!(type_error(&mut rt, result), callee_msg);
+        let result = object_delete(&mut rt, arguments.get(), "callee", true);
+        assert_eq!(
+            type_error(&mut rt, result),
+            "Cannot delete property 'callee' of object"
+        );
+        // Sloppy code only gets `false`.
+        let result = object_delete(&mut rt, arguments.get(), "callee", false);
+        assert_eq!(result.ok(), Some(false));
+
+        // Object.freeze(arguments); arguments[0] = 2
+        let desc = JSProperty::new().writable(false).configurable(false);
+        object_define_own_property(&mut rt, arguments.get(), "0", desc, true)
+            .ok()
+            .unwrap();
+        let result = object_put(&mut rt, arguments.get(), "0", value, true);
+        assert_eq!(
+            type_error(&mut rt, result),
+            "Cannot assign to read only property '0' of object"
+        );
+        assert!(object_put(&mut rt, arguments.get(), "0", value, false).is_ok());
+        assert_eq!(int(&mut rt, arguments.get(), "0"), 1);
+    }
+
+    #[test]
+    fn throw_type_error_is_frozen() {
+        let mut rt = Runtime::new();
+        let thrower = rt.throw_type_error;
+        let desc = JSProperty::new().value(JSValue::new_int(1));
+        let defined = object_define_own_property(&mut rt, thrower, "x", desc, false);
+        assert_eq!(defined.ok(), Some(false));
+        let result = object_delete(&mut rt, thrower, "length", false);
+        assert_eq!(result.ok(), Some(false));
+    }
+}
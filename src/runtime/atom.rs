@@ -0,0 +1,275 @@
+//! Atom table.
+//!
+//! Interned strings are atoms: equal atoms share one string cell and compare by pointer. Atoms are
+//! flat strings with [JSString::is_atom] set, see [Runtime::intern] and [Runtime::intern_string].
+//!
+//! Atoms interned by name (identifiers of code blocks, names used by embedders) are pinned and live
+//! as long as the runtime. Computed property keys come from arbitrary strings, so the atoms
+//! [Runtime::to_property_key] creates are weak: the table drops them once nothing else refers to
+//! them, the same way weak containers drop dead entries (see `jsweak` module).
+//!
+//! Object property maps are still keyed by Rust strings, atoms only give property names a shared
+//! cell for now.
+use super::jsstring::*;
+use super::jsvalue::*;
+use super::Runtime;
+use cgc::api::{Finalizer, Traceable, Tracer};
+use fxhash::FxHashMap;
+
+#[derive(Copy, Clone)]
+struct Atom {
+    value: JSValue,
+    /// Pinned atoms are roots, the others are weak.
+    pinned: bool,
+}
+
+#[derive(Default)]
+pub struct AtomTable {
+    /// Atoms by hash of their code units.
+    buckets: FxHashMap<u32, Vec<Atom>>,
+    len: usize,
+    weak: usize,
+}
+
+impl AtomTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of weak atoms, these are swept by every collection.
+    pub fn weak_len(&self) -> usize {
+        self.weak
+    }
+
+    /// Returns the atom with hash `hash` for which `eq` returns true, pins it if `pin` is set.
+    fn find(
+        &mut self,
+        hash: u32,
+        pin: bool,
+        mut eq: impl FnMut(&JSString) -> bool,
+    ) -> Option<JSValue> {
+        let atom = self
+            .buckets
+            .get_mut(&hash)?
+            .iter_mut()
+            .find(|atom| eq(atom.value.as_cell().unchecked_string()))?;
+        if pin && !atom.pinned {
+            atom.pinned = true;
+            self.weak -= 1;
+        }
+        Some(atom.value)
+    }
+
+    fn insert(&mut self, hash: u32, value: JSValue, pinned: bool) {
+        self.buckets
+            .entry(hash)
+            .or_default()
+            .push(Atom { value, pinned });
+        self.len += 1;
+        if !pinned {
+            self.weak += 1;
+        }
+    }
+
+    pub fn atoms(&self) -> impl Iterator<Item = &JSValue> {
+        self.buckets.values().flatten().map(|atom| &atom.value)
+    }
+
+    /// Removes weak atoms for which `is_marked` returns false, called with the cells reachable
+    /// from the roots marked.
+    pub(crate) fn sweep(&mut self, mut is_marked: impl FnMut(JSValue) -> bool) {
+        if self.weak == 0 {
+            return;
+        }
+        let (mut len, mut weak) = (0, 0);
+        self.buckets.retain(|_, atoms| {
+            atoms.retain(|atom| atom.pinned || is_marked(atom.value));
+            len += atoms.len();
+            weak += atoms.iter().filter(|atom| !atom.pinned).count();
+            !atoms.is_empty()
+        });
+        self.len = len;
+        self.weak = weak;
+    }
+
+    /// Traces weak atoms so the collector relocates them, only after [AtomTable::sweep] removed
+    /// the dead ones.
+    pub(crate) fn trace_weak(&self, tracer: &mut Tracer) {
+        self.buckets
+            .values()
+            .flatten()
+            .filter(|atom| !atom.pinned)
+            .for_each(|atom| atom.value.trace_with(tracer));
+    }
+}
+
+impl Runtime {
+    /// Returns the pinned atom equal to `s`, creating it if `s` was not interned yet.
+    pub fn intern(&mut self, s: &str) -> JSValue {
+        let hash = hash_code_units(s.encode_utf16());
+        if let Some(atom) = self
+            .atoms
+            .find(hash, true, |atom| atom.code_units().eq(s.encode_utf16()))
+        {
+            return atom;
+        }
+        let atom = self.new_string(s);
+        self.insert_atom(hash, atom, true)
+    }
+
+    /// Returns the pinned atom equal to string `value`. If there is none `value` becomes the atom,
+    /// ropes are flattened in place and substrings are copied so atoms do not keep a base string
+    /// alive.
+    pub fn intern_string(&mut self, value: JSValue) -> JSValue {
+        self.intern_string_with(value, true)
+    }
+
+    fn intern_string_with(&mut self, value: JSValue, pin: bool) -> JSValue {
+        self.assert_live(value);
+        let mut cell = value.as_cell();
+        let s = cell.unchecked_string_mut();
+        if s.is_atom() {
+            return value;
+        }
+        let hash = s.hash_code();
+        if let Some(atom) = self.atoms.find(hash, pin, |atom| atom == &*s) {
+            return atom;
+        }
+        s.flatten();
+        let atom = if s.is_substring() {
+            let units = s.to_utf16();
+            self.new_string_from_utf16(&units)
+        } else {
+            value
+        };
+        self.insert_atom(hash, atom, pin)
+    }
+
+    fn insert_atom(&mut self, hash: u32, atom: JSValue, pinned: bool) -> JSValue {
+        atom.as_cell().unchecked_string_mut().mark_atom();
+        self.atoms.insert(hash, atom, pinned);
+        atom
+    }
+
+    /// [ToPropertyKey](https://tc39.es/ecma262/#sec-topropertykey), computed keys are interned as
+    /// weak atoms.
+    ///
+    /// TODO: Return symbols as they are once symbols are implemented.
+    pub fn to_property_key(&mut self, value: JSValue) -> Result<JSValue, JSValue> {
+        let key = value.to_primitive(self, PreferredType::String)?;
+        let key = key.to_js_string(self)?;
+        Ok(self.intern_string_with(key, false))
+    }
+}
+
+/// Traces pinned atoms, weak ones are traced by [AtomTable::trace_weak].
+impl Traceable for AtomTable {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.buckets
+            .values()
+            .flatten()
+            .filter(|atom| atom.pinned)
+            .for_each(|atom| atom.value.trace_with(tracer));
+    }
+}
+
+impl Finalizer for AtomTable {}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::jsvalue::JSValue;
+    use crate::runtime::rooting::HandleScope;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn equal_names_share_one_cell() {
+        let mut rt = Runtime::new();
+        let a = rt.intern("length");
+        let b = rt.intern("length");
+        assert!(a == b);
+        assert!(a.as_cell().unchecked_string().is_atom());
+        assert!(rt.intern("prototype") != a);
+        let s = rt.new_string("length");
+        assert!(!s.as_cell().unchecked_string().is_atom());
+        assert!(rt.intern_string(s) == a);
+        assert!(!s.as_cell().unchecked_string().is_atom());
+    }
+
+    #[test]
+    fn strings_become_atoms() {
+        let mut rt = Runtime::new();
+        let (x, y) = (
+            rt.new_string("\u{3b1}\u{3b2}\u{3b3}\u{3b4}"),
+            rt.new_string("efghijklmnop"),
+        );
+        let rope = rt.concat_strings(x, y).ok().unwrap();
+        let atom = rt.intern_string(rope);
+        assert!(atom == rope);
+        assert!(rope.as_cell().unchecked_string().is_flat());
+        assert!(rt.intern("\u{3b1}\u{3b2}\u{3b3}\u{3b4}efghijklmnop") == rope);
+
+        let sub = rt.new_substring(rope, 1, 16).ok().unwrap();
+        assert!(sub.as_cell().unchecked_string().is_substring());
+        let atom = rt.intern_string(sub);
+        assert!(atom != sub && atom.as_cell().unchecked_string().is_flat());
+        assert!(rt.intern("\u{3b2}\u{3b3}\u{3b4}efghijklmnop") == atom);
+        assert_eq!(rt.atoms.len(), 2);
+    }
+
+    #[test]
+    fn property_keys_are_atoms() {
+        let mut rt = Runtime::new();
+        let key = rt.to_property_key(JSValue::new_int(42));
+        assert!(key.ok().unwrap() == rt.intern("42"));
+        let key = rt.to_property_key(JSValue::new_double(0.5));
+        assert!(key.ok().unwrap() == rt.intern("0.5"));
+    }
+
+    #[test]
+    fn computed_keys_are_weak() {
+        let mut rt = Runtime::new();
+        let pinned = rt.atoms.len();
+        let scope = HandleScope::new(&rt);
+        for i in 0..100 {
+            rt.to_property_key(JSValue::new_int(1000 + i)).ok().unwrap();
+        }
+        let kept = scope.root(rt.to_property_key(JSValue::new_int(7)).ok().unwrap());
+        assert_eq!(rt.atoms.len(), pinned + 101);
+        assert_eq!(rt.atoms.weak_len(), 101);
+        rt.gc();
+        assert_eq!(rt.atoms.len(), pinned + 1);
+        assert_eq!(rt.atoms.weak_len(), 1);
+        // The surviving atom moved and is still found by its contents.
+        let again = rt.to_property_key(JSValue::new_int(7)).ok().unwrap();
+        assert!(again == kept.get());
+        assert!(kept.get().as_cell().unchecked_string().is_atom());
+
+        // Interning by name pins an existing weak atom.
+        assert!(rt.intern("7") == kept.get());
+        assert_eq!(rt.atoms.weak_len(), 0);
+        rt.gc();
+        assert!(rt.intern("7").as_cell().unchecked_string().is_atom());
+        assert_eq!(rt.atoms.len(), pinned + 1);
+    }
+
+    #[test]
+    fn latin1_and_utf16_hash_the_same() {
+        let mut rt = Runtime::new();
+        let latin1 = rt.new_string("caf\u{e9} caf\u{e9} caf\u{e9}");
+        let utf16 = rt.new_string("caf\u{e9} caf\u{e9} caf\u{e9}\u{1F600}");
+        let utf16 = rt.new_substring(utf16, 0, 14).ok().unwrap();
+        let (l, u) = (latin1.as_cell(), utf16.as_cell());
+        let (l, u) = (l.unchecked_string(), u.unchecked_string());
+        assert!(l.is_latin1() && !u.is_latin1());
+        assert_eq!(l.hash_code(), u.hash_code());
+        assert!(l == u);
+    }
+}
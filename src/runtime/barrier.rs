@@ -0,0 +1,243 @@
+//! Write barrier and remembered set.
+//!
+//! A generational collector that skips the old generation during minor collections has to know
+//! the old cells pointing at young ones. Stores of values into cells that are already in the heap
+//! go through [Runtime::write_barrier], which remembers old cells that got a young value. The
+//! remembered set is traced as a root and pruned after every collection.
+//!
+//! cgc does not need the set for correctness: its minor collections copy young cells only, but
+//! they still visit every old cell reachable from the roots, so young children of old cells are
+//! found either way. The barrier is kept so store paths stay ready for a collector that does skip
+//! old cells, and is cheap on the common path: stores into young cells return after reading the
+//! owner's generation.
+//!
+//! Cells that are not moved into the heap yet need no barrier, that is why objects are built with
+//! `JSObject` methods first and allocated afterwards.
+use super::cell::*;
+use super::jsproperty::*;
+use super::jsvalue::*;
+use super::rooting::{cell_address, for_each_child};
+use super::Runtime;
+use cgc::api::{Finalizer, Handle, HeapTrait, Traceable, Tracer};
+use fxhash::FxHashSet;
+
+/// Cells surviving this many collections are promoted to the old generation.
+pub const OLD_GENERATION: u8 = 5;
+
+/// Returns true if `cell` was promoted to the old generation.
+pub fn is_old(cell: Handle<Cell>) -> bool {
+    unsafe { (*cell.inner()).generation() >= OLD_GENERATION }
+}
+
+fn is_heap_value(value: JSValue) -> bool {
+    value.is_cell() && !value.is_empty()
+}
+
+/// Old cells that may point at young cells.
+#[derive(Default)]
+pub struct RememberedSet {
+    cells: Vec<JSValue>,
+    /// Addresses of `cells`, old cells move only when the old generation is collected and the
+    /// set is rebuilt after every collection.
+    addresses: FxHashSet<usize>,
+}
+
+impl RememberedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, value: JSValue) -> bool {
+        is_heap_value(value) && self.addresses.contains(&cell_address(value.as_cell()))
+    }
+
+    fn insert(&mut self, value: JSValue) {
+        if self.addresses.insert(cell_address(value.as_cell())) {
+            self.cells.push(value);
+        }
+    }
+}
+
+impl Traceable for RememberedSet {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.cells.trace_with(tracer);
+    }
+}
+
+impl Finalizer for RememberedSet {}
+
+impl Runtime {
+    /// Records that `value` was stored into cell `owner`. Must be called after every store into a
+    /// cell that is already in the heap: property and internal slot writes, array element writes,
+    /// prototype changes and environment slot writes.
+    #[inline]
+    pub fn write_barrier(&mut self, owner: JSValue, value: JSValue) {
+        if is_heap_value(owner)
+            && is_old(owner.as_cell())
+            && is_heap_value(value)
+            && !is_old(value.as_cell())
+        {
+            self.remembered_set.insert(owner);
+        }
+    }
+
+    /// [Runtime::write_barrier] for the value, getter and setter of `property`.
+    pub fn property_write_barrier(&mut self, owner: JSValue, property: &JSProperty) {
+        for value in [property.value, property.getter, property.setter]
+            .iter()
+            .flatten()
+        {
+            self.write_barrier(owner, *value);
+        }
+    }
+
+    /// Drops remembered cells that no longer point at young cells, called after collections.
+    pub(crate) fn prune_remembered_set(&mut self) {
+        let cells = std::mem::take(&mut self.remembered_set.cells);
+        self.remembered_set.addresses.clear();
+        for value in cells {
+            let cell = value.as_cell();
+            let mut young = false;
+            for_each_child(cell.get(), |child| young |= !is_old(child));
+            if is_old(cell) && young {
+                self.remembered_set.insert(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::environment::*;
+    use crate::runtime::jsobject::*;
+    use crate::runtime::rooting::*;
+    use crate::runtime::vtable::*;
+
+    fn promote(rt: &mut Runtime, value: &Persistent<JSValue>) {
+        while !is_old(value.get().as_cell()) {
+            rt.gc();
+        }
+    }
+
+    fn string(value: JSValue) -> String {
+        value.as_cell().unchecked_string().to_string()
+    }
+
+    #[test]
+    fn property_stores_remember_old_objects() {
+        let mut rt = Runtime::new();
+        let object = rt.new_object();
+        let object = Persistent::new(&rt, object);
+        promote(&mut rt, &object);
+        assert!(rt.remembered_set.is_empty());
+
+        let value = rt.new_string("young value");
+        object_put(&mut rt, object.get(), "key", value, true)
+            .ok()
+            .unwrap();
+        assert!(rt.remembered_set.contains(object.get()));
+        let fresh = rt.new_object();
+        object_put(&mut rt, fresh, "key", value, true).ok().unwrap();
+        assert_eq!(rt.remembered_set.len(), 1);
+
+        rt.gc();
+        let value = object_get(&mut rt, object.get(), "key").ok().unwrap();
+        rt.assert_live(value);
+        assert_eq!(string(value), "young value");
+        assert!(rt.remembered_set.contains(object.get()));
+
+        let value = Persistent::new(&rt, value);
+        promote(&mut rt, &value);
+        assert!(rt.remembered_set.is_empty());
+        assert_eq!(string(value.get()), "young value");
+    }
+
+    #[test]
+    fn prototype_and_environment_stores() {
+        let mut rt = Runtime::new();
+        let object = rt.new_object();
+        let object = Persistent::new(&rt, object);
+        let env = new_declarative_environment(&mut rt, None, 1);
+        env.as_cell()
+            .unchecked_environment_mut()
+            .create_lexical_binding("x", true);
+        let env = Persistent::new(&rt, env);
+        promote(&mut rt, &object);
+        promote(&mut rt, &env);
+
+        let proto = rt.new_object();
+        assert!(object_set_prototype_of(&mut rt, object.get(), proto));
+        assert!(!object_set_prototype_of(&mut rt, proto, object.get()));
+        let slot = rt.new_string("slot");
+        put_to_slot(&mut rt, env.get(), 0, slot);
+        assert!(rt.remembered_set.contains(object.get()));
+        assert!(rt.remembered_set.contains(env.get()));
+
+        rt.gc();
+        let proto = object.get().as_cell().unchecked_object_mut().prototype;
+        rt.assert_live(proto.unwrap());
+        let slot = env.get().as_cell().unchecked_environment_mut().get_slot(0);
+        assert_eq!(string(slot), "slot");
+        assert!(object_set_prototype_of(
+            &mut rt,
+            object.get(),
+            JSValue::null()
+        ));
+        assert!(object
+            .get()
+            .as_cell()
+            .unchecked_object_mut()
+            .prototype
+            .is_none());
+    }
+
+    #[test]
+    fn element_stores() {
+        let mut rt = Runtime::new();
+        let kind = JSObjectKind::Array(vec![]);
+        let array = rt.allocate(Cell::Object(Box::new(JSObject::new(kind, None, "Array"))));
+        let array = Persistent::new(&rt, array);
+        promote(&mut rt, &array);
+
+        let element = rt.new_string("element");
+        array_set_element(&mut rt, array.get(), 2, element);
+        assert!(rt.remembered_set.contains(array.get()));
+        rt.gc();
+        match &array.get().as_cell().unchecked_object_mut().kind {
+            JSObjectKind::Array(elements) => {
+                assert_eq!(elements.len(), 3);
+                assert!(elements[0].is_undefined());
+                assert_eq!(string(elements[2]), "element");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn minor_collections_visit_old_cells() {
+        // cgc finds young children of reachable old cells without the remembered set.
+        let mut rt = Runtime::new();
+        let object = rt.new_object();
+        let object = Persistent::new(&rt, object);
+        promote(&mut rt, &object);
+        let value = rt.new_string("unremembered");
+        let mut cell = object.get().as_cell();
+        cell.unchecked_object_mut()
+            ._write("key", JSProperty::new().value(value));
+        assert!(rt.remembered_set.is_empty());
+
+        rt.gc();
+        let value = object_get(&mut rt, object.get(), "key").ok().unwrap();
+        rt.assert_live(value);
+        assert_eq!(string(value), "unremembered");
+    }
+}
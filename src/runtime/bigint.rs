@@ -0,0 +1,143 @@
+//! `BigInt` function and `BigInt.prototype` methods.
+//!
+//! Like the `number` module these are written as native functions. `BigInt` is not a constructor,
+//! so `new BigInt()` throws TypeError.
+use super::cell::*;
+use super::jsbigint::*;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+
+fn argument(args: &[JSValue], index: usize) -> JSValue {
+    args.get(index).copied().unwrap_or_else(JSValue::undefined)
+}
+
+/// thisBigIntValue, accepts BigInt primitives and BigInt wrapper objects.
+pub fn this_bigint_value(
+    rt: &mut Runtime,
+    this: JSValue,
+    method: &str,
+) -> Result<JSBigInt, JSValue> {
+    if this.is_bigint() {
+        return Ok(this.as_bigint().clone());
+    }
+    if this.is_object() {
+        let primitive = match &*this.as_cell() {
+            Cell::Object(object) if matches!(object.kind, JSObjectKind::BigInt) => {
+                object.get_internal("PrimitiveValue")
+            }
+            _ => JSValue::undefined(),
+        };
+        if primitive.is_bigint() {
+            return Ok(primitive.as_bigint().clone());
+        }
+    }
+    let msg = format!(
+        "BigInt.prototype.{} requires that 'this' be a BigInt",
+        method
+    );
+    Err(rt.new_type_error(&msg))
+}
+
+/// [ToIndex](https://tc39.es/ecma262/#sec-toindex)
+fn to_index(rt: &mut Runtime, value: JSValue) -> Result<u64, JSValue> {
+    let index = value.to_integer_or_infinity(rt)?;
+    if !(0.0..=9007199254740991.0).contains(&index) {
+        return Err(rt.new_range_error("Invalid value: not (convertible to) a safe integer"));
+    }
+    Ok(index as u64)
+}
+
+/// [BigInt ( value )](https://tc39.es/ecma262/#sec-bigint-constructor-number-value)
+pub fn bigint_constructor(
+    rt: &mut Runtime,
+    _this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let primitive = argument(args, 0).to_primitive(rt, PreferredType::Number)?;
+    let value = if primitive.is_number() {
+        match JSBigInt::from_f64(primitive.as_number()) {
+            Some(n) => n,
+            None => {
+                let msg = format!(
+                    "The number {} cannot be converted to a BigInt because it is not an integer",
+                    primitive.to_string(rt)?
+                );
+                return Err(rt.new_range_error(&msg));
+            }
+        }
+    } else {
+        primitive.to_bigint(rt)?
+    };
+    Ok(rt.new_bigint(value))
+}
+
+/// [BigInt.asIntN](https://tc39.es/ecma262/#sec-bigint.asintn)
+pub fn bigint_as_int_n(
+    rt: &mut Runtime,
+    _this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let bits = to_index(rt, argument(args, 0))?;
+    let n = argument(args, 1).to_bigint(rt)?;
+    Ok(rt.new_bigint(n.as_int_n(bits)))
+}
+
+/// [BigInt.asUintN](https://tc39.es/ecma262/#sec-bigint.asuintn)
+pub fn bigint_as_uint_n(
+    rt: &mut Runtime,
+    _this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let bits = to_index(rt, argument(args, 0))?;
+    let n = argument(args, 1).to_bigint(rt)?;
+    match n.as_uint_n(bits) {
+        Ok(n) => Ok(rt.new_bigint(n)),
+        Err(e) => Err(rt.new_range_error(e.message())),
+    }
+}
+
+/// [BigInt.prototype.toString](https://tc39.es/ecma262/#sec-bigint.prototype.tostring)
+pub fn bigint_proto_to_string(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let x = this_bigint_value(rt, this, "toString")?;
+    let radix = argument(args, 0);
+    let radix = if radix.is_undefined() {
+        10.0
+    } else {
+        radix.to_integer_or_infinity(rt)?
+    };
+    if !(2.0..=36.0).contains(&radix) {
+        return Err(rt.new_range_error("toString() radix must be between 2 and 36"));
+    }
+    Ok(rt.new_string(&x.to_string_radix(radix as u32)))
+}
+
+/// [BigInt.prototype.valueOf](https://tc39.es/ecma262/#sec-bigint.prototype.valueof)
+pub fn bigint_proto_value_of(
+    rt: &mut Runtime,
+    this: JSValue,
+    _args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let x = this_bigint_value(rt, this, "valueOf")?;
+    Ok(rt.new_bigint(x))
+}
+
+pub(crate) fn install(rt: &mut Runtime) {
+    let proto = rt.bigint;
+    let bigint = rt.new_native_function("BigInt", 1, false, bigint_constructor);
+    let scope = HandleScope::new(rt);
+    let bigint = scope.root(bigint);
+    rt.define_builtin(bigint.get(), "prototype", proto);
+    rt.define_builtin(proto, "constructor", bigint.get());
+    let global = rt.global;
+    rt.define_builtin(global, "BigInt", bigint.get());
+    rt.define_native_method(bigint.get(), "asIntN", 2, bigint_as_int_n);
+    rt.define_native_method(bigint.get(), "asUintN", 2, bigint_as_uint_n);
+    rt.define_native_method(proto, "toString", 0, bigint_proto_to_string);
+    rt.define_native_method(proto, "valueOf", 0, bigint_proto_value_of);
+}
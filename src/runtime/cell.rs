@@ -1,8 +1,13 @@
 use cgc::api::{Finalizer, Traceable, Tracer};
 
+/// GC heap cell. Payloads are boxed: when cgc copies a cell it traces the old copy, so handles
+/// stored inline in the cell would only be updated in the copy that is left behind. With boxed
+/// payloads both copies share the handles and the new copy sees the relocated cells.
 pub enum Cell {
-    String(String),
-    Object(super::jsobject::JSObject),
+    String(Box<super::jsstring::JSString>),
+    BigInt(Box<super::jsbigint::JSBigInt>),
+    Object(Box<super::jsobject::JSObject>),
+    Environment(Box<super::environment::Environment>),
 }
 impl Cell {
     pub fn unchecked_object_mut(&mut self) -> &mut super::jsobject::JSObject {
@@ -11,13 +16,37 @@ impl Cell {
             _ => unreachable!(),
         }
     }
+
+    pub fn unchecked_string(&self) -> &super::jsstring::JSString {
+        match self {
+            Cell::String(s) => s,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn unchecked_string_mut(&mut self) -> &mut super::jsstring::JSString {
+        match self {
+            Cell::String(s) => s,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn unchecked_environment_mut(&mut self) -> &mut super::environment::Environment {
+        match self {
+            Cell::Environment(env) => env,
+            _ => unreachable!(),
+        }
+    }
 }
 
 impl Traceable for Cell {
     fn trace_with(&self, tracer: &mut Tracer) {
+        super::heap_stats::count_traced_cell(self);
         match self {
             Self::Object(obj) => obj.trace_with(tracer),
-            _ => (),
+            Self::Environment(env) => env.trace_with(tracer),
+            Self::String(s) => s.trace_with(tracer),
+            Self::BigInt(_) => (),
         }
     }
 }
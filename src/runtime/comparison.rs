@@ -0,0 +1,361 @@
+//! Equality and relational comparison of JS values.
+//!
+//! `PartialEq` on [JSValue] compares encodings, so it is only identity: it tells `+0` from `-0`
+//! and an int32 `1` from a double `1.0`. Everything that implements a JS operator or a spec
+//! algorithm comparing values has to use these functions instead.
+use super::cell::*;
+use super::jsbigint::*;
+use super::jsstring::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+use std::cmp::Ordering;
+
+fn as_str(value: &JSValue) -> Option<&JSString> {
+    if value.is_string() {
+        if let Cell::String(s) = value.as_cell_ref().get() {
+            return Some(s);
+        }
+    }
+    None
+}
+
+fn same_type(x: JSValue, y: JSValue) -> bool {
+    (x.is_number() && y.is_number())
+        || (x.is_undefined() && y.is_undefined())
+        || (x.is_null() && y.is_null())
+        || (x.is_boolean() && y.is_boolean())
+        || (x.is_string() && y.is_string())
+        || (x.is_bigint() && y.is_bigint())
+        || (x.is_object() && y.is_object())
+}
+
+/// [SameValueNonNumber](https://tc39.es/ecma262/#sec-samevaluenonnumber), `x` and `y` must have the
+/// same type.
+fn same_value_non_number(x: JSValue, y: JSValue) -> bool {
+    if x.is_string() {
+        return x == y || as_str(&x) == as_str(&y);
+    }
+    if x.is_bigint() {
+        return x.as_bigint() == y.as_bigint();
+    }
+    x == y
+}
+
+/// [IsStrictlyEqual](https://tc39.es/ecma262/#sec-isstrictlyequal), the `===` operator.
+pub fn strict_equals(x: JSValue, y: JSValue) -> bool {
+    if x.is_number() && y.is_number() {
+        return x.as_number() == y.as_number();
+    }
+    same_type(x, y) && same_value_non_number(x, y)
+}
+
+/// [SameValue](https://tc39.es/ecma262/#sec-samevalue), used by `Object.is`. NaN is equal to
+/// itself and `+0` is not equal to `-0`.
+pub fn same_value(x: JSValue, y: JSValue) -> bool {
+    if x.is_number() && y.is_number() {
+        let (x, y) = (x.as_number(), y.as_number());
+        if x.is_nan() && y.is_nan() {
+            return true;
+        }
+        return x == y && x.is_sign_negative() == y.is_sign_negative();
+    }
+    same_type(x, y) && same_value_non_number(x, y)
+}
+
+/// [SameValueZero](https://tc39.es/ecma262/#sec-samevaluezero), used by `Map`, `Set` and
+/// `Array.prototype.includes`. Like [same_value] but `+0` and `-0` are equal.
+pub fn same_value_zero(x: JSValue, y: JSValue) -> bool {
+    if x.is_number() && y.is_number() {
+        let (x, y) = (x.as_number(), y.as_number());
+        return (x.is_nan() && y.is_nan()) || x == y;
+    }
+    same_type(x, y) && same_value_non_number(x, y)
+}
+
+/// [IsLooselyEqual](https://tc39.es/ecma262/#sec-islooselyequal), the `==` operator.
+pub fn abstract_equals(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<bool, JSValue> {
+    if same_type(x, y) {
+        return Ok(strict_equals(x, y));
+    }
+    if x.is_undefined_or_null() && y.is_undefined_or_null() {
+        return Ok(true);
+    }
+    if x.is_number() && y.is_string() {
+        return Ok(x.as_number() == y.to_number(rt)?);
+    }
+    if x.is_string() && y.is_number() {
+        return Ok(x.to_number(rt)? == y.as_number());
+    }
+    if x.is_bigint() && y.is_string() {
+        let y = y.to_string(rt)?;
+        return Ok(JSBigInt::from_string(&y).as_ref() == Some(x.as_bigint()));
+    }
+    if x.is_string() && y.is_bigint() {
+        return abstract_equals(rt, y, x);
+    }
+    if x.is_boolean() {
+        let x = JSValue::new_double(x.to_number(rt)?);
+        return abstract_equals(rt, x, y);
+    }
+    if y.is_boolean() {
+        let y = JSValue::new_double(y.to_number(rt)?);
+        return abstract_equals(rt, x, y);
+    }
+    if (x.is_number() || x.is_string() || x.is_bigint()) && y.is_object() {
+        let scope = HandleScope::new(rt);
+        let x = scope.root(x);
+        let y = y.to_primitive(rt, PreferredType::Default)?;
+        return abstract_equals(rt, x.get(), y);
+    }
+    if x.is_object() && (y.is_number() || y.is_string() || y.is_bigint()) {
+        let scope = HandleScope::new(rt);
+        let y = scope.root(y);
+        let x = x.to_primitive(rt, PreferredType::Default)?;
+        return abstract_equals(rt, x, y.get());
+    }
+    if x.is_bigint() && y.is_number() {
+        return Ok(x.as_bigint().cmp_f64(y.as_number()) == Some(Ordering::Equal));
+    }
+    if x.is_number() && y.is_bigint() {
+        return abstract_equals(rt, y, x);
+    }
+    Ok(false)
+}
+
+/// [IsLessThan](https://tc39.es/ecma262/#sec-islessthan)
+///
+/// Returns `None` (undefined in the spec) when either operand converts to NaN. `left_first`
+/// controls order of ToPrimitive calls, which is observable through `valueOf`.
+pub fn less_than(
+    rt: &mut Runtime,
+    x: JSValue,
+    y: JSValue,
+    left_first: bool,
+) -> Result<Option<bool>, JSValue> {
+    let scope = HandleScope::new(rt);
+    let (x, y) = (scope.root(x), scope.root(y));
+    let (px, py) = if left_first {
+        let px = scope.root(x.get().to_primitive(rt, PreferredType::Number)?);
+        let py = y.get().to_primitive(rt, PreferredType::Number)?;
+        (px.get(), py)
+    } else {
+        let py = scope.root(y.get().to_primitive(rt, PreferredType::Number)?);
+        let px = x.get().to_primitive(rt, PreferredType::Number)?;
+        (px, py.get())
+    };
+    // Conversions of primitives below don't allocate, `px` and `py` stay valid.
+    if let (Some(sx), Some(sy)) = (as_str(&px), as_str(&py)) {
+        // Strings are ordered by UTF-16 code units, not by code points.
+        return Ok(Some(sx < sy));
+    }
+    if px.is_bigint() && py.is_string() {
+        let sy = py.to_string(rt)?;
+        return Ok(JSBigInt::from_string(&sy).map(|ny| px.as_bigint() < &ny));
+    }
+    if px.is_string() && py.is_bigint() {
+        let sx = px.to_string(rt)?;
+        return Ok(JSBigInt::from_string(&sx).map(|nx| &nx < py.as_bigint()));
+    }
+    let nx = px.to_numeric(rt)?;
+    let ny = py.to_numeric(rt)?;
+    match (nx.is_bigint(), ny.is_bigint()) {
+        (true, true) => return Ok(Some(nx.as_bigint() < ny.as_bigint())),
+        (true, false) => {
+            let ordering = nx.as_bigint().cmp_f64(ny.as_number());
+            return Ok(ordering.map(|ordering| ordering == Ordering::Less));
+        }
+        (false, true) => {
+            let ordering = ny.as_bigint().cmp_f64(nx.as_number());
+            return Ok(ordering.map(|ordering| ordering == Ordering::Greater));
+        }
+        (false, false) => (),
+    }
+    let (nx, ny) = (nx.as_number(), ny.as_number());
+    if nx.is_nan() || ny.is_nan() {
+        return Ok(None);
+    }
+    Ok(Some(nx < ny))
+}
+
+/// `x < y`
+pub fn less(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<bool, JSValue> {
+    Ok(less_than(rt, x, y, true)? == Some(true))
+}
+
+/// `x > y`
+pub fn greater(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<bool, JSValue> {
+    Ok(less_than(rt, y, x, false)? == Some(true))
+}
+
+/// `x <= y`
+pub fn less_eq(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<bool, JSValue> {
+    Ok(less_than(rt, y, x, false)? == Some(false))
+}
+
+/// `x >= y`
+pub fn greater_eq(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<bool, JSValue> {
+    Ok(less_than(rt, x, y, true)? == Some(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn double(x: f64) -> JSValue {
+        JSValue::new_double(x)
+    }
+
+    fn bigint(rt: &mut Runtime, x: i64) -> JSValue {
+        rt.new_bigint(JSBigInt::from_i64(x))
+    }
+
+    #[test]
+    fn nan_and_zeros() {
+        let nan = double(f64::NAN);
+        let (zero, negative_zero) = (JSValue::new_int(0), double(-0.0));
+        assert!(!strict_equals(nan, nan));
+        assert!(same_value(nan, nan));
+        assert!(same_value_zero(nan, nan));
+        assert!(strict_equals(zero, negative_zero));
+        assert!(!same_value(zero, negative_zero));
+        assert!(!same_value(double(0.0), negative_zero));
+        assert!(same_value_zero(zero, negative_zero));
+        assert!(same_value(negative_zero, double(-0.0)));
+    }
+
+    #[test]
+    fn int32_and_double() {
+        let (int, float) = (JSValue::new_int(1), double(1.0));
+        assert!(int != float);
+        assert!(strict_equals(int, float));
+        assert!(same_value(int, float));
+        assert!(same_value_zero(float, int));
+        assert!(!strict_equals(int, double(1.5)));
+    }
+
+    #[test]
+    fn loose_equality() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let (undefined, null) = (JSValue::undefined(), JSValue::null());
+        let zero = JSValue::new_int(0);
+        let empty = scope.root(rt.new_string(""));
+        let object = scope.root(rt.new_object());
+        let equal =
+            |rt: &mut Runtime, x: JSValue, y: JSValue| abstract_equals(rt, x, y).ok().unwrap();
+        assert!(equal(&mut rt, undefined, null));
+        assert!(equal(&mut rt, null, null));
+        assert!(!equal(&mut rt, null, zero));
+        assert!(!equal(&mut rt, undefined, JSValue::false_()));
+        assert!(!equal(&mut rt, null, empty.get()));
+        assert!(!equal(&mut rt, null, object.get()));
+        assert!(equal(&mut rt, empty.get(), zero));
+        assert!(equal(&mut rt, JSValue::true_(), JSValue::new_int(1)));
+        assert!(equal(&mut rt, object.get(), object.get()));
+        assert!(!strict_equals(undefined, null));
+    }
+
+    #[test]
+    fn bigint_equality() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let ten = scope.root(bigint(&mut rt, 10));
+        let other_ten = scope.root(bigint(&mut rt, 10));
+        let text = scope.root(rt.new_string(" 10 "));
+        let fraction = scope.root(rt.new_string("10.5"));
+        let equal = |rt: &mut Runtime, x: JSValue, y: &Rooted<JSValue>| {
+            abstract_equals(rt, x, y.get()).ok().unwrap()
+        };
+        assert!(strict_equals(ten.get(), other_ten.get()));
+        assert!(same_value(ten.get(), other_ten.get()));
+        assert!(!strict_equals(ten.get(), JSValue::new_int(10)));
+        assert!(equal(&mut rt, JSValue::new_int(10), &ten));
+        assert!(equal(&mut rt, double(10.0), &ten));
+        assert!(!equal(&mut rt, double(10.5), &ten));
+        assert!(!equal(&mut rt, double(f64::INFINITY), &ten));
+        assert!(equal(&mut rt, ten.get(), &text));
+        assert!(equal(&mut rt, text.get(), &ten));
+        assert!(!equal(&mut rt, ten.get(), &fraction));
+    }
+
+    #[test]
+    fn relational_comparison() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let nan = double(f64::NAN);
+        assert_eq!(
+            less_than(&mut rt, nan, JSValue::new_int(1), true).ok(),
+            Some(None)
+        );
+        assert!(!less_eq(&mut rt, nan, nan).ok().unwrap());
+        assert!(
+            !greater_eq(&mut rt, JSValue::undefined(), JSValue::new_int(0))
+                .ok()
+                .unwrap()
+        );
+        assert!(less_eq(&mut rt, JSValue::null(), JSValue::new_int(0))
+            .ok()
+            .unwrap());
+        assert!(!less(&mut rt, double(-0.0), JSValue::new_int(0))
+            .ok()
+            .unwrap());
+
+        // Strings compare by UTF-16 code units: U+1F600 is stored as 0xD83D 0xDE00 < 0xFF61.
+        let emoji = scope.root(rt.new_string("\u{1F600}"));
+        let halfwidth = scope.root(rt.new_string("\u{FF61}"));
+        assert!(less(&mut rt, emoji.get(), halfwidth.get()).ok().unwrap());
+        let (a, ab) = (
+            scope.root(rt.new_string("a")),
+            scope.root(rt.new_string("ab")),
+        );
+        assert!(less(&mut rt, a.get(), ab.get()).ok().unwrap());
+        let (ten, nine) = (
+            scope.root(rt.new_string("10")),
+            scope.root(rt.new_string("9")),
+        );
+        assert!(less(&mut rt, ten.get(), nine.get()).ok().unwrap());
+        assert!(!less(&mut rt, ten.get(), JSValue::new_int(9)).ok().unwrap());
+
+        let big = scope.root(bigint(&mut rt, 1));
+        let invalid = scope.root(rt.new_string("1.5"));
+        assert!(less(&mut rt, big.get(), double(1.5)).ok().unwrap());
+        assert!(less(&mut rt, big.get(), nine.get()).ok().unwrap());
+        assert_eq!(
+            less_than(&mut rt, big.get(), invalid.get(), true).ok(),
+            Some(None)
+        );
+        assert_eq!(less_than(&mut rt, big.get(), nan, true).ok(), Some(None));
+    }
+
+    #[test]
+    fn to_primitive_order() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let calls = Rc::new(RefCell::new(vec![]));
+        let object = |rt: &mut Runtime, name: &'static str, value: i32| {
+            let object = scope.root(rt.new_object());
+            let calls = calls.clone();
+            rt.define_native_method(object.get(), "valueOf", 0, move |_, _, _| {
+                calls.borrow_mut().push(name);
+                Ok(JSValue::new_int(value))
+            });
+            object
+        };
+        let x = object(&mut rt, "x", 1);
+        let y = object(&mut rt, "y", 2);
+        assert!(less(&mut rt, x.get(), y.get()).ok().unwrap());
+        assert!(!greater(&mut rt, x.get(), y.get()).ok().unwrap());
+        assert!(less_eq(&mut rt, x.get(), y.get()).ok().unwrap());
+        assert!(!greater_eq(&mut rt, x.get(), y.get()).ok().unwrap());
+        assert_eq!(*calls.borrow(), ["x", "y", "x", "y", "x", "y", "x", "y"]);
+        calls.borrow_mut().clear();
+        assert_eq!(
+            less_than(&mut rt, x.get(), y.get(), false).ok(),
+            Some(Some(true))
+        );
+        assert_eq!(*calls.borrow(), ["y", "x"]);
+    }
+}
@@ -0,0 +1,420 @@
+//! Type conversion algorithms working on Rust values.
+//!
+//! JSValue methods like `to_number` dispatch on the value and use these for the actual work.
+use super::pure_nan::*;
+
+/// WhiteSpace and LineTerminator code points, these are trimmed by StringToNumber.
+pub fn is_js_whitespace(c: char) -> bool {
+    matches!(c, '\u{2000}'..='\u{200A}')
+        || matches!(
+            c,
+            '\u{0009}'
+                | '\u{000A}'
+                | '\u{000B}'
+                | '\u{000C}'
+                | '\u{000D}'
+                | '\u{0020}'
+                | '\u{00A0}'
+                | '\u{1680}'
+                | '\u{2028}'
+                | '\u{2029}'
+                | '\u{202F}'
+                | '\u{205F}'
+                | '\u{3000}'
+                | '\u{FEFF}'
+        )
+}
+
+/// Parses digits of radix 2, 8 or 16 rounding to nearest, ties to even.
+///
+/// Accumulating `value * radix + digit` in f64 rounds after every digit, which is off by one ulp
+/// for long literals, so the first 64 bits are collected exactly and the rest only matter as a
+/// sticky bit.
+fn parse_power_of_two_radix(digits: &str, radix: u32) -> f64 {
+    let bits_per_digit = radix.trailing_zeros();
+    let mut mantissa = 0u64;
+    let mut exponent = 0i32;
+    let mut sticky = false;
+    for c in digits.chars() {
+        let digit = c.to_digit(radix).unwrap() as u64;
+        if mantissa >> (64 - bits_per_digit) == 0 {
+            mantissa = (mantissa << bits_per_digit) | digit;
+        } else {
+            exponent += bits_per_digit as i32;
+            sticky |= digit != 0;
+        }
+    }
+    let significant_bits = 64 - mantissa.leading_zeros();
+    if significant_bits <= 53 {
+        return mantissa as f64 * 2f64.powi(exponent);
+    }
+    let shift = significant_bits - 53;
+    let mut rounded = mantissa >> shift;
+    let rest = mantissa & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    if rest > half || (rest == half && (sticky || rounded & 1 == 1)) {
+        rounded += 1;
+    }
+    rounded as f64 * 2f64.powi(exponent + shift as i32)
+}
+
+fn skip_digits(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Checks that `s` is StrUnsignedDecimalLiteral without `Infinity`.
+fn is_unsigned_decimal_literal(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    let int_end = skip_digits(bytes, 0);
+    let mut pos = int_end;
+    let mut has_digits = int_end > 0;
+    if pos < bytes.len() && bytes[pos] == b'.' {
+        let fraction_end = skip_digits(bytes, pos + 1);
+        has_digits |= fraction_end > pos + 1;
+        pos = fraction_end;
+    }
+    if !has_digits {
+        return false;
+    }
+    if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+        pos += 1;
+        if pos < bytes.len() && (bytes[pos] == b'+' || bytes[pos] == b'-') {
+            pos += 1;
+        }
+        let exponent_end = skip_digits(bytes, pos);
+        if exponent_end == pos {
+            return false;
+        }
+        pos = exponent_end;
+    }
+    pos == bytes.len()
+}
+
+/// [StringToNumber](https://tc39.es/ecma262/#sec-stringtonumber)
+///
+/// Returns NaN when `s` is not a StringNumericLiteral.
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_js_whitespace);
+    if s.is_empty() {
+        return 0.0;
+    }
+    let bytes = s.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'0' {
+        let radix = match bytes[1] {
+            b'x' | b'X' => 16,
+            b'o' | b'O' => 8,
+            b'b' | b'B' => 2,
+            _ => 0,
+        };
+        if radix != 0 {
+            let digits = &s[2..];
+            if !digits.chars().all(|c| c.is_digit(radix)) {
+                return pure_nan();
+            }
+            return parse_power_of_two_radix(digits, radix);
+        }
+    }
+    let (negative, unsigned) = match bytes[0] {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if unsigned == "Infinity" {
+        return if negative {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+    }
+    if !is_unsigned_decimal_literal(unsigned) {
+        return pure_nan();
+    }
+    // The grammar is checked above, std parsing is correctly rounded for what is left.
+    let value = unsigned.parse::<f64>().unwrap_or_else(|_| pure_nan());
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// [ToInt32](https://tc39.es/ecma262/#sec-toint32) of a Number.
+///
+/// `as i32` saturates, ToInt32 wraps modulo 2^32. The low 32 bits of the truncated value are taken
+/// straight from the mantissa, so no intermediate value can overflow.
+pub fn f64_to_int32(number: f64) -> i32 {
+    let bits = number.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1023;
+    // Exponent below 0 covers zeros, denormals and |number| < 1. Above 83 the lowest mantissa bit
+    // is worth at least 2^32 and NaN and infinities have exponent 1024, all of these give 0.
+    if !(0..=83).contains(&exponent) {
+        return 0;
+    }
+    let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+    let low_bits = if exponent <= 52 {
+        mantissa >> (52 - exponent)
+    } else {
+        mantissa << (exponent - 52)
+    } as u32 as i32;
+    if number.is_sign_negative() {
+        low_bits.wrapping_neg()
+    } else {
+        low_bits
+    }
+}
+
+/// [ToUint32](https://tc39.es/ecma262/#sec-touint32) of a Number.
+pub fn f64_to_uint32(number: f64) -> u32 {
+    f64_to_int32(number) as u32
+}
+
+/// [ToInt16](https://tc39.es/ecma262/#sec-toint16) of a Number.
+pub fn f64_to_int16(number: f64) -> i16 {
+    f64_to_int32(number) as i16
+}
+
+/// [ToUint16](https://tc39.es/ecma262/#sec-touint16) of a Number.
+pub fn f64_to_uint16(number: f64) -> u16 {
+    f64_to_int32(number) as u16
+}
+
+/// [ToInt8](https://tc39.es/ecma262/#sec-toint8) of a Number.
+pub fn f64_to_int8(number: f64) -> i8 {
+    f64_to_int32(number) as i8
+}
+
+/// [ToUint8](https://tc39.es/ecma262/#sec-touint8) of a Number.
+pub fn f64_to_uint8(number: f64) -> u8 {
+    f64_to_int32(number) as u8
+}
+
+/// [ToUint8Clamp](https://tc39.es/ecma262/#sec-touint8clamp) of a Number.
+///
+/// Unlike the other conversions this one clamps and rounds half to even.
+pub fn f64_to_uint8_clamp(number: f64) -> u8 {
+    if number.is_nan() || number <= 0.0 {
+        return 0;
+    }
+    if number >= 255.0 {
+        return 255;
+    }
+    let floor = number.floor();
+    let rounded = if floor + 0.5 < number {
+        floor + 1.0
+    } else if number < floor + 0.5 || floor % 2.0 == 0.0 {
+        floor
+    } else {
+        floor + 1.0
+    };
+    rounded as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_32: f64 = 4294967296.0;
+    const TWO_31: f64 = 2147483648.0;
+
+    #[test]
+    fn string_to_number_grammar() {
+        let cases: [(&str, f64); 28] = [
+            ("", 0.0),
+            (" \t\n\u{a0}\u{feff}\u{2028} ", 0.0),
+            ("  42  ", 42.0),
+            ("-0", -0.0),
+            ("+.5", 0.5),
+            ("5.", 5.0),
+            ("1e3", 1000.0),
+            ("1E-3", 0.001),
+            ("-1.5e+2", -150.0),
+            ("Infinity", f64::INFINITY),
+            ("+Infinity", f64::INFINITY),
+            ("-Infinity", f64::NEG_INFINITY),
+            ("0x1F", 31.0),
+            ("0XfF", 255.0),
+            ("0o17", 15.0),
+            ("0b101", 5.0),
+            ("007", 7.0),
+            ("1e400", f64::INFINITY),
+            ("1e-400", 0.0),
+            ("0.1", 0.1),
+            ("9007199254740993", 9007199254740992.0),
+            ("0x20000000000001", 9007199254740992.0),
+            ("0x20000000000003", 9007199254740996.0),
+            (
+                "0x20000000000001000000000001",
+                2f64.powi(101) + 2f64.powi(49),
+            ),
+            ("1.7976931348623157e308", f64::MAX),
+            ("5e-324", 5e-324),
+            ("\u{3000}-3\u{3000}", -3.0),
+            ("00.5e1", 5.0),
+        ];
+        for (source, expected) in cases.iter() {
+            let value = string_to_number(source);
+            assert_eq!(value, *expected, "StringToNumber({:?})", source);
+            assert_eq!(
+                value.is_sign_negative(),
+                expected.is_sign_negative(),
+                "StringToNumber({:?})",
+                source
+            );
+        }
+        let invalid = [
+            ".",
+            "e1",
+            "1e",
+            "1e+",
+            "+-1",
+            "- 1",
+            "1 2",
+            "0x",
+            "-0x10",
+            "+0x10",
+            "0xg",
+            "0b2",
+            "0o8",
+            "infinity",
+            "INFINITY",
+            "Inf",
+            "NaN",
+            "1_000",
+            "١",
+            "0x1.8",
+            "1n",
+            "\u{180e}1",
+        ];
+        for source in invalid.iter() {
+            assert!(
+                string_to_number(source).is_nan(),
+                "StringToNumber({:?})",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn to_int32_special_values() {
+        for value in [
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            0.0,
+            -0.0,
+            f64::MIN_POSITIVE,
+            -f64::MIN_POSITIVE,
+            5e-324,
+            -5e-324,
+            0.5,
+            -0.5,
+            0.999_999_999_999_999_9,
+            -0.999_999_999_999_999_9,
+            TWO_32,
+            -TWO_32,
+            TWO_32 * TWO_32,
+            f64::MAX,
+            f64::MIN,
+        ] {
+            assert_eq!(f64_to_int32(value), 0, "ToInt32({:e})", value);
+            assert_eq!(f64_to_uint32(value), 0, "ToUint32({:e})", value);
+        }
+    }
+
+    #[test]
+    fn to_int32_truncates_toward_zero() {
+        assert_eq!(f64_to_int32(1.9), 1);
+        assert_eq!(f64_to_int32(-1.9), -1);
+        assert_eq!(f64_to_int32(2147483647.9), i32::MAX);
+        assert_eq!(f64_to_int32(-2147483648.9), i32::MIN);
+        assert_eq!(f64_to_uint32(-1.9), u32::MAX);
+    }
+
+    #[test]
+    fn to_int32_wraps_modulo_2_32() {
+        assert_eq!(f64_to_int32(TWO_31), i32::MIN);
+        assert_eq!(f64_to_int32(-TWO_31), i32::MIN);
+        assert_eq!(f64_to_int32(TWO_31 - 1.0), i32::MAX);
+        assert_eq!(f64_to_int32(-TWO_31 - 1.0), i32::MAX);
+        assert_eq!(f64_to_int32(TWO_32 - 1.0), -1);
+        assert_eq!(f64_to_int32(TWO_32 + 5.0), 5);
+        assert_eq!(f64_to_int32(-TWO_32 - 5.0), -5);
+        assert_eq!(f64_to_int32(TWO_32 + 0.5), 0);
+        assert_eq!(f64_to_uint32(-1.0), u32::MAX);
+        assert_eq!(f64_to_uint32(TWO_32 - 1.0), u32::MAX);
+        assert_eq!(f64_to_uint32(-TWO_31), TWO_31 as u32);
+        // 2^53 + 2 and neighbours, where the mantissa is shifted left.
+        assert_eq!(f64_to_int32(9007199254740994.0), 2);
+        assert_eq!(f64_to_int32(-9007199254740994.0), -2);
+        assert_eq!(f64_to_int32(9007199254740991.0), -1);
+        // Largest exponents that still leave bits below 2^32.
+        assert_eq!(
+            f64_to_int32((1u64 << 52 | 1) as f64 * 2f64.powi(31)),
+            i32::MIN
+        );
+        assert_eq!(f64_to_int32((1u64 << 52 | 1) as f64 * 2f64.powi(32)), 0);
+    }
+
+    #[test]
+    fn to_int32_matches_wrapping_cast_for_every_bit_position() {
+        // Every power of two and its neighbours, in both signs, against i128 arithmetic.
+        for exponent in 0..=1023 {
+            let power = 2f64.powi(exponent);
+            for value in [power, power - 1.0, power + 1.0, power * 1.5] {
+                for value in [value, -value] {
+                    if !value.is_finite() {
+                        continue;
+                    }
+                    let expected = if value.abs() < 2f64.powi(120) {
+                        (value.trunc() as i128 as u32) as i32
+                    } else {
+                        0
+                    };
+                    assert_eq!(f64_to_int32(value), expected, "ToInt32({:e})", value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_conversions() {
+        assert_eq!(f64_to_int16(32767.0), i16::MAX);
+        assert_eq!(f64_to_int16(32768.0), i16::MIN);
+        assert_eq!(f64_to_int16(-32769.0), i16::MAX);
+        assert_eq!(f64_to_int16(65536.0 + 1.0), 1);
+        assert_eq!(f64_to_int16(f64::NAN), 0);
+        assert_eq!(f64_to_uint16(-1.0), u16::MAX);
+        assert_eq!(f64_to_uint16(65536.0), 0);
+        assert_eq!(f64_to_uint16(TWO_32 + 7.0), 7);
+        assert_eq!(f64_to_int8(127.0), i8::MAX);
+        assert_eq!(f64_to_int8(128.0), i8::MIN);
+        assert_eq!(f64_to_int8(-129.0), i8::MAX);
+        assert_eq!(f64_to_uint8(-1.0), u8::MAX);
+        assert_eq!(f64_to_uint8(256.0), 0);
+        assert_eq!(f64_to_uint8(257.9), 1);
+        assert_eq!(f64_to_uint8(f64::INFINITY), 0);
+        assert_eq!(f64_to_uint8(-0.0), 0);
+    }
+
+    #[test]
+    fn uint8_clamp() {
+        assert_eq!(f64_to_uint8_clamp(f64::NAN), 0);
+        assert_eq!(f64_to_uint8_clamp(f64::NEG_INFINITY), 0);
+        assert_eq!(f64_to_uint8_clamp(f64::INFINITY), 255);
+        assert_eq!(f64_to_uint8_clamp(-0.0), 0);
+        assert_eq!(f64_to_uint8_clamp(-1.0), 0);
+        assert_eq!(f64_to_uint8_clamp(0.4), 0);
+        assert_eq!(f64_to_uint8_clamp(0.5), 0);
+        assert_eq!(f64_to_uint8_clamp(0.6), 1);
+        assert_eq!(f64_to_uint8_clamp(1.5), 2);
+        assert_eq!(f64_to_uint8_clamp(2.5), 2);
+        assert_eq!(f64_to_uint8_clamp(253.5), 254);
+        assert_eq!(f64_to_uint8_clamp(254.5), 254);
+        assert_eq!(f64_to_uint8_clamp(254.6), 255);
+        assert_eq!(f64_to_uint8_clamp(255.0), 255);
+        assert_eq!(f64_to_uint8_clamp(300.0), 255);
+    }
+}
@@ -0,0 +1,642 @@
+//! Conversions between Rust types and JavaScript values for embedders.
+//!
+//! [ToJS] and [FromJS] are strict: unlike the abstract operations in [conversions](super::conversions)
+//! they don't coerce, a value of the wrong type or an integer out of range throws TypeError.
+//! `undefined` and `null` convert to `None`, so optional arguments are taken as `Option<T>`.
+//! Vectors and tuples convert to and from arrays, `HashMap<String, T>` to and from plain objects
+//! with their own enumerable properties.
+//!
+//! [Runtime::new_typed_function] adapts closures with typed arguments to native functions.
+//!
+//! Containers report the `JSValue`s they hold through [HoldsValues], conversions root them while
+//! converting other elements can allocate. The converted `JSValue` itself is not rooted, like any
+//! other local it is stale after a collection.
+use super::function::NativeFn;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::operations::type_of;
+use super::rooting::*;
+use super::Runtime;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+
+/// Rust value that may hold JavaScript values.
+pub trait HoldsValues {
+    /// Calls `f` with every `JSValue` in `self`, in the same order every time. Conversions use it
+    /// to root the values and write back their current locations.
+    fn for_each_value(&mut self, _f: &mut dyn FnMut(&mut JSValue)) {}
+}
+
+/// Rust value that can be converted into a JavaScript value.
+pub trait ToJS: HoldsValues {
+    /// Converts `self`, `Err` is thrown into the script.
+    fn to_js(self, rt: &mut Runtime) -> Result<JSValue, JSValue>;
+}
+
+/// Rust value that can be created from a JavaScript value.
+pub trait FromJS: HoldsValues + Sized {
+    /// Converts `value`, throws TypeError if it has the wrong type.
+    fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue>;
+}
+
+/// Roots the values held by `value` in `scope`.
+fn root_values<'s, T: HoldsValues>(
+    scope: &'s HandleScope,
+    value: &mut T,
+) -> Vec<Rooted<'s, JSValue>> {
+    let mut rooted = vec![];
+    value.for_each_value(&mut |value| rooted.push(scope.root(*value)));
+    rooted
+}
+
+/// Updates the values held by `value` from the first slots of `rooted`, returns how many it holds.
+fn reload_values<T: HoldsValues>(value: &mut T, rooted: &[Rooted<JSValue>]) -> usize {
+    let mut count = 0;
+    value.for_each_value(&mut |value| {
+        *value = rooted[count].get();
+        count += 1;
+    });
+    count
+}
+
+fn conversion_error(rt: &mut Runtime, value: JSValue, expected: &str) -> JSValue {
+    let msg = format!("Cannot convert {} to {}", type_of(value), expected);
+    rt.new_type_error(&msg)
+}
+
+impl HoldsValues for JSValue {
+    fn for_each_value(&mut self, f: &mut dyn FnMut(&mut JSValue)) {
+        f(self)
+    }
+}
+
+impl HoldsValues for () {}
+impl HoldsValues for bool {}
+impl HoldsValues for &str {}
+impl HoldsValues for String {}
+
+impl ToJS for JSValue {
+    fn to_js(self, _rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        Ok(self)
+    }
+}
+
+impl FromJS for JSValue {
+    fn from_js(_rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+        Ok(value)
+    }
+}
+
+impl ToJS for () {
+    fn to_js(self, _rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        Ok(JSValue::undefined())
+    }
+}
+
+impl ToJS for bool {
+    fn to_js(self, _rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        Ok(JSValue::new_bool(self))
+    }
+}
+
+impl FromJS for bool {
+    fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+        if value.is_boolean() {
+            Ok(value.is_true())
+        } else {
+            Err(conversion_error(rt, value, "boolean"))
+        }
+    }
+}
+
+macro_rules! integer {
+    ($($t:ty),*) => {$(
+        impl HoldsValues for $t {}
+
+        impl ToJS for $t {
+            /// Integers outside of the int32 range become doubles, losing precision above 2^53.
+            fn to_js(self, _rt: &mut Runtime) -> Result<JSValue, JSValue> {
+                Ok(match i32::try_from(self) {
+                    Ok(int) => JSValue::new_int(int),
+                    Err(_) => JSValue::new_double(self as f64),
+                })
+            }
+        }
+
+        impl FromJS for $t {
+            fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+                if value.is_number() {
+                    let number = value.as_number();
+                    let int = number as i128;
+                    if int as f64 == number && int >= <$t>::MIN as i128 && int <= <$t>::MAX as i128 {
+                        return Ok(int as $t);
+                    }
+                }
+                Err(conversion_error(rt, value, stringify!($t)))
+            }
+        }
+    )*};
+}
+
+integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float {
+    ($($t:ty),*) => {$(
+        impl HoldsValues for $t {}
+
+        impl ToJS for $t {
+            fn to_js(self, _rt: &mut Runtime) -> Result<JSValue, JSValue> {
+                Ok(JSValue::new_double(self as f64))
+            }
+        }
+
+        impl FromJS for $t {
+            fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+                if value.is_number() {
+                    Ok(value.as_number() as $t)
+                } else {
+                    Err(conversion_error(rt, value, "number"))
+                }
+            }
+        }
+    )*};
+}
+
+float!(f32, f64);
+
+impl ToJS for &str {
+    fn to_js(self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        rt.try_new_string(self)
+    }
+}
+
+impl ToJS for String {
+    fn to_js(self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        rt.try_new_string(&self)
+    }
+}
+
+impl FromJS for String {
+    fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+        if value.is_string() {
+            Ok(value.as_cell().unchecked_string().to_string())
+        } else {
+            Err(conversion_error(rt, value, "string"))
+        }
+    }
+}
+
+impl<T: HoldsValues> HoldsValues for Option<T> {
+    fn for_each_value(&mut self, f: &mut dyn FnMut(&mut JSValue)) {
+        if let Some(value) = self {
+            value.for_each_value(f);
+        }
+    }
+}
+
+impl<T: ToJS> ToJS for Option<T> {
+    /// `None` converts to `undefined`.
+    fn to_js(self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        match self {
+            Some(value) => value.to_js(rt),
+            None => Ok(JSValue::undefined()),
+        }
+    }
+}
+
+impl<T: FromJS> FromJS for Option<T> {
+    fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+        if value.is_undefined_or_null() {
+            Ok(None)
+        } else {
+            T::from_js(rt, value).map(Some)
+        }
+    }
+}
+
+impl<T: HoldsValues, E: HoldsValues> HoldsValues for Result<T, E> {
+    fn for_each_value(&mut self, f: &mut dyn FnMut(&mut JSValue)) {
+        match self {
+            Ok(value) => value.for_each_value(f),
+            Err(error) => error.for_each_value(f),
+        }
+    }
+}
+
+impl<T: ToJS, E: ToJS> ToJS for Result<T, E> {
+    /// `Err` is converted and thrown.
+    fn to_js(self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        match self {
+            Ok(value) => value.to_js(rt),
+            Err(error) => Err(error.to_js(rt)?),
+        }
+    }
+}
+
+/// Creates an array of `elements`, which are rooted until it is allocated.
+fn new_array(rt: &mut Runtime, elements: &[Rooted<JSValue>]) -> Result<JSValue, JSValue> {
+    rt.new_array(elements.iter().map(Rooted::get).collect())
+}
+
+fn array_len(array: JSValue) -> usize {
+    match &array.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::Array(elements) => elements.len(),
+        _ => 0,
+    }
+}
+
+/// Element `index` of `array`, `undefined` if it is out of bounds or a hole.
+fn array_element(array: JSValue, index: usize) -> JSValue {
+    match &array.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::Array(elements) => match elements.get(index) {
+            Some(element) if !element.is_empty() => *element,
+            _ => JSValue::undefined(),
+        },
+        _ => JSValue::undefined(),
+    }
+}
+
+impl<T: HoldsValues> HoldsValues for Vec<T> {
+    fn for_each_value(&mut self, f: &mut dyn FnMut(&mut JSValue)) {
+        self.iter_mut()
+            .for_each(|element| element.for_each_value(f));
+    }
+}
+
+impl<T: ToJS> ToJS for Vec<T> {
+    /// Converts the elements first and allocates the array afterwards.
+    fn to_js(mut self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        let scope = HandleScope::new(rt);
+        let held = root_values(&scope, &mut self);
+        let mut offset = 0;
+        let mut elements = Vec::with_capacity(self.len());
+        for mut element in self {
+            offset += reload_values(&mut element, &held[offset..]);
+            elements.push(scope.root(element.to_js(rt)?));
+        }
+        new_array(rt, &elements)
+    }
+}
+
+impl<T: FromJS> FromJS for Vec<T> {
+    fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+        if !value.is_array() {
+            return Err(conversion_error(rt, value, "array"));
+        }
+        let scope = HandleScope::new(rt);
+        let array = scope.root(value);
+        let len = array_len(value);
+        let mut elements = Vec::with_capacity(len);
+        let mut held = vec![];
+        for index in 0..len {
+            let mut element = T::from_js(rt, array_element(array.get(), index))?;
+            held.extend(root_values(&scope, &mut element));
+            elements.push(element);
+        }
+        reload_values(&mut elements, &held);
+        Ok(elements)
+    }
+}
+
+macro_rules! tuple {
+    ($($name:ident: $t:ident $index:tt),*) => {
+        impl<$($t: HoldsValues),*> HoldsValues for ($($t,)*) {
+            fn for_each_value(&mut self, f: &mut dyn FnMut(&mut JSValue)) {
+                $(self.$index.for_each_value(f);)*
+            }
+        }
+
+        impl<$($t: ToJS),*> ToJS for ($($t,)*) {
+            #[allow(unused_assignments)]
+            fn to_js(mut self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+                let scope = HandleScope::new(rt);
+                let held = root_values(&scope, &mut self);
+                let mut offset = 0;
+                let ($(mut $name,)*) = self;
+                let elements = [$({
+                    offset += reload_values(&mut $name, &held[offset..]);
+                    scope.root($name.to_js(rt)?)
+                }),*];
+                new_array(rt, &elements)
+            }
+        }
+
+        impl<$($t: FromJS),*> FromJS for ($($t,)*) {
+            /// Converts elements of an array, missing elements are `undefined`.
+            fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+                if !value.is_array() {
+                    return Err(conversion_error(rt, value, "array"));
+                }
+                let scope = HandleScope::new(rt);
+                let array = scope.root(value);
+                let mut held = vec![];
+                $(
+                    let mut $name = $t::from_js(rt, array_element(array.get(), $index))?;
+                    held.extend(root_values(&scope, &mut $name));
+                )*
+                let mut tuple = ($($name,)*);
+                reload_values(&mut tuple, &held);
+                Ok(tuple)
+            }
+        }
+    };
+}
+
+tuple!(a: A 0);
+tuple!(a: A 0, b: B 1);
+tuple!(a: A 0, b: B 1, c: C 2);
+tuple!(a: A 0, b: B 1, c: C 2, d: D 3);
+tuple!(a: A 0, b: B 1, c: C 2, d: D 3, e: E 4);
+
+impl<T: HoldsValues, S> HoldsValues for HashMap<String, T, S> {
+    fn for_each_value(&mut self, f: &mut dyn FnMut(&mut JSValue)) {
+        self.values_mut().for_each(|value| value.for_each_value(f));
+    }
+}
+
+impl<T: ToJS, S> ToJS for HashMap<String, T, S> {
+    /// Creates an object with an enumerable property for each entry.
+    fn to_js(mut self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        let scope = HandleScope::new(rt);
+        let held = root_values(&scope, &mut self);
+        let mut offset = 0;
+        let mut entries = Vec::with_capacity(self.len());
+        for (name, mut value) in self {
+            offset += reload_values(&mut value, &held[offset..]);
+            entries.push((name, scope.root(value.to_js(rt)?)));
+        }
+        let object = scope.root(rt.new_object());
+        for (name, value) in entries {
+            let put = object
+                .get()
+                .as_cell()
+                .unchecked_object_mut()
+                .class_object
+                .put;
+            put(rt, object.get(), &name, value.get(), true)?;
+        }
+        Ok(object.get())
+    }
+}
+
+impl<T: FromJS, S: BuildHasher + Default> FromJS for HashMap<String, T, S> {
+    /// Converts own enumerable properties of an object.
+    fn from_js(rt: &mut Runtime, value: JSValue) -> Result<Self, JSValue> {
+        if !value.is_object() {
+            return Err(conversion_error(rt, value, "object"));
+        }
+        let names: Vec<String> = value
+            .as_cell()
+            .unchecked_object_mut()
+            .property
+            .iter()
+            .filter(|(_, prop)| prop.enumerable.unwrap_or(false))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let scope = HandleScope::new(rt);
+        let object = scope.root(value);
+        let mut entries = Vec::with_capacity(names.len());
+        let mut held = vec![];
+        for name in names {
+            let get = object
+                .get()
+                .as_cell()
+                .unchecked_object_mut()
+                .class_object
+                .get;
+            let value = get(rt, object.get(), &name)?;
+            let mut value = T::from_js(rt, value)?;
+            held.extend(root_values(&scope, &mut value));
+            entries.push((name, value));
+        }
+        reload_values(&mut entries, &held);
+        let mut map = HashMap::with_capacity_and_hasher(entries.len(), S::default());
+        map.extend(entries);
+        Ok(map)
+    }
+}
+
+/// Closure taking the runtime and arguments converted by [FromJS], returning a result converted
+/// by [ToJS]. Implemented for closures of up to five arguments.
+pub trait TypedFunction<Args> {
+    /// Number of arguments, `length` of the function.
+    const LENGTH: u32;
+
+    fn into_native(self) -> Box<NativeFn>;
+}
+
+fn argument(args: &[Rooted<JSValue>], index: usize) -> JSValue {
+    args.get(index).map_or_else(JSValue::undefined, Rooted::get)
+}
+
+macro_rules! typed_function {
+    ($len:expr; $($name:ident: $t:ident $index:tt),*) => {
+        impl<F, R, $($t),*> TypedFunction<($($t,)*)> for F
+        where
+            F: Fn(&mut Runtime, $($t),*) -> R + 'static,
+            R: ToJS,
+            $($t: FromJS,)*
+        {
+            const LENGTH: u32 = $len;
+
+            /// Arguments and the values held by converted arguments are rooted while later
+            /// conversions run getters.
+            #[allow(unused_variables, unused_mut)]
+            fn into_native(self) -> Box<NativeFn> {
+                Box::new(move |rt, _this, args| {
+                    let scope = HandleScope::new(rt);
+                    let args: Vec<_> = args.iter().map(|arg| scope.root(*arg)).collect();
+                    let mut held = vec![];
+                    $(
+                        let mut $name = $t::from_js(rt, argument(&args, $index))?;
+                        held.extend(root_values(&scope, &mut $name));
+                    )*
+                    let mut converted = ($($name,)*);
+                    reload_values(&mut converted, &held);
+                    let ($($name,)*) = converted;
+                    self(rt, $($name),*).to_js(rt)
+                })
+            }
+        }
+    };
+}
+
+typed_function!(0;);
+typed_function!(1; a: A 0);
+typed_function!(2; a: A 0, b: B 1);
+typed_function!(3; a: A 0, b: B 1, c: C 2);
+typed_function!(4; a: A 0, b: B 1, c: C 2, d: D 3);
+typed_function!(5; a: A 0, b: B 1, c: C 2, d: D 3, e: E 4);
+
+impl Runtime {
+    /// Creates a native function calling `f` with converted arguments, missing arguments are
+    /// `undefined`. Functions needing `this` are created with [Runtime::new_native_function].
+    pub fn new_typed_function<Args, F: TypedFunction<Args>>(
+        &mut self,
+        name: &str,
+        f: F,
+    ) -> JSValue {
+        self.new_native_function(name, F::LENGTH, false, f.into_native())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::vtable::object_get;
+
+    fn round_trip<T: ToJS + FromJS>(rt: &mut Runtime, value: T) -> T {
+        let value = value.to_js(rt).ok().unwrap();
+        T::from_js(rt, value).ok().unwrap()
+    }
+
+    fn message(rt: &mut Runtime, error: JSValue) -> String {
+        let message = object_get(rt, error, "message").ok().unwrap();
+        message.as_cell().unchecked_string().to_string()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut rt = Runtime::new();
+        assert_eq!(round_trip(&mut rt, -7i8), -7);
+        assert_eq!(round_trip(&mut rt, u64::from(u32::MAX) + 1), 1 << 32);
+        assert_eq!(round_trip(&mut rt, 0.5f64), 0.5);
+        assert!(round_trip(&mut rt, true));
+        assert_eq!(round_trip(&mut rt, "str".to_string()), "str");
+        assert_eq!(round_trip(&mut rt, Some(1u8)), Some(1));
+        assert_eq!(round_trip::<Option<u8>>(&mut rt, None), None);
+        let strings = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(round_trip(&mut rt, strings.clone()), strings);
+        let tuple = (1i32, "x".to_string(), vec![false]);
+        assert_eq!(round_trip(&mut rt, tuple.clone()), tuple);
+        let mut map = HashMap::new();
+        map.insert("one".to_string(), vec![1.0]);
+        map.insert("two".to_string(), vec![2.0, 2.0]);
+        let object = map.clone().to_js(&mut rt).ok().unwrap();
+        assert!(object.is_object());
+        let converted: HashMap<String, Vec<f64>> = FromJS::from_js(&mut rt, object).ok().unwrap();
+        assert_eq!(converted, map);
+
+        let value = "str".to_js(&mut rt).ok().unwrap();
+        assert!(value.is_string());
+        let result: Result<i32, &str> = Err("failed");
+        let thrown = result.to_js(&mut rt).err().unwrap();
+        assert_eq!(thrown.as_cell().unchecked_string().to_string(), "failed");
+    }
+
+    #[test]
+    fn conversion_errors() {
+        let mut rt = Runtime::new();
+        let error = u8::from_js(&mut rt, JSValue::new_int(256)).err().unwrap();
+        assert_eq!(message(&mut rt, error), "Cannot convert number to u8");
+        assert!(i32::from_js(&mut rt, JSValue::new_double(1.5)).is_err());
+        assert!(u32::from_js(&mut rt, JSValue::new_int(-1)).is_err());
+        let string = rt.new_string("1");
+        let error = f64::from_js(&mut rt, string).err().unwrap();
+        assert_eq!(message(&mut rt, error), "Cannot convert string to number");
+        assert!(bool::from_js(&mut rt, JSValue::new_int(1)).is_err());
+        let array = vec![JSValue::new_int(1), JSValue::null()];
+        let array = array.to_js(&mut rt).ok().unwrap();
+        assert!(Vec::<i32>::from_js(&mut rt, array).is_err());
+        let elements = Vec::<Option<i32>>::from_js(&mut rt, array).ok().unwrap();
+        assert_eq!(elements, [Some(1), None]);
+        assert!(String::from_js(&mut rt, array).is_err());
+
+        // Strings and arrays from Rust throw instead of going over the heap limit.
+        rt.gc();
+        rt.max_heap_size = Some(rt.heap_statistics().live_bytes());
+        let error = "x".repeat(1024).to_js(&mut rt).err().unwrap();
+        assert_eq!(message(&mut rt, error), "Out of memory");
+        let error = vec![0; 1024].to_js(&mut rt).err().unwrap();
+        assert_eq!(message(&mut rt, error), "Out of memory");
+    }
+
+    #[test]
+    fn held_values_are_rooted() {
+        use crate::runtime::jsproperty::JSProperty;
+        let mut rt = Runtime::new();
+        rt.gc_stress = true;
+        let scope = HandleScope::new(&rt);
+        let rooted = scope.root(rt.new_object());
+        let array = vec![rooted.get(), rooted.get()]
+            .to_js(&mut rt)
+            .ok()
+            .unwrap();
+        let elements: Vec<JSValue> = FromJS::from_js(&mut rt, array).ok().unwrap();
+        elements.iter().for_each(|element| rt.assert_live(*element));
+        assert!(elements[0] == rooted.get() && elements[1] == rooted.get());
+
+        let tuple = ("first", rooted.get(), "last".to_string());
+        let array = tuple.to_js(&mut rt).ok().unwrap();
+        let (_, element, _): (String, JSValue, String) =
+            FromJS::from_js(&mut rt, array).ok().unwrap();
+        rt.assert_live(element);
+        assert!(element == rooted.get());
+
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), Some(rooted.get()));
+        map.insert("b".to_string(), None);
+        let object = map.to_js(&mut rt).ok().unwrap();
+        let map: HashMap<String, Option<JSValue>> = FromJS::from_js(&mut rt, object).ok().unwrap();
+        rt.assert_live(map["a"].unwrap());
+        assert!(map["a"] == Some(rooted.get()) && map["b"].is_none());
+
+        let getter = rt.new_native_function("get", 0, false, |rt, _, _| Ok(rt.new_string("got")));
+        let getter = scope.root(getter);
+        let accessors = scope.root(rt.new_object());
+        let desc = JSProperty::new().getter(getter.get()).enumerable(true);
+        accessors
+            .get()
+            .as_cell()
+            .unchecked_object_mut()
+            ._write("x", desc);
+        let f = rt.new_typed_function(
+            "f",
+            |rt: &mut Runtime, value: JSValue, map: HashMap<String, String>| {
+                rt.assert_live(value);
+                map["x"].clone()
+            },
+        );
+        let f = scope.root(f);
+        let args = [rooted.get(), accessors.get()];
+        let result = rt
+            .execute(f.get(), JSValue::undefined(), &args)
+            .ok()
+            .unwrap();
+        assert_eq!(String::from_js(&mut rt, result).ok().unwrap(), "got");
+    }
+
+    #[test]
+    fn typed_functions() {
+        let mut rt = Runtime::new();
+        let repeat = rt.new_typed_function(
+            "repeat",
+            |_: &mut Runtime, s: String, count: Option<usize>| s.repeat(count.unwrap_or(2)),
+        );
+        let repeat = Persistent::new(&rt, repeat);
+        let length = object_get(&mut rt, repeat.get(), "length").ok().unwrap();
+        assert_eq!(length.as_number(), 2.0);
+
+        let ab = rt.new_string("ab");
+        let result = rt.execute(repeat.get(), JSValue::undefined(), &[ab]);
+        let result = result.ok().unwrap();
+        assert_eq!(result.as_cell().unchecked_string().to_string(), "abab");
+        let ab = rt.new_string("ab");
+        let result = rt.execute(
+            repeat.get(),
+            JSValue::undefined(),
+            &[ab, JSValue::new_int(3)],
+        );
+        assert_eq!(
+            String::from_js(&mut rt, result.ok().unwrap()).ok(),
+            Some("ababab".into())
+        );
+
+        let error = rt.execute(repeat.get(), JSValue::undefined(), &[JSValue::new_int(1)]);
+        let error = error.err().unwrap();
+        assert_eq!(message(&mut rt, error), "Cannot convert number to string");
+        let proto = error.as_cell().unchecked_object_mut().prototype;
+        assert!(proto == Some(rt.type_error));
+    }
+}
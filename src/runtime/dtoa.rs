@@ -0,0 +1,669 @@
+//! Number to string conversions.
+//!
+//! All decimal conversions start from the exact decimal expansion of the double, which has at most
+//! 767 significant digits, so rounding for `toFixed`, `toExponential` and `toPrecision` is exact.
+//! Shortest round-trip digits for [number_to_string] are found by rounding that expansion to 1, 2,
+//! ... 17 digits and parsing each candidate back.
+
+/// Arbitrary precision unsigned integer with base 10^9 limbs, least significant first.
+struct Decimal {
+    limbs: Vec<u32>,
+}
+
+const LIMB_BASE: u64 = 1_000_000_000;
+
+impl Decimal {
+    fn new(value: u64) -> Self {
+        let mut limbs = vec![];
+        let mut value = value;
+        while value != 0 {
+            limbs.push((value % LIMB_BASE) as u32);
+            value /= LIMB_BASE;
+        }
+        Self { limbs }
+    }
+
+    /// `factor` must be below 2^32 so that no step overflows u64.
+    fn mul_small(&mut self, factor: u64) {
+        let mut carry = 0u64;
+        for limb in self.limbs.iter_mut() {
+            let product = *limb as u64 * factor + carry;
+            *limb = (product % LIMB_BASE) as u32;
+            carry = product / LIMB_BASE;
+        }
+        while carry != 0 {
+            self.limbs.push((carry % LIMB_BASE) as u32);
+            carry /= LIMB_BASE;
+        }
+    }
+
+    fn mul_pow(&mut self, base: u64, mut exponent: u32, chunk: u32) {
+        while exponent > 0 {
+            let step = exponent.min(chunk);
+            self.mul_small(base.pow(step));
+            exponent -= step;
+        }
+    }
+
+    fn digits(&self) -> Vec<u8> {
+        let mut digits = vec![];
+        for (i, limb) in self.limbs.iter().rev().enumerate() {
+            let mut chunk = [0u8; 9];
+            let mut limb = *limb;
+            for digit in chunk.iter_mut().rev() {
+                *digit = (limb % 10) as u8;
+                limb /= 10;
+            }
+            if i == 0 {
+                let first = chunk.iter().position(|d| *d != 0).unwrap_or(8);
+                digits.extend_from_slice(&chunk[first..]);
+            } else {
+                digits.extend_from_slice(&chunk);
+            }
+        }
+        digits
+    }
+}
+
+/// Decimal digits of a positive number, the value is `0.d1d2d3... * 10^point`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Digits {
+    digits: Vec<u8>,
+    point: i32,
+}
+
+impl Digits {
+    /// Exact expansion of finite positive `value` without trailing zeros.
+    fn exact(value: f64) -> Self {
+        debug_assert!(value.is_finite() && value > 0.0);
+        let bits = value.to_bits();
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mantissa, exponent) = if biased_exponent == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased_exponent - 1075)
+        };
+        let mut number = Decimal::new(mantissa);
+        let mut digits;
+        let point;
+        if exponent >= 0 {
+            number.mul_pow(2, exponent as u32, 31);
+            digits = number.digits();
+            point = digits.len() as i32;
+        } else {
+            // m * 2^e = m * 5^-e / 10^-e
+            number.mul_pow(5, (-exponent) as u32, 13);
+            digits = number.digits();
+            point = digits.len() as i32 + exponent;
+        }
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Self { digits, point }
+    }
+
+    fn digit(&self, index: usize) -> u8 {
+        self.digits.get(index).copied().unwrap_or(0)
+    }
+
+    /// Keeps first `count` digits, adding one to the last kept digit when `round_up` is set.
+    ///
+    /// The result has exactly `count` digits, a carry out of the first digit moves the point.
+    /// With `count <= 0` the result is either no digits (zero) or "1" at the rounding position.
+    fn truncate(&self, count: i32, round_up: bool) -> Self {
+        if count <= 0 {
+            return if round_up {
+                Self {
+                    digits: vec![1],
+                    point: self.point - count + 1,
+                }
+            } else {
+                Self {
+                    digits: vec![],
+                    point: self.point,
+                }
+            };
+        }
+        let count = count as usize;
+        let mut digits: Vec<u8> = (0..count).map(|i| self.digit(i)).collect();
+        let mut point = self.point;
+        if round_up {
+            let mut i = count;
+            loop {
+                if i == 0 {
+                    digits.insert(0, 1);
+                    digits.pop();
+                    point += 1;
+                    break;
+                }
+                i -= 1;
+                if digits[i] == 9 {
+                    digits[i] = 0;
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        Self { digits, point }
+    }
+
+    /// Rounds to `count` significant digits, half away from zero.
+    fn round(&self, count: i32) -> Self {
+        let round_up = if count < 0 {
+            false
+        } else {
+            self.digit(count as usize) >= 5
+        };
+        self.truncate(count, round_up)
+    }
+
+    /// Rounds to nearest `count` digit number, in the direction opposite to [Digits::round].
+    fn round_other_way(&self, count: i32) -> Self {
+        let has_rest = self.digits.len() > count as usize;
+        self.truncate(count, has_rest && self.digit(count as usize) < 5)
+    }
+
+    fn to_f64(&self) -> f64 {
+        let mut literal = String::from("0.");
+        for digit in self.digits.iter() {
+            literal.push((b'0' + digit) as char);
+        }
+        literal.push('e');
+        literal.push_str(&self.point.to_string());
+        literal.parse().unwrap()
+    }
+
+    /// Shortest digits that round-trip to `value`, closest to `value` when there is a choice.
+    fn shortest(value: f64) -> Self {
+        let exact = Self::exact(value);
+        for count in 1..=17 {
+            let nearest = exact.round(count);
+            if nearest.to_f64() == value {
+                return nearest.trimmed();
+            }
+            let other = exact.round_other_way(count);
+            if other.to_f64() == value {
+                return other.trimmed();
+            }
+        }
+        exact.round(17).trimmed()
+    }
+
+    fn trimmed(mut self) -> Self {
+        while self.digits.len() > 1 && self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        self
+    }
+
+    /// Exactly `count` digits, zero pads when there are less.
+    fn padded(mut self, count: usize) -> Self {
+        self.digits.resize(count, 0);
+        self
+    }
+
+    fn push_digits(&self, out: &mut String, range: std::ops::Range<usize>) {
+        for i in range {
+            out.push((b'0' + self.digit(i)) as char);
+        }
+    }
+}
+
+fn push_exponent(out: &mut String, exponent: i32) {
+    out.push('e');
+    out.push(if exponent < 0 { '-' } else { '+' });
+    out.push_str(&exponent.abs().to_string());
+}
+
+fn special_to_string(value: f64) -> Option<&'static str> {
+    if value.is_nan() {
+        Some("NaN")
+    } else if value == 0.0 {
+        Some("0")
+    } else if value == f64::INFINITY {
+        Some("Infinity")
+    } else if value == f64::NEG_INFINITY {
+        Some("-Infinity")
+    } else {
+        None
+    }
+}
+
+/// [Number::toString](https://tc39.es/ecma262/#sec-numeric-types-number-tostring) with radix 10.
+pub fn number_to_string(value: f64) -> String {
+    if let Some(special) = special_to_string(value) {
+        return special.to_owned();
+    }
+    let mut out = String::new();
+    if value < 0.0 {
+        out.push('-');
+    }
+    let digits = Digits::shortest(value.abs());
+    let k = digits.digits.len() as i32;
+    let n = digits.point;
+    if k <= n && n <= 21 {
+        digits.push_digits(&mut out, 0..n as usize);
+    } else if 0 < n && n <= 21 {
+        digits.push_digits(&mut out, 0..n as usize);
+        out.push('.');
+        digits.push_digits(&mut out, n as usize..k as usize);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        for _ in 0..-n {
+            out.push('0');
+        }
+        digits.push_digits(&mut out, 0..k as usize);
+    } else {
+        digits.push_digits(&mut out, 0..1);
+        if k > 1 {
+            out.push('.');
+            digits.push_digits(&mut out, 1..k as usize);
+        }
+        push_exponent(&mut out, n - 1);
+    }
+    out
+}
+
+fn radix_digit(digit: u32) -> char {
+    std::char::from_digit(digit, 36).unwrap()
+}
+
+/// Exact digits of integral `value` in `radix`, least significant first.
+fn integer_to_radix(value: f64, radix: u32) -> Vec<char> {
+    let bits = value.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as u32;
+    // Doubles from 2^53 up are `mantissa * 2^shift` with 53 bit mantissa, which is split into
+    // u32 limbs, least significant first. Smaller integers fit into the mantissa as they are.
+    let mut limbs: Vec<u32> = if biased_exponent < 1075 {
+        let integer = value as u64;
+        vec![integer as u32, (integer >> 32) as u32]
+    } else {
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let shift = biased_exponent - 1075;
+        let mut limbs = vec![0u32; (shift / 32) as usize];
+        let wide = (mantissa as u128) << (shift % 32);
+        limbs.extend([wide as u32, (wide >> 32) as u32, (wide >> 64) as u32]);
+        limbs
+    };
+    let mut digits = vec![];
+    loop {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        if limbs.is_empty() {
+            break;
+        }
+        let mut remainder = 0u64;
+        for limb in limbs.iter_mut().rev() {
+            let current = (remainder << 32) | *limb as u64;
+            *limb = (current / radix as u64) as u32;
+            remainder = current % radix as u64;
+        }
+        digits.push(radix_digit(remainder as u32));
+    }
+    if digits.is_empty() {
+        digits.push('0');
+    }
+    digits
+}
+
+/// Number::toString for radix other than 10.
+///
+/// The spec leaves the algorithm to the implementation. Integer part is converted exactly, fraction
+/// digits are produced until the remaining fraction is below the precision of `value` the same way
+/// V8 does, so results round-trip when parsed back.
+pub fn number_to_radix_string(value: f64, radix: u32) -> String {
+    debug_assert!((2..=36).contains(&radix));
+    if radix == 10 {
+        return number_to_string(value);
+    }
+    if let Some(special) = special_to_string(value) {
+        return special.to_owned();
+    }
+    let negative = value < 0.0;
+    let value = value.abs();
+    let radix_f = radix as f64;
+    let mut integer = value.floor();
+    let mut fraction = value - integer;
+    // Half of the distance to the next double, numbers closer than this are the same double.
+    let mut delta = (0.5 * (f64::from_bits(value.to_bits() + 1) - value)).max(f64::from_bits(1));
+    let mut fraction_digits: Vec<u32> = vec![];
+    if fraction >= delta {
+        loop {
+            fraction *= radix_f;
+            delta *= radix_f;
+            let digit = fraction as u32;
+            fraction_digits.push(digit);
+            fraction -= digit as f64;
+            if (fraction > 0.5 || (fraction == 0.5 && digit & 1 == 1)) && fraction + delta > 1.0 {
+                // Round up, propagating carry into the integer part if needed.
+                loop {
+                    match fraction_digits.pop() {
+                        None => {
+                            integer += 1.0;
+                            break;
+                        }
+                        Some(digit) if digit + 1 < radix => {
+                            fraction_digits.push(digit + 1);
+                            break;
+                        }
+                        Some(_) => {}
+                    }
+                }
+                break;
+            }
+            if fraction < delta {
+                break;
+            }
+        }
+    }
+    let integer_digits = integer_to_radix(integer, radix);
+    let mut out = String::new();
+    if negative {
+        out.push('-');
+    }
+    out.extend(integer_digits.iter().rev());
+    if !fraction_digits.is_empty() {
+        out.push('.');
+        out.extend(fraction_digits.iter().map(|digit| radix_digit(*digit)));
+    }
+    out
+}
+
+/// Number.prototype.toFixed for finite `value` and `fraction_digits` in 0..=100.
+pub fn number_to_fixed(value: f64, fraction_digits: u32) -> String {
+    if value.abs() >= 1e21 || !value.is_finite() {
+        return number_to_string(value);
+    }
+    let mut out = String::new();
+    if value < 0.0 {
+        out.push('-');
+    }
+    let f = fraction_digits as i32;
+    let digits = if value == 0.0 {
+        Digits {
+            digits: vec![],
+            point: 0,
+        }
+    } else {
+        let exact = Digits::exact(value.abs());
+        exact.round(exact.point + f)
+    };
+    // Integer part has at least one digit.
+    let point = digits.point.max(1);
+    let start = point - digits.point;
+    let mut integer = String::new();
+    for i in 0..point {
+        let index = i - start;
+        integer.push(
+            (b'0'
+                + if index < 0 {
+                    0
+                } else {
+                    digits.digit(index as usize)
+                }) as char,
+        );
+    }
+    out.push_str(&integer);
+    if f > 0 {
+        out.push('.');
+        for i in point..point + f {
+            let index = i - start;
+            out.push(
+                (b'0'
+                    + if index < 0 {
+                        0
+                    } else {
+                        digits.digit(index as usize)
+                    }) as char,
+            );
+        }
+    }
+    out
+}
+
+fn exponential(out: &mut String, digits: &Digits, count: usize) {
+    digits.push_digits(out, 0..1);
+    if count > 1 {
+        out.push('.');
+        digits.push_digits(out, 1..count);
+    }
+    push_exponent(out, digits.point - 1);
+}
+
+/// Number.prototype.toExponential for finite `value`, `None` means as many digits as needed.
+pub fn number_to_exponential(value: f64, fraction_digits: Option<u32>) -> String {
+    if !value.is_finite() {
+        return number_to_string(value);
+    }
+    let mut out = String::new();
+    if value < 0.0 {
+        out.push('-');
+    }
+    let digits = match (value == 0.0, fraction_digits) {
+        (true, f) => Digits {
+            digits: vec![],
+            point: 1,
+        }
+        .padded(f.unwrap_or(0) as usize + 1),
+        (false, None) => Digits::shortest(value.abs()),
+        (false, Some(f)) => Digits::exact(value.abs())
+            .round(f as i32 + 1)
+            .padded(f as usize + 1),
+    };
+    let count = digits.digits.len();
+    exponential(&mut out, &digits, count);
+    out
+}
+
+/// Number.prototype.toPrecision for finite `value` and `precision` in 1..=100.
+pub fn number_to_precision(value: f64, precision: u32) -> String {
+    if !value.is_finite() {
+        return number_to_string(value);
+    }
+    let mut out = String::new();
+    if value < 0.0 {
+        out.push('-');
+    }
+    let p = precision as i32;
+    let digits = if value == 0.0 {
+        Digits {
+            digits: vec![],
+            point: 1,
+        }
+    } else {
+        Digits::exact(value.abs()).round(p)
+    }
+    .padded(p as usize);
+    let e = digits.point - 1;
+    if e < -6 || e >= p {
+        exponential(&mut out, &digits, p as usize);
+    } else if e == p - 1 {
+        digits.push_digits(&mut out, 0..p as usize);
+    } else if e >= 0 {
+        digits.push_digits(&mut out, 0..(e + 1) as usize);
+        out.push('.');
+        digits.push_digits(&mut out, (e + 1) as usize..p as usize);
+    } else {
+        out.push_str("0.");
+        for _ in 0..-(e + 1) {
+            out.push('0');
+        }
+        digits.push_digits(&mut out, 0..p as usize);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortest_round_trip() {
+        let cases = [
+            (0.1 + 0.2, "0.30000000000000004"),
+            (0.1, "0.1"),
+            (5e-324, "5e-324"),
+            (-5e-324, "-5e-324"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+            (9007199254740993.0, "9007199254740992"),
+            (123.456, "123.456"),
+            (-1.5, "-1.5"),
+            (1.0 / 3.0, "0.3333333333333333"),
+            (f64::NAN, "NaN"),
+            (f64::INFINITY, "Infinity"),
+            (f64::NEG_INFINITY, "-Infinity"),
+        ];
+        for (value, expected) in cases.iter() {
+            let string = number_to_string(*value);
+            assert_eq!(string, *expected, "{:e}", value);
+            if value.is_finite() {
+                assert_eq!(string.parse::<f64>().unwrap(), *value);
+            }
+        }
+    }
+
+    #[test]
+    fn exponent_cutoffs() {
+        let cases = [
+            (1e21, "1e+21"),
+            (-1e21, "-1e+21"),
+            (1.5e21, "1.5e+21"),
+            (999999999999999900000.0, "999999999999999900000"),
+            (1e20, "100000000000000000000"),
+            (123e18, "123000000000000000000"),
+            (1e-7, "1e-7"),
+            (1.5e-7, "1.5e-7"),
+            (1e-6, "0.000001"),
+            (0.000001234, "0.000001234"),
+            (-0.0, "0"),
+            (0.0, "0"),
+        ];
+        for (value, expected) in cases.iter() {
+            assert_eq!(number_to_string(*value), *expected, "{:e}", value);
+        }
+    }
+
+    #[test]
+    fn to_fixed() {
+        let cases = [
+            (1.005, 2, "1.00"),
+            (1.45, 1, "1.4"),
+            (1.55, 1, "1.6"),
+            (0.5, 0, "1"),
+            (1.5, 0, "2"),
+            (2.5, 0, "3"),
+            (-2.5, 0, "-3"),
+            (0.0, 2, "0.00"),
+            (-0.0, 2, "0.00"),
+            (0.000001, 7, "0.0000010"),
+            (123.456, 10, "123.4560000000"),
+            (1e21, 2, "1e+21"),
+            (999999999999999900000.0, 1, "999999999999999868928.0"),
+            (-0.0000001, 2, "-0.00"),
+            (0.1, 20, "0.10000000000000000555"),
+        ];
+        for (value, digits, expected) in cases.iter() {
+            assert_eq!(
+                number_to_fixed(*value, *digits),
+                *expected,
+                "({:e}).toFixed({})",
+                value,
+                digits
+            );
+        }
+    }
+
+    #[test]
+    fn to_exponential() {
+        let cases = [
+            (1.25, Some(1), "1.3e+0"),
+            (1.35, Some(1), "1.4e+0"),
+            (-1.25, Some(1), "-1.3e+0"),
+            (123456.0, Some(2), "1.23e+5"),
+            (123456.0, None, "1.23456e+5"),
+            (0.0, Some(2), "0.00e+0"),
+            (0.0, None, "0e+0"),
+            (5e-324, None, "5e-324"),
+            (0.00001, None, "1e-5"),
+            (9.5, Some(0), "1e+1"),
+            (1.7976931348623157e308, Some(3), "1.798e+308"),
+        ];
+        for (value, digits, expected) in cases.iter() {
+            assert_eq!(
+                number_to_exponential(*value, *digits),
+                *expected,
+                "({:e}).toExponential({:?})",
+                value,
+                digits
+            );
+        }
+    }
+
+    #[test]
+    fn to_precision() {
+        let cases = [
+            (2.5, 1, "3"),
+            (0.25, 1, "0.3"),
+            (-1.005, 3, "-1.00"),
+            (123.456, 2, "1.2e+2"),
+            (123.456, 3, "123"),
+            (123.456, 4, "123.5"),
+            (99.99, 3, "100"),
+            (99.99, 2, "1.0e+2"),
+            (0.000001, 2, "0.0000010"),
+            (0.0000001, 1, "1e-7"),
+            (0.0, 3, "0.00"),
+            (1e21, 3, "1.00e+21"),
+        ];
+        for (value, precision, expected) in cases.iter() {
+            assert_eq!(
+                number_to_precision(*value, *precision),
+                *expected,
+                "({:e}).toPrecision({})",
+                value,
+                precision
+            );
+        }
+    }
+
+    #[test]
+    fn radix_output() {
+        let cases = [
+            (255.0, 16, "ff"),
+            (-255.0, 2, "-11111111"),
+            (35.0, 36, "z"),
+            (-0.0, 2, "0"),
+            (0.5, 2, "0.1"),
+            (-0.5, 16, "-0.8"),
+            (3.75, 16, "3.c"),
+            (-3.75, 2, "-11.11"),
+            (0.1, 16, "0.1999999999999a"),
+            (
+                0.1,
+                2,
+                "0.0001100110011001100110011001100110011001100110011001101",
+            ),
+            (2f64.powi(60), 16, "1000000000000000"),
+            (
+                -2f64.powi(70),
+                2,
+                "-10000000000000000000000000000000000000000000000000000000000000000000000",
+            ),
+            (f64::NAN, 2, "NaN"),
+            (f64::NEG_INFINITY, 16, "-Infinity"),
+        ];
+        for (value, radix, expected) in cases.iter() {
+            assert_eq!(
+                number_to_radix_string(*value, *radix),
+                *expected,
+                "({:e}).toString({})",
+                value,
+                radix
+            );
+        }
+    }
+}
@@ -1 +1,1247 @@
+//! Lexical environments.
+//!
+//! Every scope pushed by `PushScope` is an [Environment] living in the GC heap as `Cell::Environment`.
+//! Environments are linked through `parent` up to the global environment (the one stored in
+//! `Runtime::global_environment`), this chain is what `ResolveScope` and `GetParentScope` walk.
+//!
+//! There are four kinds of environment records, mirroring [the spec](https://tc39.es/ecma262/#sec-environment-records):
+//! - Declarative records keep bindings in a slot array, the bytecode generator knows slot count
+//!   of every scope so the array is allocated once with the right size.
+//! - Object records expose properties of an object as bindings, used for `with` statements and
+//!   as the variable part of the global environment.
+//! - Function records are declarative records that also provide `this` and `super` for `GetThis`/`GetSuper`.
+//! - The global record combines an object record for the global object with a declarative record
+//!   for top level lexical declarations. Scripts declare their names in it with
+//!   [global_declaration_instantiation].
+//!
+//! # Temporal dead zone
+//!
+//! `let`, `const` and `class` bindings are created uninitialized: their slot holds the empty value
+//! (`JSValue::default()`) until the declaration is evaluated. Reading or assigning a binding in this
+//! state throws ReferenceError. Loads that go through a slot directly must be checked with
+//! [check_tdz] (or `IsEmpty` in bytecode) every time, there is no analysis yet that proves a load
+//! always runs after the declaration.
+use super::cell::*;
+use super::jsproperty::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+use crate::bytecode::codeblock::CodeBlock;
+use cgc::api::{Finalizer, Handle, Traceable, Tracer};
+use fxhash::FxHashMap;
 
+/// Binding stored in declarative record.
+#[derive(Copy, Clone)]
+pub struct Binding {
+    /// Index of binding value in `DeclarativeRecord::slots`.
+    pub slot: u32,
+    pub mutable: bool,
+    /// Bindings created by `eval` code might be deleted.
+    pub deletable: bool,
+    /// Assignment to immutable binding created with `strict` set throws even in sloppy mode.
+    pub strict: bool,
+}
+
+pub struct DeclarativeRecord {
+    pub bindings: FxHashMap<String, Binding>,
+    /// Binding values, slots of bindings that are not created yet hold the empty value.
+    pub slots: Vec<JSValue>,
+    /// Slot of the next binding to be created.
+    next_slot: u32,
+}
+
+impl DeclarativeRecord {
+    /// Creates record with `slots` preallocated slots. Bindings known at compile time occupy the
+    /// first slots in declaration order, bindings introduced dynamically (by `eval`) are appended.
+    pub fn new(slots: usize) -> Self {
+        Self {
+            bindings: FxHashMap::default(),
+            slots: vec![JSValue::default(); slots],
+            next_slot: 0,
+        }
+    }
+
+    pub fn has_binding(&self, name: &str) -> bool {
+        self.bindings.contains_key(name)
+    }
+
+    fn create_binding(&mut self, name: &str, binding: Binding, init: JSValue) -> u32 {
+        debug_assert!(!self.has_binding(name), "binding '{}' already exists", name);
+        self.bindings.insert(name.to_owned(), binding);
+        match self.slots.get_mut(binding.slot as usize) {
+            Some(slot) => *slot = init,
+            None => self.slots.push(init),
+        }
+        self.next_slot += 1;
+        binding.slot
+    }
+
+    /// [CreateMutableBinding](https://tc39.es/ecma262/#sec-declarative-environment-records-createmutablebinding-n-d)
+    /// for `var` declarations and parameters, the binding is initialized to `undefined` right away.
+    ///
+    /// Returns slot index of the new binding.
+    pub fn create_mutable_binding(&mut self, name: &str, deletable: bool) -> u32 {
+        let slot = self.next_slot;
+        self.create_binding(
+            name,
+            Binding {
+                slot,
+                mutable: true,
+                deletable,
+                strict: false,
+            },
+            JSValue::undefined(),
+        )
+    }
+
+    /// Creates uninitialized mutable binding for `let` and `class` declarations.
+    ///
+    /// Returns slot index of the new binding.
+    pub fn create_lexical_binding(&mut self, name: &str) -> u32 {
+        let slot = self.next_slot;
+        self.create_binding(
+            name,
+            Binding {
+                slot,
+                mutable: true,
+                deletable: false,
+                strict: false,
+            },
+            JSValue::default(),
+        )
+    }
+
+    /// [CreateImmutableBinding](https://tc39.es/ecma262/#sec-declarative-environment-records-createimmutablebinding-n-s)
+    ///
+    /// Returns slot index of the new binding, it stays uninitialized until `initialize_binding`.
+    /// `const` declarations pass `strict = true` so assignments throw in sloppy code too.
+    pub fn create_immutable_binding(&mut self, name: &str, strict: bool) -> u32 {
+        let slot = self.next_slot;
+        self.create_binding(
+            name,
+            Binding {
+                slot,
+                mutable: false,
+                deletable: false,
+                strict,
+            },
+            JSValue::default(),
+        )
+    }
+
+    pub fn initialize_binding(&mut self, name: &str, value: JSValue) {
+        let binding = self.bindings[name];
+        self.slots[binding.slot as usize] = value;
+    }
+
+    pub fn is_initialized(&self, name: &str) -> bool {
+        !self.slots[self.bindings[name].slot as usize].is_empty()
+    }
+
+    /// [SetMutableBinding](https://tc39.es/ecma262/#sec-declarative-environment-records-setmutablebinding-n-v-s)
+    pub fn set_mutable_binding(
+        &mut self,
+        rt: &mut Runtime,
+        name: &str,
+        value: JSValue,
+        strict: bool,
+    ) -> Result<(), JSValue> {
+        let binding = match self.bindings.get(name) {
+            Some(binding) => *binding,
+            None => {
+                if strict {
+                    let msg = format!("{} is not defined", name);
+                    return Err(rt.new_reference_error(&msg));
+                }
+                self.create_mutable_binding(name, true);
+                self.initialize_binding(name, value);
+                return Ok(());
+            }
+        };
+        if self.slots[binding.slot as usize].is_empty() {
+            return Err(tdz_error(rt, name));
+        }
+        if binding.mutable {
+            self.slots[binding.slot as usize] = value;
+        } else if strict || binding.strict {
+            let msg = format!("Assignment to constant variable '{}'", name);
+            return Err(rt.new_type_error(&msg));
+        }
+        Ok(())
+    }
+
+    /// [GetBindingValue](https://tc39.es/ecma262/#sec-declarative-environment-records-getbindingvalue-n-s)
+    pub fn get_binding_value(&self, rt: &mut Runtime, name: &str) -> Result<JSValue, JSValue> {
+        check_tdz(rt, self.slots[self.bindings[name].slot as usize], name)
+    }
+
+    /// [DeleteBinding](https://tc39.es/ecma262/#sec-declarative-environment-records-deletebinding-n)
+    pub fn delete_binding(&mut self, name: &str) -> bool {
+        match self.bindings.get(name) {
+            None => true,
+            Some(binding) if binding.deletable => {
+                // The slot is left in place: slot indices of other bindings stay valid.
+                self.slots[binding.slot as usize] = JSValue::undefined();
+                self.bindings.remove(name);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct ObjectRecord {
+    pub binding_object: JSValue,
+    /// `true` when this record was created by `with` statement.
+    pub with_environment: bool,
+}
+
+impl ObjectRecord {
+    fn vtable(&self) -> &'static super::vtable::VTable {
+        self.binding_object
+            .as_cell()
+            .get_mut()
+            .unchecked_object_mut()
+            .class_object
+    }
+
+    pub fn has_binding(&self, rt: &mut Runtime, name: &str) -> bool {
+        // TODO: Check @@unscopables once symbols are implemented.
+        (self.vtable().has_property)(rt, self.binding_object, name)
+    }
+
+    pub fn create_mutable_binding(
+        &self,
+        rt: &mut Runtime,
+        name: &str,
+        deletable: bool,
+    ) -> Result<(), JSValue> {
+        (self.vtable().define_own_property)(
+            rt,
+            self.binding_object,
+            name,
+            JSProperty::new()
+                .value(JSValue::undefined())
+                .writable(true)
+                .enumerable(true)
+                .configurable(deletable),
+            true,
+        )?;
+        Ok(())
+    }
+
+    pub fn set_mutable_binding(
+        &self,
+        rt: &mut Runtime,
+        name: &str,
+        value: JSValue,
+        strict: bool,
+    ) -> Result<(), JSValue> {
+        let still_exists = (self.vtable().has_property)(rt, self.binding_object, name);
+        if !still_exists && strict {
+            let msg = format!("{} is not defined", name);
+            return Err(rt.new_reference_error(&msg));
+        }
+        (self.vtable().put)(rt, self.binding_object, name, value, strict)
+    }
+
+    pub fn get_binding_value(
+        &self,
+        rt: &mut Runtime,
+        name: &str,
+        strict: bool,
+    ) -> Result<JSValue, JSValue> {
+        if !(self.vtable().has_property)(rt, self.binding_object, name) {
+            if strict {
+                let msg = format!("{} is not defined", name);
+                return Err(rt.new_reference_error(&msg));
+            }
+            return Ok(JSValue::undefined());
+        }
+        (self.vtable().get)(rt, self.binding_object, name)
+    }
+
+    pub fn delete_binding(&self, rt: &mut Runtime, name: &str) -> Result<bool, JSValue> {
+        (self.vtable().delete)(rt, self.binding_object, name, false)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ThisBindingStatus {
+    /// Arrow functions take `this` from enclosing environment.
+    Lexical,
+    Initialized,
+    /// Derived class constructors have no `this` until `super()` is called.
+    Uninitialized,
+}
+
+pub struct FunctionRecord {
+    pub declarative: DeclarativeRecord,
+    pub this_value: JSValue,
+    pub this_binding_status: ThisBindingStatus,
+    pub function_object: JSValue,
+    /// Object whose prototype is used as `super` base, `undefined` for functions that are not methods.
+    pub home_object: JSValue,
+    pub new_target: JSValue,
+}
+
+impl FunctionRecord {
+    /// [BindThisValue](https://tc39.es/ecma262/#sec-bindthisvalue)
+    pub fn bind_this_value(&mut self, rt: &mut Runtime, this: JSValue) -> Result<(), JSValue> {
+        assert_ne!(self.this_binding_status, ThisBindingStatus::Lexical);
+        if self.this_binding_status == ThisBindingStatus::Initialized {
+            return Err(rt.new_reference_error("Super constructor may only be called once"));
+        }
+        self.this_value = this;
+        self.this_binding_status = ThisBindingStatus::Initialized;
+        Ok(())
+    }
+
+    pub fn get_this_binding(&self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        assert_ne!(self.this_binding_status, ThisBindingStatus::Lexical);
+        if self.this_binding_status == ThisBindingStatus::Uninitialized {
+            return Err(rt.new_reference_error(
+                "Must call super constructor in derived class before accessing 'this'",
+            ));
+        }
+        Ok(self.this_value)
+    }
+
+    /// [GetSuperBase](https://tc39.es/ecma262/#sec-getsuperbase)
+    pub fn get_super_base(&self) -> JSValue {
+        if self.home_object.is_undefined() {
+            return JSValue::undefined();
+        }
+        let mut cell = self.home_object.as_cell();
+        match cell.unchecked_object_mut().prototype {
+            Some(proto) => proto,
+            None => JSValue::null(),
+        }
+    }
+}
+
+pub struct GlobalRecord {
+    pub object: ObjectRecord,
+    pub declarative: DeclarativeRecord,
+    pub this_value: JSValue,
+}
+
+pub enum EnvironmentRecord {
+    Declarative(DeclarativeRecord),
+    Object(ObjectRecord),
+    Function(FunctionRecord),
+    Global(GlobalRecord),
+}
+
+pub struct Environment {
+    /// Outer environment, `None` only for the global environment.
+    pub parent: Option<JSValue>,
+    pub record: EnvironmentRecord,
+}
+
+impl Environment {
+    fn declarative(&self) -> Option<&DeclarativeRecord> {
+        match &self.record {
+            EnvironmentRecord::Declarative(record) => Some(record),
+            EnvironmentRecord::Function(record) => Some(&record.declarative),
+            EnvironmentRecord::Global(record) => Some(&record.declarative),
+            EnvironmentRecord::Object(_) => None,
+        }
+    }
+
+    fn declarative_mut(&mut self) -> Option<&mut DeclarativeRecord> {
+        match &mut self.record {
+            EnvironmentRecord::Declarative(record) => Some(record),
+            EnvironmentRecord::Function(record) => Some(&mut record.declarative),
+            EnvironmentRecord::Global(record) => Some(&mut record.declarative),
+            EnvironmentRecord::Object(_) => None,
+        }
+    }
+
+    /// Reads binding by slot index, used when the generator resolved variable at compile time.
+    /// Slots of bindings that are not created yet read as the empty value.
+    pub fn get_slot(&self, slot: u32) -> JSValue {
+        self.declarative().expect("environment has no slots").slots[slot as usize]
+    }
+
+    /// Stores into environments in the heap need [Runtime::write_barrier], see [put_to_slot].
+    pub fn set_slot(&mut self, slot: u32, value: JSValue) {
+        self.declarative_mut()
+            .expect("environment has no slots")
+            .slots[slot as usize] = value;
+    }
+
+    pub fn has_binding(&self, rt: &mut Runtime, name: &str) -> bool {
+        match &self.record {
+            EnvironmentRecord::Object(record) => record.has_binding(rt, name),
+            EnvironmentRecord::Global(record) => {
+                record.declarative.has_binding(name) || record.object.has_binding(rt, name)
+            }
+            _ => self.declarative().unwrap().has_binding(name),
+        }
+    }
+
+    pub fn create_mutable_binding(
+        &mut self,
+        rt: &mut Runtime,
+        name: &str,
+        deletable: bool,
+    ) -> Result<(), JSValue> {
+        match &mut self.record {
+            EnvironmentRecord::Object(record) => record.create_mutable_binding(rt, name, deletable),
+            EnvironmentRecord::Global(record) => {
+                record.object.create_mutable_binding(rt, name, deletable)
+            }
+            _ => {
+                self.declarative_mut()
+                    .unwrap()
+                    .create_mutable_binding(name, deletable);
+                Ok(())
+            }
+        }
+    }
+
+    /// Creates uninitialized lexical (`let`, `const`, `class`) binding. In the global environment
+    /// these go to the declarative part instead of the global object.
+    pub fn create_lexical_binding(&mut self, name: &str, mutable: bool) -> u32 {
+        let record = self
+            .declarative_mut()
+            .expect("lexical declarations are not allowed in object environment");
+        if mutable {
+            record.create_lexical_binding(name)
+        } else {
+            record.create_immutable_binding(name, true)
+        }
+    }
+
+    pub fn initialize_binding(&mut self, rt: &mut Runtime, name: &str, value: JSValue) {
+        match &mut self.record {
+            EnvironmentRecord::Object(record) => {
+                // Errors are impossible here: strict is false.
+                let _ = record.set_mutable_binding(rt, name, value, false);
+            }
+            EnvironmentRecord::Global(record) if !record.declarative.has_binding(name) => {
+                let _ = record.object.set_mutable_binding(rt, name, value, false);
+            }
+            _ => self
+                .declarative_mut()
+                .unwrap()
+                .initialize_binding(name, value),
+        }
+    }
+
+    pub fn set_mutable_binding(
+        &mut self,
+        rt: &mut Runtime,
+        name: &str,
+        value: JSValue,
+        strict: bool,
+    ) -> Result<(), JSValue> {
+        match &mut self.record {
+            EnvironmentRecord::Object(record) => {
+                record.set_mutable_binding(rt, name, value, strict)
+            }
+            EnvironmentRecord::Global(record) if !record.declarative.has_binding(name) => {
+                record.object.set_mutable_binding(rt, name, value, strict)
+            }
+            _ => self
+                .declarative_mut()
+                .unwrap()
+                .set_mutable_binding(rt, name, value, strict),
+        }
+    }
+
+    pub fn get_binding_value(
+        &self,
+        rt: &mut Runtime,
+        name: &str,
+        strict: bool,
+    ) -> Result<JSValue, JSValue> {
+        match &self.record {
+            EnvironmentRecord::Object(record) => record.get_binding_value(rt, name, strict),
+            EnvironmentRecord::Global(record) if !record.declarative.has_binding(name) => {
+                record.object.get_binding_value(rt, name, strict)
+            }
+            _ => self.declarative().unwrap().get_binding_value(rt, name),
+        }
+    }
+
+    pub fn delete_binding(&mut self, rt: &mut Runtime, name: &str) -> Result<bool, JSValue> {
+        match &mut self.record {
+            EnvironmentRecord::Object(record) => record.delete_binding(rt, name),
+            EnvironmentRecord::Global(record) if !record.declarative.has_binding(name) => {
+                record.object.delete_binding(rt, name)
+            }
+            _ => Ok(self.declarative_mut().unwrap().delete_binding(name)),
+        }
+    }
+
+    pub fn has_this_binding(&self) -> bool {
+        match &self.record {
+            EnvironmentRecord::Function(record) => {
+                record.this_binding_status != ThisBindingStatus::Lexical
+            }
+            EnvironmentRecord::Global(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_super_binding(&self) -> bool {
+        match &self.record {
+            EnvironmentRecord::Function(record) => {
+                record.this_binding_status != ThisBindingStatus::Lexical
+                    && !record.home_object.is_undefined()
+            }
+            _ => false,
+        }
+    }
+
+    /// [WithBaseObject](https://tc39.es/ecma262/#sec-object-environment-records-withbaseobject)
+    pub fn with_base_object(&self) -> JSValue {
+        match &self.record {
+            EnvironmentRecord::Object(record) if record.with_environment => record.binding_object,
+            _ => JSValue::undefined(),
+        }
+    }
+}
+
+fn tdz_error(rt: &mut Runtime, name: &str) -> JSValue {
+    let msg = format!("Cannot access '{}' before initialization", name);
+    rt.new_reference_error(&msg)
+}
+
+/// Throws ReferenceError if `value` loaded from binding `name` is the uninitialized marker.
+pub fn check_tdz(rt: &mut Runtime, value: JSValue, name: &str) -> Result<JSValue, JSValue> {
+    if value.is_empty() {
+        return Err(tdz_error(rt, name));
+    }
+    Ok(value)
+}
+
+impl Traceable for Environment {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.parent.trace_with(tracer);
+        match &self.record {
+            EnvironmentRecord::Declarative(record) => record.slots.trace_with(tracer),
+            EnvironmentRecord::Object(record) => record.binding_object.trace_with(tracer),
+            EnvironmentRecord::Function(record) => {
+                record.declarative.slots.trace_with(tracer);
+                record.this_value.trace_with(tracer);
+                record.function_object.trace_with(tracer);
+                record.home_object.trace_with(tracer);
+                record.new_target.trace_with(tracer);
+            }
+            EnvironmentRecord::Global(record) => {
+                record.object.binding_object.trace_with(tracer);
+                record.declarative.slots.trace_with(tracer);
+                record.this_value.trace_with(tracer);
+            }
+        }
+    }
+}
+
+impl Finalizer for Environment {}
+
+/// [NewDeclarativeEnvironment](https://tc39.es/ecma262/#sec-newdeclarativeenvironment)
+pub fn new_declarative_environment(
+    rt: &mut Runtime,
+    parent: Option<JSValue>,
+    slots: usize,
+) -> JSValue {
+    rt.allocate(Cell::Environment(Box::new(Environment {
+        parent,
+        record: EnvironmentRecord::Declarative(DeclarativeRecord::new(slots)),
+    })))
+}
+
+/// [NewObjectEnvironment](https://tc39.es/ecma262/#sec-newobjectenvironment)
+pub fn new_object_environment(
+    rt: &mut Runtime,
+    parent: Option<JSValue>,
+    object: JSValue,
+    with_environment: bool,
+) -> JSValue {
+    rt.allocate(Cell::Environment(Box::new(Environment {
+        parent,
+        record: EnvironmentRecord::Object(ObjectRecord {
+            binding_object: object,
+            with_environment,
+        }),
+    })))
+}
+
+/// [NewFunctionEnvironment](https://tc39.es/ecma262/#sec-newfunctionenvironment)
+///
+/// Pass `lexical_this` for arrow functions, they resolve `this` and `super` through `parent`.
+pub fn new_function_environment(
+    rt: &mut Runtime,
+    parent: Option<JSValue>,
+    slots: usize,
+    function_object: JSValue,
+    home_object: JSValue,
+    new_target: JSValue,
+    lexical_this: bool,
+) -> JSValue {
+    let this_binding_status = if lexical_this {
+        ThisBindingStatus::Lexical
+    } else {
+        ThisBindingStatus::Uninitialized
+    };
+    rt.allocate(Cell::Environment(Box::new(Environment {
+        parent,
+        record: EnvironmentRecord::Function(FunctionRecord {
+            declarative: DeclarativeRecord::new(slots),
+            this_value: JSValue::undefined(),
+            this_binding_status,
+            function_object,
+            home_object,
+            new_target,
+        }),
+    })))
+}
+
+/// [NewGlobalEnvironment](https://tc39.es/ecma262/#sec-newglobalenvironment)
+pub fn new_global_environment(rt: &mut Runtime, global: JSValue, this_value: JSValue) -> JSValue {
+    rt.allocate(Cell::Environment(Box::new(Environment {
+        parent: None,
+        record: EnvironmentRecord::Global(GlobalRecord {
+            object: ObjectRecord {
+                binding_object: global,
+                with_environment: false,
+            },
+            declarative: DeclarativeRecord::new(0),
+            this_value,
+        }),
+    })))
+}
+
+/// [GlobalDeclarationInstantiation](https://tc39.es/ecma262/#sec-globaldeclarationinstantiation)
+///
+/// `var_names` are `var` and function declaration names of a script, `lexical_names` are
+/// `let`/`class` (`true`) and `const` (`false`) declarations. Throws SyntaxError when a name is
+/// declared both ways across scripts. Var names of earlier scripts are not tracked, they are found
+/// as non-configurable properties of the global object.
+pub fn global_declaration_instantiation(
+    rt: &mut Runtime,
+    var_names: &[&str],
+    lexical_names: &[(&str, bool)],
+) -> Result<(), JSValue> {
+    let get_own_property = rt
+        .global
+        .as_cell()
+        .unchecked_object_mut()
+        .class_object
+        .get_own_property;
+    for (name, _) in lexical_names {
+        let restricted = match get_own_property(rt, rt.global, name) {
+            Some(desc) => !desc.configurable.unwrap_or(false),
+            None => false,
+        };
+        let mut cell = rt.global_environment.as_cell();
+        let declared = cell.unchecked_environment_mut().declarative().unwrap();
+        if restricted || declared.has_binding(name) || var_names.contains(name) {
+            let msg = format!("Identifier '{}' has already been declared", name);
+            return Err(rt.new_syntax_error(&msg));
+        }
+    }
+    for name in var_names {
+        let mut cell = rt.global_environment.as_cell();
+        if cell
+            .unchecked_environment_mut()
+            .declarative()
+            .unwrap()
+            .has_binding(name)
+        {
+            let msg = format!("Identifier '{}' has already been declared", name);
+            return Err(rt.new_syntax_error(&msg));
+        }
+    }
+
+    for name in var_names {
+        // CreateGlobalVarBinding: existing properties are left as they are.
+        if get_own_property(rt, rt.global, name).is_none() {
+            let mut cell = rt.global_environment.as_cell();
+            cell.unchecked_environment_mut()
+                .create_mutable_binding(rt, name, false)?;
+        }
+    }
+    let mut cell = rt.global_environment.as_cell();
+    let environment = cell.unchecked_environment_mut();
+    for (name, mutable) in lexical_names {
+        environment.create_lexical_binding(name, *mutable);
+    }
+    Ok(())
+}
+
+/// Implements `GetParentScope`.
+pub fn get_parent_environment(env: JSValue) -> Option<JSValue> {
+    env.as_cell().unchecked_environment_mut().parent
+}
+
+/// Implements `ResolveScope`: returns environment that has binding `name` or `undefined` if the
+/// reference is unresolvable.
+pub fn resolve_binding(rt: &mut Runtime, env: JSValue, name: &str) -> JSValue {
+    let mut current = Some(env);
+    while let Some(env) = current {
+        let mut cell = env.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        if environment.has_binding(rt, name) {
+            return env;
+        }
+        current = environment.parent;
+    }
+    JSValue::undefined()
+}
+
+/// [GetThisEnvironment](https://tc39.es/ecma262/#sec-getthisenvironment)
+pub fn get_this_environment(env: JSValue) -> JSValue {
+    let mut current = env;
+    loop {
+        let mut cell = current.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        if environment.has_this_binding() {
+            return current;
+        }
+        // The global environment always has `this` binding so `parent` can't be `None` here.
+        current = environment.parent.unwrap();
+    }
+}
+
+/// Implements `GetThis`.
+pub fn resolve_this_binding(rt: &mut Runtime, env: JSValue) -> Result<JSValue, JSValue> {
+    let mut cell = get_this_environment(env).as_cell();
+    match &cell.unchecked_environment_mut().record {
+        EnvironmentRecord::Function(record) => record.get_this_binding(rt),
+        EnvironmentRecord::Global(record) => Ok(record.this_value),
+        _ => unreachable!(),
+    }
+}
+
+/// Implements `GetSuper`: returns base object for `super.x` lookups.
+pub fn resolve_super_base(rt: &mut Runtime, env: JSValue) -> Result<JSValue, JSValue> {
+    let mut cell = get_this_environment(env).as_cell();
+    let environment = cell.unchecked_environment_mut();
+    if !environment.has_super_binding() {
+        return Err(rt.new_syntax_error("'super' keyword unexpected here"));
+    }
+    match &environment.record {
+        EnvironmentRecord::Function(record) => Ok(record.get_super_base()),
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the nearest environment `var` declarations are hoisted to: function or global environment.
+pub fn get_var_environment(env: JSValue) -> JSValue {
+    let mut current = env;
+    loop {
+        let mut cell = current.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        match environment.record {
+            EnvironmentRecord::Function(_) | EnvironmentRecord::Global(_) => return current,
+            _ => current = environment.parent.unwrap(),
+        }
+    }
+}
+
+/// Implements `GetFromScope` for names not resolved at compile time. Reading unresolvable name
+/// throws ReferenceError in both strict and sloppy code. `code` is the code doing the lookup.
+pub fn get_variable(
+    rt: &mut Runtime,
+    env: JSValue,
+    name: &str,
+    code: &CodeBlock,
+) -> Result<JSValue, JSValue> {
+    let scope = resolve_binding(rt, env, name);
+    if scope.is_undefined() {
+        let msg = format!("{} is not defined", name);
+        return Err(rt.new_reference_error(&msg));
+    }
+    scope
+        .as_cell()
+        .unchecked_environment_mut()
+        .get_binding_value(rt, name, code.strict)
+}
+
+/// Implements `PutToScope` for names not resolved at compile time. Assignment to undeclared
+/// variable creates global object property in sloppy code and throws ReferenceError in strict code.
+pub fn put_variable(
+    rt: &mut Runtime,
+    env: JSValue,
+    name: &str,
+    value: JSValue,
+    code: &CodeBlock,
+) -> Result<(), JSValue> {
+    let handles = HandleScope::new(rt);
+    let value = handles.root(value);
+    let scope = resolve_binding(rt, env, name);
+    if scope.is_undefined() {
+        if code.strict {
+            let msg = format!("{} is not defined", name);
+            return Err(rt.new_reference_error(&msg));
+        }
+        let global = rt.global;
+        let mut cell = global.as_cell();
+        let put = cell.unchecked_object_mut().class_object.put;
+        return put(rt, global, name, value.get(), false);
+    }
+    let scope = handles.root(scope);
+    scope
+        .get()
+        .as_cell()
+        .unchecked_environment_mut()
+        .set_mutable_binding(rt, name, value.get(), code.strict)?;
+    // Setters of object records can run a collection, the stored value is re-read.
+    rt.write_barrier(scope.get(), value.get());
+    Ok(())
+}
+
+/// Implements `delete name` for names not resolved at compile time. Unresolvable names are
+/// deleted successfully, bindings of declarations are not deletable unless they were created by
+/// sloppy eval. Deleting an unqualified name is a SyntaxError in strict code.
+pub fn delete_variable(
+    rt: &mut Runtime,
+    env: JSValue,
+    name: &str,
+    code: &CodeBlock,
+) -> Result<bool, JSValue> {
+    if code.strict {
+        let msg = "Delete of an unqualified identifier in strict mode.";
+        return Err(rt.new_syntax_error(msg));
+    }
+    let scope = resolve_binding(rt, env, name);
+    if scope.is_undefined() {
+        return Ok(true);
+    }
+    scope
+        .as_cell()
+        .unchecked_environment_mut()
+        .delete_binding(rt, name)
+}
+
+/// Implements `PutToScope` for bindings resolved to slot `slot` of `env` at compile time.
+pub fn put_to_slot(rt: &mut Runtime, env: JSValue, slot: u32, value: JSValue) {
+    env.as_cell()
+        .unchecked_environment_mut()
+        .set_slot(slot, value);
+    rt.write_barrier(env, value);
+}
+
+/// [OrdinaryCallBindThis](https://tc39.es/ecma262/#sec-ordinarycallbindthis)
+///
+/// Strict functions see `this` exactly as passed. Sloppy functions get the global `this` for
+/// `undefined` and `null` and a wrapper object for other primitives. `code` is the code of the
+/// called function.
+pub fn bind_call_this(
+    rt: &mut Runtime,
+    env: JSValue,
+    this_argument: JSValue,
+    code: &CodeBlock,
+) -> Result<(), JSValue> {
+    fn record(cell: &mut Handle<Cell>) -> &mut FunctionRecord {
+        match &mut cell.unchecked_environment_mut().record {
+            EnvironmentRecord::Function(record) => record,
+            _ => unreachable!("bind_call_this requires function environment"),
+        }
+    }
+    if record(&mut env.as_cell()).this_binding_status == ThisBindingStatus::Lexical {
+        return Ok(());
+    }
+    let scope = HandleScope::new(rt);
+    let env = scope.root(env);
+    let this = if code.strict {
+        this_argument
+    } else if this_argument.is_undefined_or_null() {
+        resolve_this_binding(rt, rt.global_environment)?
+    } else {
+        rt.to_object(this_argument)?
+    };
+    // ToObject allocates, so the record is fetched again from the rooted environment.
+    record(&mut env.get().as_cell()).bind_this_value(rt, this)?;
+    rt.write_barrier(env.get(), this);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::error::JSError;
+    use crate::runtime::vtable::*;
+
+    fn has_binding(rt: &mut Runtime, env: JSValue, name: &str) -> bool {
+        env.as_cell()
+            .unchecked_environment_mut()
+            .has_binding(rt, name)
+    }
+
+    fn thrown<T>(rt: &mut Runtime, result: Result<T, JSValue>) -> JSError {
+        JSError::from_value(rt, result.err().unwrap())
+    }
+
+    #[test]
+    fn delete_unqualified_names() {
+        let mut rt = Runtime::new();
+        let (sloppy, strict) = (
+            CodeBlock::new("", "main.js", false),
+            CodeBlock::new("", "main.js", true),
+        );
+        let global_env = rt.global_environment;
+        let env = new_declarative_environment(&mut rt, Some(global_env), 0);
+        let mut cell = env.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        environment
+            .create_mutable_binding(&mut rt, "declared", false)
+            .ok();
+        environment
+            .create_mutable_binding(&mut rt, "evaluated", true)
+            .ok();
+
+        assert_eq!(
+            delete_variable(&mut rt, env, "declared", &sloppy).ok(),
+            Some(false)
+        );
+        assert!(has_binding(&mut rt, env, "declared"));
+        assert_eq!(
+            delete_variable(&mut rt, env, "evaluated", &sloppy).ok(),
+            Some(true)
+        );
+        assert!(!has_binding(&mut rt, env, "evaluated"));
+        assert_eq!(
+            delete_variable(&mut rt, env, "missing", &sloppy).ok(),
+            Some(true)
+        );
+
+        // x = 1; delete x
+        put_variable(&mut rt, env, "x", JSValue::new_int(1), &sloppy)
+            .ok()
+            .unwrap();
+        assert_eq!(delete_variable(&mut rt, env, "x", &sloppy).ok(), Some(true));
+        assert!(!has_binding(&mut rt, global_env, "x"));
+
+        let result = delete_variable(&mut rt, env, "declared", &strict);
+        let error = thrown(&mut rt, result);
+        assert_eq!(error.name, "SyntaxError");
+        assert_eq!(
+            error.message,
+            "Delete of an unqualified identifier in strict mode."
+        );
+    }
+
+    #[test]
+    fn call_this_follows_code_strictness() {
+        let mut rt = Runtime::new();
+        let (sloppy, strict) = (
+            CodeBlock::new("f", "main.js", false),
+            CodeBlock::new("f", "main.js", true),
+        );
+        let undefined = JSValue::undefined();
+        let this_of = |rt: &mut Runtime, this: JSValue, code: &CodeBlock| {
+            let global_env = rt.global_environment;
+            let env = new_function_environment(
+                rt,
+                Some(global_env),
+                0,
+                undefined,
+                undefined,
+                undefined,
+                false,
+            );
+            bind_call_this(rt, env, this, code).ok().unwrap();
+            resolve_this_binding(rt, env).ok().unwrap()
+        };
+        // f.call(undefined), f.call(1)
+        assert!(this_of(&mut rt, undefined, &strict).is_undefined());
+        assert!(this_of(&mut rt, undefined, &sloppy) == rt.global);
+        let one = JSValue::new_int(1);
+        assert_eq!(this_of(&mut rt, one, &strict).as_int32(), 1);
+        assert!(this_of(&mut rt, one, &sloppy).is_object());
+    }
+
+    fn slot(record: &DeclarativeRecord, slot: u32) -> JSValue {
+        record.slots[slot as usize]
+    }
+
+    #[test]
+    fn declarative_slots() {
+        let mut record = DeclarativeRecord::new(3);
+        assert_eq!(record.slots.len(), 3);
+        assert!(slot(&record, 0).is_empty());
+        let x = record.create_mutable_binding("x", false);
+        let y = record.create_lexical_binding("y");
+        assert_eq!((x, y), (0, 1));
+        assert_eq!(record.slots.len(), 3);
+        assert!(slot(&record, x).is_undefined());
+        assert!(slot(&record, y).is_empty());
+        assert!(slot(&record, 2).is_empty());
+        // Scopes extended by sloppy eval grow past the preallocated slots.
+        record.create_mutable_binding("z", true);
+        let w = record.create_mutable_binding("w", true);
+        assert_eq!(w, 3);
+        assert_eq!(record.slots.len(), 4);
+    }
+
+    fn function_environment(rt: &mut Runtime, parent: JSValue, home_object: JSValue) -> JSValue {
+        let undefined = JSValue::undefined();
+        new_function_environment(
+            rt,
+            Some(parent),
+            0,
+            undefined,
+            home_object,
+            undefined,
+            false,
+        )
+    }
+
+    #[test]
+    fn with_object_records() {
+        let mut rt = Runtime::new();
+        let strict = CodeBlock::new("", "main.js", true);
+        let object = rt.new_object();
+        object_put(&mut rt, object, "p", JSValue::new_int(1), true)
+            .ok()
+            .unwrap();
+        let global_env = rt.global_environment;
+        // with (object) { ... }
+        let env = new_object_environment(&mut rt, Some(global_env), object, true);
+        assert!(resolve_binding(&mut rt, env, "p") == env);
+        assert!(resolve_binding(&mut rt, env, "q").is_undefined());
+        let value = get_variable(&mut rt, env, "p", &strict).ok().unwrap();
+        assert_eq!(value.as_int32(), 1);
+        put_variable(&mut rt, env, "p", JSValue::new_int(2), &strict)
+            .ok()
+            .unwrap();
+        let value = object_get(&mut rt, object, "p").ok().unwrap();
+        assert_eq!(value.as_int32(), 2);
+        let mut cell = env.as_cell();
+        assert!(cell.unchecked_environment_mut().with_base_object() == object);
+        let mut cell = global_env.as_cell();
+        assert!(cell
+            .unchecked_environment_mut()
+            .with_base_object()
+            .is_undefined());
+
+        // The property can disappear between resolving and assigning.
+        let mut cell = env.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        assert_eq!(environment.delete_binding(&mut rt, "p").ok(), Some(true));
+        let result = environment.set_mutable_binding(&mut rt, "p", JSValue::new_int(3), true);
+        let error = thrown(&mut rt, result);
+        assert_eq!(error.name, "ReferenceError");
+        assert_eq!(error.message, "p is not defined");
+        let mut cell = env.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        let value = environment
+            .get_binding_value(&mut rt, "p", false)
+            .ok()
+            .unwrap();
+        assert!(value.is_undefined());
+        environment
+            .set_mutable_binding(&mut rt, "p", JSValue::new_int(3), false)
+            .ok()
+            .unwrap();
+        let value = object_get(&mut rt, object, "p").ok().unwrap();
+        assert_eq!(value.as_int32(), 3);
+    }
+
+    #[test]
+    fn global_var_and_lexical_conflicts() {
+        let mut rt = Runtime::new();
+        let (strict, sloppy) = (
+            CodeBlock::new("", "main.js", true),
+            CodeBlock::new("", "main.js", false),
+        );
+        let global = rt.global;
+        global_declaration_instantiation(&mut rt, &["a"], &[("b", true), ("c", false)])
+            .ok()
+            .unwrap();
+        let desc = object_get_own_property(&mut rt, global, "a").unwrap();
+        assert_eq!(desc.configurable, Some(false));
+        assert!(!object_has_own_property(&mut rt, global, "b"));
+        let global_env = rt.global_environment;
+        assert!(has_binding(&mut rt, global_env, "b"));
+
+        type Script = (&'static [&'static str], &'static [(&'static str, bool)]);
+        let conflicts: [Script; 5] = [
+            (&[], &[("a", true)]),
+            (&["b"], &[]),
+            (&[], &[("c", true)]),
+            (&["d"], &[("d", true)]),
+            (&[], &[("Infinity", false)]),
+        ];
+        let infinity = JSProperty::new()
+            .value(JSValue::new_double(f64::INFINITY))
+            .writable(false)
+            .enumerable(false)
+            .configurable(false);
+        object_define_own_property(&mut rt, global, "Infinity", infinity, true)
+            .ok()
+            .unwrap();
+        for (var_names, lexical_names) in conflicts.iter() {
+            let result = global_declaration_instantiation(&mut rt, var_names, lexical_names);
+            let error = thrown(&mut rt, result);
+            assert_eq!(error.name, "SyntaxError");
+            assert!(error.message.ends_with("has already been declared"));
+        }
+        let global_env = rt.global_environment;
+        assert!(!has_binding(&mut rt, global_env, "d"));
+
+        // x = 1; let x = 2 in a later script shadows the global object property.
+        let global_env = rt.global_environment;
+        put_variable(&mut rt, global_env, "x", JSValue::new_int(1), &sloppy)
+            .ok()
+            .unwrap();
+        global_declaration_instantiation(&mut rt, &[], &[("x", true)])
+            .ok()
+            .unwrap();
+        let mut cell = rt.global_environment.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        environment.initialize_binding(&mut rt, "x", JSValue::new_int(2));
+        let value = get_variable(&mut rt, global_env, "x", &strict)
+            .ok()
+            .unwrap();
+        assert_eq!(value.as_int32(), 2);
+        let global = rt.global;
+        let value = object_get(&mut rt, global, "x").ok().unwrap();
+        assert_eq!(value.as_int32(), 1);
+    }
+
+    #[test]
+    fn this_and_super_binding_status() {
+        let mut rt = Runtime::new();
+        let global_env = rt.global_environment;
+        let this = resolve_this_binding(&mut rt, global_env).ok().unwrap();
+        assert!(this == rt.global);
+
+        // class B extends A { constructor() { () => this; super(); } }
+        let home = rt.new_object();
+        let env = function_environment(&mut rt, global_env, home);
+        let undefined = JSValue::undefined();
+        let arrow =
+            new_function_environment(&mut rt, Some(env), 0, undefined, undefined, undefined, true);
+        let result = resolve_this_binding(&mut rt, arrow);
+        let error = thrown(&mut rt, result);
+        assert_eq!(error.name, "ReferenceError");
+        assert_eq!(
+            error.message,
+            "Must call super constructor in derived class before accessing 'this'"
+        );
+        let this = rt.new_object();
+        let mut cell = env.as_cell();
+        match &mut cell.unchecked_environment_mut().record {
+            EnvironmentRecord::Function(record) => {
+                record.bind_this_value(&mut rt, this).ok().unwrap();
+                let result = record.bind_this_value(&mut rt, this);
+                let error = thrown(&mut rt, result);
+                assert_eq!(error.message, "Super constructor may only be called once");
+            }
+            _ => unreachable!(),
+        }
+        let resolved = resolve_this_binding(&mut rt, arrow).ok().unwrap();
+        assert!(resolved == this);
+        assert!(get_this_environment(arrow) == env);
+        let base = resolve_super_base(&mut rt, arrow).ok().unwrap();
+        assert!(base == rt.object);
+
+        // Plain functions have `this` but no `super`.
+        let plain = function_environment(&mut rt, global_env, JSValue::undefined());
+        let mut cell = plain.as_cell();
+        assert!(cell.unchecked_environment_mut().has_this_binding());
+        assert!(!cell.unchecked_environment_mut().has_super_binding());
+        let result = resolve_super_base(&mut rt, plain);
+        let error = thrown(&mut rt, result);
+        assert_eq!(error.name, "SyntaxError");
+        assert_eq!(error.message, "'super' keyword unexpected here");
+    }
+
+    #[test]
+    fn temporal_dead_zone() {
+        let mut rt = Runtime::new();
+        let (sloppy, strict) = (
+            CodeBlock::new("", "main.js", false),
+            CodeBlock::new("", "main.js", true),
+        );
+        let global_env = rt.global_environment;
+        let env = new_declarative_environment(&mut rt, Some(global_env), 0);
+        // { x; let x = 1; }
+        let mut cell = env.as_cell();
+        let slot = cell
+            .unchecked_environment_mut()
+            .create_lexical_binding("x", true);
+        let msg = "Cannot access 'x' before initialization";
+        for code in [&sloppy, &strict].iter() {
+            let result = get_variable(&mut rt, env, "x", code);
+            let error = thrown(&mut rt, result);
+            assert_eq!(error.name, "ReferenceError");
+            assert_eq!(error.message, msg);
+            let result = put_variable(&mut rt, env, "x", JSValue::new_int(2), code);
+            let error = thrown(&mut rt, result);
+            assert_eq!(error.name, "ReferenceError");
+            assert_eq!(error.message, msg);
+        }
+        let mut cell = env.as_cell();
+        let value = cell.unchecked_environment_mut().get_slot(slot);
+        let result = check_tdz(&mut rt, value, "x");
+        assert_eq!(thrown(&mut rt, result).message, msg);
+
+        let mut cell = env.as_cell();
+        cell.unchecked_environment_mut()
+            .initialize_binding(&mut rt, "x", JSValue::new_int(1));
+        let value = get_variable(&mut rt, env, "x", &strict).ok().unwrap();
+        assert_eq!(value.as_int32(), 1);
+        put_variable(&mut rt, env, "x", JSValue::new_int(2), &strict)
+            .ok()
+            .unwrap();
+        let mut cell = env.as_cell();
+        let value = cell.unchecked_environment_mut().get_slot(slot);
+        assert_eq!(check_tdz(&mut rt, value, "x").ok().unwrap().as_int32(), 2);
+    }
+
+    #[test]
+    fn const_assignment() {
+        let mut rt = Runtime::new();
+        let (sloppy, strict) = (
+            CodeBlock::new("", "main.js", false),
+            CodeBlock::new("", "main.js", true),
+        );
+        let global_env = rt.global_environment;
+        let env = new_declarative_environment(&mut rt, Some(global_env), 0);
+        // const c = 1; c = 2
+        let mut cell = env.as_cell();
+        let environment = cell.unchecked_environment_mut();
+        environment.create_lexical_binding("c", false);
+        environment.initialize_binding(&mut rt, "c", JSValue::new_int(1));
+        for code in [&sloppy, &strict].iter() {
+            let result = put_variable(&mut rt, env, "c", JSValue::new_int(2), code);
+            let error = thrown(&mut rt, result);
+            assert_eq!(error.name, "TypeError");
+            assert_eq!(error.message, "Assignment to constant variable 'c'");
+        }
+        let value = get_variable(&mut rt, env, "c", &strict).ok().unwrap();
+        assert_eq!(value.as_int32(), 1);
+
+        // The name of a named function expression is immutable, but only strict code throws.
+        let mut record = DeclarativeRecord::new(1);
+        record.create_immutable_binding("f", false);
+        record.initialize_binding("f", JSValue::new_int(1));
+        assert!(record
+            .set_mutable_binding(&mut rt, "f", JSValue::new_int(2), false)
+            .is_ok());
+        assert_eq!(
+            record
+                .get_binding_value(&mut rt, "f")
+                .ok()
+                .unwrap()
+                .as_int32(),
+            1
+        );
+        let result = record.set_mutable_binding(&mut rt, "f", JSValue::new_int(2), true);
+        assert_eq!(thrown(&mut rt, result).name, "TypeError");
+
+        // Assigning a const still in its TDZ is a ReferenceError.
+        let mut record = DeclarativeRecord::new(1);
+        record.create_immutable_binding("d", true);
+        assert!(!record.is_initialized("d"));
+        let result = record.set_mutable_binding(&mut rt, "d", JSValue::new_int(2), false);
+        assert_eq!(thrown(&mut rt, result).name, "ReferenceError");
+    }
+}
@@ -0,0 +1,482 @@
+//! Error objects and stack traces.
+//!
+//! Error objects get `stack` property when they are created: the interpreter keeps a [CallFrame]
+//! for every active function in `Runtime::frames`, creating an error walks these frames and maps
+//! their `pc` to source positions using CodeBlock expression info.
+//!
+//! [JSError] is the Rust side view of a thrown value, embedders get it for uncaught exceptions.
+use super::cell::*;
+use super::jsobject::*;
+use super::jsproperty::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+use crate::bytecode::codeblock::CodeBlock;
+use cgc::api::{Finalizer, Traceable, Tracer};
+use std::fmt;
+use std::rc::Rc;
+
+/// Interpreter frame of a function or script being executed.
+pub struct CallFrame {
+    pub code_block: Rc<CodeBlock>,
+    /// Offset of the instruction being executed, for callers it is the call instruction.
+    pub pc: u32,
+}
+
+impl Traceable for CallFrame {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        (*self.code_block).trace_with(tracer);
+    }
+}
+
+impl Finalizer for CallFrame {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    Error,
+    EvalError,
+    RangeError,
+    ReferenceError,
+    SyntaxError,
+    TypeError,
+    URIError,
+}
+
+impl ErrorKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Error => "Error",
+            ErrorKind::EvalError => "EvalError",
+            ErrorKind::RangeError => "RangeError",
+            ErrorKind::ReferenceError => "ReferenceError",
+            ErrorKind::SyntaxError => "SyntaxError",
+            ErrorKind::TypeError => "TypeError",
+            ErrorKind::URIError => "URIError",
+        }
+    }
+}
+
+/// One line of a stack trace.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StackFrame {
+    /// Empty for top level code.
+    pub function_name: String,
+    pub source_url: String,
+    /// 0 when the CodeBlock has no expression info for the frame's `pc`.
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = if self.function_name.is_empty() {
+            "<anonymous>"
+        } else {
+            &self.function_name
+        };
+        write!(
+            f,
+            "at {} ({}:{}:{})",
+            name, self.source_url, self.line, self.column
+        )
+    }
+}
+
+/// Walks interpreter frames from the innermost one.
+pub fn capture_stack_trace(rt: &Runtime) -> Vec<StackFrame> {
+    rt.frames
+        .iter()
+        .rev()
+        .map(|frame| {
+            let position = frame
+                .code_block
+                .line_column_for(frame.pc)
+                .unwrap_or_default();
+            StackFrame {
+                function_name: frame.code_block.name.clone(),
+                source_url: frame.code_block.source_url.clone(),
+                line: position.line,
+                column: position.column,
+            }
+        })
+        .collect()
+}
+
+fn format_stack(header: &str, frames: &[StackFrame]) -> String {
+    let mut stack = header.to_owned();
+    for frame in frames {
+        stack.push_str("\n    ");
+        stack.push_str(&frame.to_string());
+    }
+    stack
+}
+
+fn hidden(value: JSValue) -> JSProperty {
+    JSProperty::new()
+        .value(value)
+        .writable(true)
+        .enumerable(false)
+        .configurable(true)
+}
+
+/// Creates `%Error.prototype%` or one of `%NativeError.prototype%` objects.
+pub(crate) fn new_error_prototype(rt: &mut Runtime, kind: ErrorKind, proto: JSValue) -> JSValue {
+    let scope = HandleScope::new(rt);
+    let proto = scope.root(proto);
+    let name = scope.root(rt.new_string(kind.name()));
+    let message = rt.new_string("");
+    let mut obj = JSObject::new(JSObjectKind::Normal, Some(proto.get()), kind.name());
+    obj._write("name", hidden(name.get()));
+    obj._write("message", hidden(message));
+    rt.allocate(Cell::Object(Box::new(obj)))
+}
+
+/// Creates error object the way `new Error(message, { cause })` does and captures its stack.
+pub fn new_error_object(
+    rt: &mut Runtime,
+    kind: ErrorKind,
+    message: Option<&str>,
+    cause: Option<JSValue>,
+) -> JSValue {
+    let proto = rt.error_prototype(kind);
+    new_error_object_with_proto(rt, kind, proto, message, cause)
+}
+
+/// Like [`new_error_object`], but the new error inherits from `proto` instead of the built-in
+/// prototype of `kind`.
+fn new_error_object_with_proto(
+    rt: &mut Runtime,
+    kind: ErrorKind,
+    proto: JSValue,
+    message: Option<&str>,
+    cause: Option<JSValue>,
+) -> JSValue {
+    let frames = capture_stack_trace(rt);
+    let header = match message {
+        Some(message) if !message.is_empty() => format!("{}: {}", kind.name(), message),
+        _ => kind.name().to_owned(),
+    };
+    let stack = format_stack(&header, &frames);
+    let scope = HandleScope::new(rt);
+    let proto = scope.root(proto);
+    let cause = cause.map(|cause| scope.root(cause));
+    let stack = scope.root(rt.new_string(&stack));
+    let message = message.map(|message| rt.new_string(message));
+    let mut obj = JSObject::new(JSObjectKind::Error(frames), Some(proto.get()), "Error");
+    if let Some(message) = message {
+        obj._write("message", hidden(message));
+    }
+    if let Some(cause) = cause {
+        obj._write("cause", hidden(cause.get()));
+    }
+    obj._write("stack", hidden(stack.get()));
+    rt.allocate(Cell::Object(Box::new(obj)))
+}
+
+/// Thrown JS value converted to Rust error.
+#[derive(Clone, Debug)]
+pub struct JSError {
+    pub name: String,
+    pub message: String,
+    /// Innermost frame first, empty if thrown value is not an error object.
+    pub stack: Vec<StackFrame>,
+}
+
+fn as_string(value: JSValue) -> Option<String> {
+    if value.is_string() {
+        if let Cell::String(s) = &*value.as_cell() {
+            return Some(s.to_string());
+        }
+    }
+    None
+}
+
+fn string_property(rt: &mut Runtime, object: JSValue, name: &str) -> Option<String> {
+    let mut cell = object.as_cell();
+    let get_property = cell.unchecked_object_mut().class_object.get_property;
+    as_string(get_property(rt, object, name)?.value?)
+}
+
+impl JSError {
+    /// Converts thrown `value`. Primitives like `throw 42` are named "Uncaught" and their message
+    /// is the primitive converted to string.
+    pub fn from_value(rt: &mut Runtime, value: JSValue) -> Self {
+        if !value.is_object() {
+            // ToString of primitives has no side effects and can't throw.
+            let message = value.to_string(rt).unwrap_or_default();
+            return Self {
+                name: String::from("Uncaught"),
+                message,
+                stack: vec![],
+            };
+        }
+        let name = string_property(rt, value, "name").unwrap_or_else(|| "Error".to_owned());
+        let message = string_property(rt, value, "message").unwrap_or_default();
+        let stack = match &value.as_cell().unchecked_object_mut().kind {
+            JSObjectKind::Error(frames) => frames.clone(),
+            _ => vec![],
+        };
+        Self {
+            name,
+            message,
+            stack,
+        }
+    }
+
+    fn top(&self) -> Option<&StackFrame> {
+        self.stack.first()
+    }
+
+    /// Name of function the error was thrown from.
+    pub fn function_name(&self) -> Option<&str> {
+        self.top().map(|frame| frame.function_name.as_str())
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.top().map(|frame| frame.source_url.as_str())
+    }
+
+    pub fn line(&self) -> Option<u32> {
+        self.top().map(|frame| frame.line)
+    }
+
+    pub fn column(&self) -> Option<u32> {
+        self.top().map(|frame| frame.column)
+    }
+}
+
+impl fmt::Display for JSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = if self.message.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}: {}", self.name, self.message)
+        };
+        f.write_str(&format_stack(&header, &self.stack))
+    }
+}
+
+impl std::error::Error for JSError {}
+
+/// [NativeError ( message [ , options ] )](https://tc39.es/ecma262/#sec-nativeerror-constructors),
+/// creates a new error whether it is called with `new` or not. When called with `new`, the error
+/// inherits from the prototype [`Runtime::construct`] picked for `this` from `newTarget`.
+fn error_constructor(
+    kind: ErrorKind,
+) -> impl Fn(&mut Runtime, JSValue, &[JSValue]) -> Result<JSValue, JSValue> {
+    move |rt, this, args| {
+        let proto = if rt.new_target().is_object() && this.is_object() {
+            this.as_cell()
+                .unchecked_object_mut()
+                .prototype
+                .unwrap_or_else(|| rt.error_prototype(kind))
+        } else {
+            rt.error_prototype(kind)
+        };
+        let scope = HandleScope::new(rt);
+        let proto = scope.root(proto);
+        let options = scope.root(args.get(1).copied().unwrap_or_else(JSValue::undefined));
+        let message = match args.first() {
+            Some(message) if !message.is_undefined() => {
+                let message = message.to_js_string(rt)?;
+                Some(message.as_cell().unchecked_string().to_string())
+            }
+            _ => None,
+        };
+        let mut cause = None;
+        let options = options.get();
+        if options.is_object() {
+            let vtable = options.as_cell().unchecked_object_mut().class_object;
+            if (vtable.has_property)(rt, options, "cause") {
+                cause = Some((vtable.get)(rt, options, "cause")?);
+            }
+        }
+        Ok(new_error_object_with_proto(
+            rt,
+            kind,
+            proto.get(),
+            message.as_deref(),
+            cause,
+        ))
+    }
+}
+
+pub(crate) fn install(rt: &mut Runtime) {
+    let proto = rt.error;
+    let error =
+        rt.define_native_constructor("Error", 1, proto, error_constructor(ErrorKind::Error));
+    let error = Persistent::new(rt, error);
+    let kinds = [
+        ErrorKind::EvalError,
+        ErrorKind::RangeError,
+        ErrorKind::ReferenceError,
+        ErrorKind::SyntaxError,
+        ErrorKind::TypeError,
+        ErrorKind::URIError,
+    ];
+    for kind in kinds.iter() {
+        let proto = rt.error_prototype(*kind);
+        let constructor =
+            rt.define_native_constructor(kind.name(), 1, proto, error_constructor(*kind));
+        let set_prototype_of = constructor
+            .as_cell()
+            .unchecked_object_mut()
+            .class_object
+            .set_prototype_of;
+        set_prototype_of(rt, constructor, error.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::expression_info::SourcePosition;
+    use crate::runtime::vtable::*;
+    use crate::runtime::JSBigInt;
+
+    fn string(value: JSValue) -> String {
+        as_string(value).unwrap()
+    }
+
+    fn code_block(name: &str, offset: u32, line: u32, column: u32) -> Rc<CodeBlock> {
+        let mut code_block = CodeBlock::new(name, "main.js", false);
+        let (start, end) = (
+            SourcePosition::new(line, column),
+            SourcePosition::new(line, column + 4),
+        );
+        code_block.expression_info.add(offset, start, end);
+        Rc::new(code_block)
+    }
+
+    #[test]
+    fn primitive_throws() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let text = scope.root(rt.new_string("oops"));
+        let big = scope.root(rt.new_bigint(JSBigInt::from_i64(-10)));
+        let cases = [
+            (JSValue::new_int(42), "42"),
+            (JSValue::new_double(1.5), "1.5"),
+            (JSValue::new_double(-0.0), "0"),
+            (JSValue::true_(), "true"),
+            (JSValue::undefined(), "undefined"),
+            (JSValue::null(), "null"),
+            (text.get(), "oops"),
+            (big.get(), "-10"),
+        ];
+        for (value, message) in cases.iter() {
+            let error = JSError::from_value(&mut rt, *value);
+            assert_eq!(error.name, "Uncaught");
+            assert_eq!(error.message, *message);
+            assert!(error.stack.is_empty());
+            assert_eq!(error.to_string(), format!("Uncaught: {}", message));
+        }
+    }
+
+    #[test]
+    fn stack_traces() {
+        let mut rt = Runtime::new();
+        rt.frames.push(CallFrame {
+            code_block: code_block("", 0, 10, 1),
+            pc: 3,
+        });
+        rt.frames.push(CallFrame {
+            code_block: code_block("f", 4, 3, 5),
+            pc: 6,
+        });
+        // Frames without expression info for `pc` have no position.
+        rt.frames.push(CallFrame {
+            code_block: code_block("g", 8, 1, 1),
+            pc: 2,
+        });
+        let frames = capture_stack_trace(&rt);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].to_string(), "at g (main.js:0:0)");
+        assert_eq!(frames[2].to_string(), "at <anonymous> (main.js:10:1)");
+        rt.frames.pop();
+
+        let error = new_error_object(&mut rt, ErrorKind::TypeError, Some("bad"), None);
+        let expected = "TypeError: bad\n    at f (main.js:3:5)\n    at <anonymous> (main.js:10:1)";
+        let stack = object_get(&mut rt, error, "stack").ok().unwrap();
+        assert_eq!(string(stack), expected);
+        let error = JSError::from_value(&mut rt, error);
+        assert_eq!(error.name, "TypeError");
+        assert_eq!(error.message, "bad");
+        assert_eq!(error.function_name(), Some("f"));
+        assert_eq!(error.file(), Some("main.js"));
+        assert_eq!(error.line(), Some(3));
+        assert_eq!(error.column(), Some(5));
+        assert_eq!(error.to_string(), expected);
+
+        rt.frames.clear();
+        let error = new_error_object(&mut rt, ErrorKind::RangeError, None, None);
+        let stack = object_get(&mut rt, error, "stack").ok().unwrap();
+        assert_eq!(string(stack), "RangeError");
+        let error = JSError::from_value(&mut rt, error);
+        assert_eq!(error.message, "");
+        assert_eq!(error.line(), None);
+        assert_eq!(error.to_string(), "RangeError");
+    }
+
+    #[test]
+    fn error_cause() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let constructor = error_constructor(ErrorKind::Error);
+        let message = scope.root(rt.new_string("outer"));
+        let cause = scope.root(rt.new_object());
+        let options = scope.root(rt.new_object());
+        object_put(&mut rt, options.get(), "cause", cause.get(), true)
+            .ok()
+            .unwrap();
+
+        // new Error("outer", { cause })
+        let args = [message.get(), options.get()];
+        let error = constructor(&mut rt, JSValue::undefined(), &args)
+            .ok()
+            .unwrap();
+        let error = scope.root(error);
+        let desc = object_get_own_property(&mut rt, error.get(), "cause").unwrap();
+        assert!(desc.value == Some(cause.get()));
+        assert_eq!(desc.enumerable, Some(false));
+
+        // Options without `cause` create no property, an explicit undefined one does.
+        let empty = rt.new_object();
+        let error = constructor(&mut rt, JSValue::undefined(), &[message.get(), empty])
+            .ok()
+            .unwrap();
+        assert!(!object_has_own_property(&mut rt, error, "cause"));
+        let undefined = JSValue::undefined();
+        object_put(&mut rt, options.get(), "cause", undefined, true)
+            .ok()
+            .unwrap();
+        let args = [message.get(), options.get()];
+        let error = constructor(&mut rt, undefined, &args).ok().unwrap();
+        assert!(object_has_own_property(&mut rt, error, "cause"));
+
+        let error = new_error_object(&mut rt, ErrorKind::Error, None, Some(JSValue::new_int(1)));
+        let cause = object_get(&mut rt, error, "cause").ok().unwrap();
+        assert_eq!(cause.as_int32(), 1);
+    }
+
+    #[test]
+    fn new_target_prototype() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let global = rt.global;
+        let type_error = scope.root(object_get(&mut rt, global, "TypeError").ok().unwrap());
+        // Reflect.construct(TypeError, [], Sub)
+        let proto = scope.root(rt.new_object());
+        let sub = rt.define_native_constructor("Sub", 0, proto.get(), |_, this, _| Ok(this));
+        let sub = scope.root(sub);
+        let error = rt
+            .construct(type_error.get(), &[], Some(sub.get()))
+            .ok()
+            .unwrap();
+        assert!(error.as_cell().unchecked_object_mut().prototype == Some(proto.get()));
+        // Calls without `new` keep the built-in prototype.
+        let error = rt.execute(type_error.get(), JSValue::undefined(), &[]);
+        let error = error.ok().unwrap();
+        assert!(error.as_cell().unchecked_object_mut().prototype == Some(rt.type_error));
+    }
+}
@@ -0,0 +1,300 @@
+//! Runtime support for `eval` code.
+//!
+//! Before eval code runs, the caller sets up environments with [new_eval_environments] and declares
+//! hoisted names with [eval_declaration_instantiation]. There is no parser or compiler yet, so
+//! nothing in the tree runs eval code; this module only covers the environments it runs in.
+//!
+//! - Direct eval (`eval(src)` where `eval` resolves to the builtin) runs in the caller's environment
+//!   chain. In sloppy mode `var` and function declarations are hoisted into the caller's function
+//!   (or global) environment and stay deletable, in strict mode they go to a fresh variable scope
+//!   that is discarded after eval returns, like the body of a function called in place.
+//! - Indirect eval runs in the global environment as if it was a top level script.
+//!
+//! Eval code is strict when it starts with `"use strict"` or when it is a direct eval called from
+//! strict code: its [CodeBlock] is created by [CodeBlock::for_source] with the caller's strictness
+//! for direct eval and `false` for indirect eval. [dynamic_function_source] builds the source text
+//! of a `Function` constructor call, which is strict only if the body has its own directive.
+use super::environment::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+use crate::bytecode::codeblock::CodeBlock;
+
+/// Environments eval code runs in.
+#[derive(Copy, Clone)]
+pub struct EvalEnvironments {
+    /// Environment receiving `var` and function declarations.
+    pub var_env: JSValue,
+    /// Environment receiving `let`, `const` and `class` declarations, the scope code runs in.
+    pub lex_env: JSValue,
+}
+
+/// Creates environments for eval code.
+///
+/// `caller_env` is lexical environment of the caller for direct eval and `None` for indirect eval.
+/// `code` is the eval code itself (see module docs). Strict eval code gets a new variable
+/// environment with the lexical environment as its child.
+pub fn new_eval_environments(
+    rt: &mut Runtime,
+    caller_env: Option<JSValue>,
+    code: &CodeBlock,
+) -> EvalEnvironments {
+    let parent = caller_env.unwrap_or(rt.global_environment);
+    if code.strict {
+        let scope = HandleScope::new(rt);
+        let var_env = scope.root(new_declarative_environment(rt, Some(parent), 0));
+        let lex_env = new_declarative_environment(rt, Some(var_env.get()), 0);
+        return EvalEnvironments {
+            var_env: var_env.get(),
+            lex_env,
+        };
+    }
+    let lex_env = new_declarative_environment(rt, Some(parent), 0);
+    // The allocation may have moved the caller's environment, its new location is the parent.
+    let parent = get_parent_environment(lex_env).unwrap();
+    EvalEnvironments {
+        var_env: get_var_environment(parent),
+        lex_env,
+    }
+}
+
+/// Source text of a `Function` constructor call for `params` and `body`, as built by
+/// [CreateDynamicFunction](https://tc39.es/ecma262/#sec-createdynamicfunction).
+pub fn dynamic_function_source(params: &[String], body: &str) -> String {
+    format!(
+        "function anonymous({}\n) {{\n{}\n}}",
+        params.join(","),
+        body
+    )
+}
+
+/// [EvalDeclarationInstantiation](https://tc39.es/ecma262/#sec-evaldeclarationinstantiation)
+///
+/// `var_names` are `var` and function declaration names of eval code, `lexical_names` are `let`/`class`
+/// (`true`) and `const` (`false`) declarations. Throws SyntaxError when sloppy eval tries to
+/// hoist a `var` over a lexical declaration with the same name.
+pub fn eval_declaration_instantiation(
+    rt: &mut Runtime,
+    envs: EvalEnvironments,
+    var_names: &[&str],
+    lexical_names: &[(&str, bool)],
+    code: &CodeBlock,
+) -> Result<(), JSValue> {
+    if !code.strict {
+        // `var` would be hoisted through every scope between eval and the variable environment.
+        let mut current = get_parent_environment(envs.lex_env);
+        while let Some(env) = current {
+            let mut cell = env.as_cell();
+            let environment = cell.unchecked_environment_mut();
+            let lexical = match &environment.record {
+                EnvironmentRecord::Declarative(record) => Some(record),
+                EnvironmentRecord::Function(record) if env != envs.var_env => {
+                    Some(&record.declarative)
+                }
+                EnvironmentRecord::Global(record) => Some(&record.declarative),
+                _ => None,
+            };
+            if let Some(record) = lexical {
+                for name in var_names {
+                    if record.has_binding(name) {
+                        let msg = format!("Identifier '{}' has already been declared", name);
+                        return Err(rt.new_syntax_error(&msg));
+                    }
+                }
+            }
+            if env == envs.var_env {
+                break;
+            }
+            current = environment.parent;
+        }
+    }
+
+    let scope = HandleScope::new(rt);
+    let lex_env = scope.root(envs.lex_env);
+    let mut cell = envs.var_env.as_cell();
+    let var_env = cell.unchecked_environment_mut();
+    for name in var_names {
+        if !var_env.has_binding(rt, name) {
+            var_env.create_mutable_binding(rt, name, true)?;
+        }
+    }
+
+    let mut cell = lex_env.get().as_cell();
+    let lex_env = cell.unchecked_environment_mut();
+    for (name, mutable) in lexical_names {
+        lex_env.create_lexical_binding(name, *mutable);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::error::JSError;
+    use crate::runtime::vtable::object_has_own_property;
+
+    fn function_environment(rt: &mut Runtime) -> JSValue {
+        let global_env = rt.global_environment;
+        let undefined = JSValue::undefined();
+        new_function_environment(
+            rt,
+            Some(global_env),
+            0,
+            undefined,
+            undefined,
+            undefined,
+            false,
+        )
+    }
+
+    fn has_binding(rt: &mut Runtime, env: JSValue, name: &str) -> bool {
+        env.as_cell()
+            .unchecked_environment_mut()
+            .has_binding(rt, name)
+    }
+
+    fn syntax_error(rt: &mut Runtime, result: Result<(), JSValue>) -> JSError {
+        let error = JSError::from_value(rt, result.err().unwrap());
+        assert_eq!(error.name, "SyntaxError");
+        error
+    }
+
+    #[test]
+    fn sloppy_eval_hoists_vars_into_caller() {
+        let mut rt = Runtime::new();
+        let sloppy = CodeBlock::new("", "main.js", false);
+        let scope = HandleScope::new(&rt);
+        let func_env = scope.root(function_environment(&mut rt));
+        let block = scope.root(new_declarative_environment(
+            &mut rt,
+            Some(func_env.get()),
+            0,
+        ));
+        let envs = new_eval_environments(&mut rt, Some(block.get()), &sloppy);
+        assert!(envs.var_env == func_env.get());
+        assert!(get_parent_environment(envs.lex_env) == Some(block.get()));
+
+        eval_declaration_instantiation(&mut rt, envs, &["x"], &[("y", true)], &sloppy)
+            .ok()
+            .unwrap();
+        assert!(has_binding(&mut rt, func_env.get(), "x"));
+        assert!(!has_binding(&mut rt, block.get(), "x"));
+        assert!(has_binding(&mut rt, envs.lex_env, "y"));
+        assert!(!has_binding(&mut rt, func_env.get(), "y"));
+        // Variables introduced by sloppy eval can be deleted.
+        let deleted = func_env
+            .get()
+            .as_cell()
+            .unchecked_environment_mut()
+            .delete_binding(&mut rt, "x");
+        assert_eq!(deleted.ok(), Some(true));
+        assert!(!has_binding(&mut rt, func_env.get(), "x"));
+    }
+
+    #[test]
+    fn indirect_eval_vars_become_global_properties() {
+        let mut rt = Runtime::new();
+        let sloppy = CodeBlock::new("", "main.js", false);
+        let envs = new_eval_environments(&mut rt, None, &sloppy);
+        assert!(envs.var_env == rt.global_environment);
+        eval_declaration_instantiation(&mut rt, envs, &["z"], &[("w", false)], &sloppy)
+            .ok()
+            .unwrap();
+        let global = rt.global;
+        assert!(object_has_own_property(&mut rt, global, "z"));
+        assert!(!object_has_own_property(&mut rt, global, "w"));
+        let global_env = rt.global_environment;
+        assert!(!has_binding(&mut rt, global_env, "w"));
+    }
+
+    #[test]
+    fn strict_eval_is_isolated() {
+        let mut rt = Runtime::new();
+        let strict = CodeBlock::new("", "main.js", true);
+        rt.gc_stress = true;
+        let scope = HandleScope::new(&rt);
+        let func_env = scope.root(function_environment(&mut rt));
+        let envs = new_eval_environments(&mut rt, Some(func_env.get()), &strict);
+        assert!(envs.var_env != envs.lex_env);
+        assert!(envs.var_env != func_env.get());
+        assert!(get_parent_environment(envs.lex_env) == Some(envs.var_env));
+        assert!(get_parent_environment(envs.var_env) == Some(func_env.get()));
+
+        let var_env = scope.root(envs.var_env);
+        eval_declaration_instantiation(&mut rt, envs, &["x"], &[("y", true)], &strict)
+            .ok()
+            .unwrap();
+        assert!(has_binding(&mut rt, var_env.get(), "x"));
+        assert!(!has_binding(&mut rt, func_env.get(), "x"));
+        assert!(!has_binding(&mut rt, var_env.get(), "y"));
+
+        // Indirect strict eval does not leak vars to the global object either.
+        let envs = new_eval_environments(&mut rt, None, &strict);
+        eval_declaration_instantiation(&mut rt, envs, &["leaked"], &[], &strict)
+            .ok()
+            .unwrap();
+        let global = rt.global;
+        assert!(!object_has_own_property(&mut rt, global, "leaked"));
+    }
+
+    #[test]
+    fn var_conflicting_with_lexical_declaration() {
+        let mut rt = Runtime::new();
+        let (sloppy, strict) = (
+            CodeBlock::new("", "main.js", false),
+            CodeBlock::new("", "main.js", true),
+        );
+        let scope = HandleScope::new(&rt);
+        // function f() { { let x; eval("var x"); } }
+        let func_env = scope.root(function_environment(&mut rt));
+        let block = scope.root(new_declarative_environment(
+            &mut rt,
+            Some(func_env.get()),
+            0,
+        ));
+        block
+            .get()
+            .as_cell()
+            .unchecked_environment_mut()
+            .create_lexical_binding("x", true);
+        let envs = new_eval_environments(&mut rt, Some(block.get()), &sloppy);
+        let result = eval_declaration_instantiation(&mut rt, envs, &["x"], &[], &sloppy);
+        let error = syntax_error(&mut rt, result);
+        assert_eq!(error.message, "Identifier 'x' has already been declared");
+        assert!(!has_binding(&mut rt, func_env.get(), "x"));
+
+        // Strict eval keeps its vars to itself, there is nothing to conflict with.
+        let envs = new_eval_environments(&mut rt, Some(block.get()), &strict);
+        assert!(eval_declaration_instantiation(&mut rt, envs, &["x"], &[], &strict).is_ok());
+
+        // let g = 1; (0, eval)("var g")
+        let global_env = rt.global_environment;
+        global_env
+            .as_cell()
+            .unchecked_environment_mut()
+            .create_lexical_binding("g", true);
+        let envs = new_eval_environments(&mut rt, None, &sloppy);
+        let result = eval_declaration_instantiation(&mut rt, envs, &["g"], &[], &sloppy);
+        let error = syntax_error(&mut rt, result);
+        assert_eq!(error.message, "Identifier 'g' has already been declared");
+
+        // Lexical declarations of eval code itself shadow the outer ones.
+        let envs = new_eval_environments(&mut rt, Some(block.get()), &sloppy);
+        assert!(
+            eval_declaration_instantiation(&mut rt, envs, &[], &[("x", true)], &sloppy).is_ok()
+        );
+    }
+
+    #[test]
+    fn function_constructor_source() {
+        let params = vec!["a".to_owned(), "b = 1".to_owned()];
+        assert_eq!(
+            dynamic_function_source(&params, "return a + b"),
+            "function anonymous(a,b = 1\n) {\nreturn a + b\n}"
+        );
+        assert_eq!(
+            dynamic_function_source(&[], ""),
+            "function anonymous(\n) {\n\n}"
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Native function objects.
+//!
+//! A native function wraps a Rust closure taking the runtime, `this` and the arguments. Closures
+//! are not traced, values they need across collections must be kept in [Persistent] handles.
+//! Constructors additionally receive a fresh object created from `newTarget.prototype` as `this`,
+//! and can read [Runtime::new_target] to tell `new` calls from ordinary ones.
+//!
+//! `this` and the arguments are rooted until the call returns, but the closure gets copies of
+//! them, like other locals they have to be rooted in a [HandleScope] when used after allocating.
+//!
+//! [Persistent]: super::rooting::Persistent
+use super::cell::*;
+use super::jsobject::*;
+use super::jsproperty::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+use std::rc::Rc;
+
+pub type NativeFn = dyn Fn(&mut Runtime, JSValue, &[JSValue]) -> Result<JSValue, JSValue>;
+
+pub struct NativeFunction {
+    pub function: Rc<NativeFn>,
+    /// Can be called with `new`.
+    pub constructor: bool,
+}
+
+/// Attributes of `name`, `length` and `prototype` of builtin functions.
+fn read_only(value: JSValue, configurable: bool) -> JSProperty {
+    JSProperty::new()
+        .value(value)
+        .writable(false)
+        .enumerable(false)
+        .configurable(configurable)
+}
+
+/// Attributes of builtin methods and constructors.
+fn builtin(value: JSValue) -> JSProperty {
+    JSProperty::new()
+        .value(value)
+        .writable(true)
+        .enumerable(false)
+        .configurable(true)
+}
+
+/// Returns the closure of native function `value` and whether it is a constructor. The closure is
+/// shared, so it stays valid when a collection moves the function object.
+fn native_function(value: JSValue) -> Option<(Rc<NativeFn>, bool)> {
+    if !value.is_object() {
+        return None;
+    }
+    match &value.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::NativeFunction(function) => {
+            Some((function.function.clone(), function.constructor))
+        }
+        _ => None,
+    }
+}
+
+fn define_property(rt: &mut Runtime, object: JSValue, name: &str, desc: JSProperty) {
+    let define = object
+        .as_cell()
+        .unchecked_object_mut()
+        .class_object
+        .define_own_property;
+    define(rt, object, name, desc, false).ok();
+}
+
+/// Roots `args`, the prototype lookup and the allocation of `this` in [Runtime::construct] can
+/// run a collection before the closure is called.
+fn root_arguments<'s>(scope: &'s HandleScope, args: &[JSValue]) -> Vec<Rooted<'s, JSValue>> {
+    args.iter().map(|arg| scope.root(*arg)).collect()
+}
+
+impl JSValue {
+    /// [IsConstructor](https://tc39.es/ecma262/#sec-isconstructor)
+    pub fn is_constructor(&self) -> bool {
+        matches!(native_function(*self), Some((_, true)))
+    }
+}
+
+impl Runtime {
+    /// Creates a function object calling `f`, with `name` and `length` properties.
+    pub fn new_native_function(
+        &mut self,
+        name: &str,
+        length: u32,
+        constructor: bool,
+        f: impl Fn(&mut Runtime, JSValue, &[JSValue]) -> Result<JSValue, JSValue> + 'static,
+    ) -> JSValue {
+        let name = self.new_string(name);
+        let kind = JSObjectKind::NativeFunction(NativeFunction {
+            function: Rc::new(f),
+            constructor,
+        });
+        let mut object = JSObject::new(kind, Some(self.function), "Function");
+        object._write("length", read_only(JSValue::new_int(length as i32), true));
+        object._write("name", read_only(name, true));
+        self.allocate(Cell::Object(Box::new(object)))
+    }
+
+    /// Defines property `name` of `object` the way builtin methods are defined.
+    pub fn define_builtin(&mut self, object: JSValue, name: &str, value: JSValue) {
+        define_property(self, object, name, builtin(value));
+    }
+
+    /// Defines native method `name` of `object`, returns the function.
+    pub fn define_native_method(
+        &mut self,
+        object: JSValue,
+        name: &str,
+        length: u32,
+        f: impl Fn(&mut Runtime, JSValue, &[JSValue]) -> Result<JSValue, JSValue> + 'static,
+    ) -> JSValue {
+        let scope = HandleScope::new(self);
+        let object = scope.root(object);
+        let function = self.new_native_function(name, length, false, f);
+        self.define_builtin(object.get(), name, function);
+        function
+    }
+
+    /// Creates constructor `name` with `prototype` as its `prototype` property and defines it on
+    /// the global object.
+    pub fn define_native_constructor(
+        &mut self,
+        name: &str,
+        length: u32,
+        prototype: JSValue,
+        f: impl Fn(&mut Runtime, JSValue, &[JSValue]) -> Result<JSValue, JSValue> + 'static,
+    ) -> JSValue {
+        let scope = HandleScope::new(self);
+        let prototype = scope.root(prototype);
+        let constructor = scope.root(self.new_native_function(name, length, true, f));
+        let desc = read_only(prototype.get(), false);
+        define_property(self, constructor.get(), "prototype", desc);
+        self.define_builtin(prototype.get(), "constructor", constructor.get());
+        let global = self.global;
+        self.define_builtin(global, name, constructor.get());
+        constructor.get()
+    }
+
+    /// `new.target` of the innermost native call, undefined unless it was called with `new`.
+    pub fn new_target(&self) -> JSValue {
+        self.new_targets
+            .last()
+            .copied()
+            .unwrap_or_else(JSValue::undefined)
+    }
+
+    /// Calls `f` with the current values of `this` and `args`, which are rooted by the caller.
+    fn call_native(
+        &mut self,
+        f: Rc<NativeFn>,
+        this: JSValue,
+        args: &[Rooted<JSValue>],
+        new_target: JSValue,
+    ) -> Result<JSValue, JSValue> {
+        let args: Vec<JSValue> = args.iter().map(Rooted::get).collect();
+        self.new_targets.push(new_target);
+        let result = f(self, this, &args);
+        self.new_targets.pop();
+        let result = result?;
+        self.check_out_of_memory()?;
+        Ok(result)
+    }
+
+    /// [Call](https://tc39.es/ecma262/#sec-call), throws TypeError if `function` is not callable.
+    pub fn execute(
+        &mut self,
+        function: JSValue,
+        this: JSValue,
+        args: &[JSValue],
+    ) -> Result<JSValue, JSValue> {
+        let f = match native_function(function) {
+            Some((f, _)) => f,
+            None => return Err(self.new_type_error("Value is not a function")),
+        };
+        let scope = HandleScope::new(self);
+        let this = scope.root(this);
+        let args = root_arguments(&scope, args);
+        self.call_native(f, this.get(), &args, JSValue::undefined())
+    }
+
+    /// [Construct](https://tc39.es/ecma262/#sec-construct), `new_target` defaults to `function`.
+    /// Returns the object the constructor returned, or else the object passed as `this`.
+    pub fn construct(
+        &mut self,
+        function: JSValue,
+        args: &[JSValue],
+        new_target: Option<JSValue>,
+    ) -> Result<JSValue, JSValue> {
+        let f = match native_function(function) {
+            Some((f, true)) => f,
+            _ => return Err(self.new_type_error("Value is not a constructor")),
+        };
+        let new_target = new_target.unwrap_or(function);
+        let scope = HandleScope::new(self);
+        let new_target = scope.root(new_target);
+        let args = root_arguments(&scope, args);
+        let get = new_target
+            .get()
+            .as_cell()
+            .unchecked_object_mut()
+            .class_object
+            .get;
+        let proto = get(self, new_target.get(), "prototype")?;
+        let proto = if proto.is_object() {
+            proto
+        } else {
+            self.object
+        };
+        let object = JSObject::new(JSObjectKind::Normal, Some(proto), "Object");
+        let this = scope.root(self.allocate(Cell::Object(Box::new(object))));
+        let result = self.call_native(f, this.get(), &args, new_target.get())?;
+        if result.is_object() {
+            Ok(result)
+        } else {
+            Ok(this.get())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::operations::type_of;
+    use std::cell::Cell as Counter;
+
+    fn get(rt: &mut Runtime, object: JSValue, name: &str) -> JSValue {
+        let get = object.as_cell().unchecked_object_mut().class_object.get;
+        get(rt, object, name).ok().unwrap()
+    }
+
+    fn string(value: JSValue) -> String {
+        value.as_cell().unchecked_string().to_string()
+    }
+
+    #[test]
+    fn closures_are_callable() {
+        let mut rt = Runtime::new();
+        let calls = Rc::new(Counter::new(0));
+        let counter = calls.clone();
+        let add = rt.new_native_function("add", 2, false, move |_, _, args| {
+            counter.set(counter.get() + 1);
+            Ok(JSValue::new_double(
+                args[0].as_number() + args[1].as_number(),
+            ))
+        });
+        let add = Persistent::new(&rt, add);
+        assert!(add.get().is_callable());
+        assert!(!add.get().is_constructor());
+        assert_eq!(type_of(add.get()), "function");
+
+        let args = [JSValue::new_int(1), JSValue::new_double(2.5)];
+        let sum = rt.execute(add.get(), JSValue::undefined(), &args);
+        assert_eq!(sum.ok().unwrap().as_number(), 3.5);
+        assert_eq!(calls.get(), 1);
+        let name = get(&mut rt, add.get(), "name");
+        assert_eq!(string(name), "add");
+        assert_eq!(get(&mut rt, add.get(), "length").as_number(), 2.0);
+        let proto = add.get().as_cell().unchecked_object_mut().prototype;
+        assert!(proto == Some(rt.function));
+
+        assert!(rt.construct(add.get(), &[], None).is_err());
+        let object = rt.new_object();
+        assert!(rt.execute(object, JSValue::undefined(), &[]).is_err());
+    }
+
+    #[test]
+    fn constructors() {
+        let mut rt = Runtime::new();
+        let proto = rt.new_object();
+        let point = rt.define_native_constructor("Point", 1, proto, |rt, this, args| {
+            if rt.new_target().is_undefined() {
+                return Err(rt.new_type_error("Constructor Point requires 'new'"));
+            }
+            rt.define_builtin(this, "x", args[0]);
+            Ok(JSValue::undefined())
+        });
+        let point = Persistent::new(&rt, point);
+        assert!(point.get().is_constructor());
+        let global = rt.global;
+        assert!(get(&mut rt, global, "Point") == point.get());
+
+        let object = rt.construct(point.get(), &[JSValue::new_int(7)], None);
+        let object = object.ok().unwrap();
+        assert_eq!(get(&mut rt, object, "x").as_number(), 7.0);
+        let proto = get(&mut rt, point.get(), "prototype");
+        assert!(object.as_cell().unchecked_object_mut().prototype == Some(proto));
+        assert!(get(&mut rt, proto, "constructor") == point.get());
+        let result = rt.execute(point.get(), object, &[JSValue::new_int(1)]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn arguments_are_rooted() {
+        let mut rt = Runtime::new();
+        let proto = rt.new_object();
+        let boxed = rt.define_native_constructor("Box", 1, proto, |rt, this, args| {
+            rt.assert_live(args[0]);
+            rt.define_builtin(this, "v", args[0]);
+            Ok(JSValue::undefined())
+        });
+        let boxed = Persistent::new(&rt, boxed);
+        let first = rt.new_native_function("first", 1, false, |rt, _, args| {
+            rt.assert_live(args[0]);
+            Ok(args[0])
+        });
+        let first = Persistent::new(&rt, first);
+        rt.gc_stress = true;
+        let value = rt.new_string("boxed");
+        let object = rt.construct(boxed.get(), &[value], None).ok().unwrap();
+        let v = get(&mut rt, object, "v");
+        rt.assert_live(v);
+        assert_eq!(string(v), "boxed");
+
+        let value = rt.new_object();
+        let result = rt.execute(first.get(), value, &[value]).ok().unwrap();
+        rt.assert_live(result);
+        assert!(result.is_object());
+    }
+
+    #[test]
+    fn builtins_are_installed() {
+        let mut rt = Runtime::new();
+        let number = rt.number;
+        let to_fixed = get(&mut rt, number, "toFixed");
+        let to_fixed = Persistent::new(&rt, to_fixed);
+        let args = [JSValue::new_int(2)];
+        let fixed = rt.execute(to_fixed.get(), JSValue::new_double(1.005), &args);
+        assert_eq!(string(fixed.ok().unwrap()), "1.00");
+        assert_eq!(string(get(&mut rt, to_fixed.get(), "name")), "toFixed");
+
+        let global = rt.global;
+        let type_error = get(&mut rt, global, "TypeError");
+        let type_error = Persistent::new(&rt, type_error);
+        let message = rt.new_string("bad");
+        let error = rt.construct(type_error.get(), &[message], None);
+        let error = error.ok().unwrap();
+        assert_eq!(string(get(&mut rt, error, "message")), "bad");
+        let proto = get(&mut rt, type_error.get(), "prototype");
+        assert!(error.as_cell().unchecked_object_mut().prototype == Some(proto));
+        let error = get(&mut rt, global, "Error");
+        let parent = type_error.get().as_cell().unchecked_object_mut().prototype;
+        assert!(parent == Some(error));
+
+        let bigint = get(&mut rt, global, "BigInt");
+        assert!(bigint.is_callable() && !bigint.is_constructor());
+        assert!(rt.construct(bigint, &[], None).is_err());
+    }
+}
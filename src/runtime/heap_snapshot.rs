@@ -0,0 +1,553 @@
+//! Heap snapshots in the Chrome DevTools `.heapsnapshot` format.
+//!
+//! The snapshot is a graph of nodes and edges stored as flat arrays of numbers, with node and
+//! edge names interned in a string table. Node 0 is a synthetic root whose edges lead to groups of
+//! GC roots: the runtime intrinsics, active frames, pinned atoms, jobs and handles. Objects are named by
+//! their class, strings by their contents and edges by property keys or binding names, so leaks
+//! can be found with the DevTools memory panel.
+use super::cell::*;
+use super::environment::*;
+use super::heap_stats::cell_size;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::rooting::{cell_address, for_each_child, traced_cell};
+use super::Runtime;
+use cgc::api::{Handle, Traceable, Tracer};
+use fxhash::FxHashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const NODE_FIELDS: [&str; 6] = [
+    "type",
+    "name",
+    "id",
+    "self_size",
+    "edge_count",
+    "trace_node_id",
+];
+const NODE_TYPES: [&str; 14] = [
+    "hidden",
+    "array",
+    "string",
+    "object",
+    "code",
+    "closure",
+    "regexp",
+    "number",
+    "native",
+    "synthetic",
+    "concatenated string",
+    "sliced string",
+    "symbol",
+    "bigint",
+];
+const EDGE_TYPES: [&str; 7] = [
+    "context", "element", "property", "internal", "hidden", "shortcut", "weak",
+];
+
+// Indices into `NODE_TYPES`.
+const NODE_HIDDEN: usize = 0;
+const NODE_STRING: usize = 2;
+const NODE_OBJECT: usize = 3;
+const NODE_CLOSURE: usize = 5;
+const NODE_SYNTHETIC: usize = 9;
+const NODE_CONCATENATED_STRING: usize = 10;
+const NODE_SLICED_STRING: usize = 11;
+const NODE_BIGINT: usize = 13;
+
+// Indices into `EDGE_TYPES`.
+const EDGE_CONTEXT: usize = 0;
+const EDGE_ELEMENT: usize = 1;
+const EDGE_PROPERTY: usize = 2;
+const EDGE_INTERNAL: usize = 3;
+const EDGE_HIDDEN: usize = 4;
+const EDGE_WEAK: usize = 6;
+
+/// Longest string contents used as a node name.
+const MAX_NAME_LENGTH: usize = 256;
+
+struct Node {
+    kind: usize,
+    name: usize,
+    self_size: usize,
+    edges: Vec<Edge>,
+}
+
+struct Edge {
+    kind: usize,
+    /// Index of the name in the string table, or element index for element and hidden edges.
+    name_or_index: usize,
+    to: usize,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    nodes: Vec<Node>,
+    strings: Vec<String>,
+    string_ids: FxHashMap<String, usize>,
+    /// Node of every cell, keyed by cell address.
+    cells: FxHashMap<usize, usize>,
+    /// Cells whose edges are not added yet.
+    pending: Vec<(usize, Handle<Cell>)>,
+}
+
+fn is_heap_value(value: JSValue) -> bool {
+    value.is_cell() && !value.is_empty()
+}
+
+fn string_name(cell: &Cell) -> String {
+    let units: Vec<u16> = cell
+        .unchecked_string()
+        .code_units()
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Returns the `name` property of function `object`, or its class if the name is not a string.
+fn function_name(object: &JSObject) -> String {
+    match object.property.get("name").and_then(|name| name.value) {
+        Some(name) if name.is_string() => string_name(&name.as_cell()),
+        _ => object.class.clone(),
+    }
+}
+
+impl Snapshot {
+    fn string(&mut self, s: &str) -> usize {
+        if let Some(id) = self.string_ids.get(s) {
+            return *id;
+        }
+        let id = self.strings.len();
+        self.strings.push(s.to_string());
+        self.string_ids.insert(s.to_string(), id);
+        id
+    }
+
+    fn add_node(&mut self, kind: usize, name: &str, self_size: usize) -> usize {
+        let name = self.string(name);
+        self.nodes.push(Node {
+            kind,
+            name,
+            self_size,
+            edges: vec![],
+        });
+        self.nodes.len() - 1
+    }
+
+    fn node_for_cell(&mut self, cell: Handle<Cell>) -> usize {
+        let address = cell_address(cell);
+        if let Some(node) = self.cells.get(&address) {
+            return *node;
+        }
+        let (kind, name) = match cell.get() {
+            Cell::String(string) if string.is_rope() => {
+                (NODE_CONCATENATED_STRING, string_name(cell.get()))
+            }
+            Cell::String(string) if string.is_substring() => {
+                (NODE_SLICED_STRING, string_name(cell.get()))
+            }
+            Cell::String(_) => (NODE_STRING, string_name(cell.get())),
+            Cell::BigInt(_) => (NODE_BIGINT, "bigint".to_string()),
+            Cell::Object(object) => match &object.kind {
+                JSObjectKind::NativeFunction(_) => (NODE_CLOSURE, function_name(object)),
+                _ => (NODE_OBJECT, object.class.clone()),
+            },
+            Cell::Environment(_) => (NODE_HIDDEN, "system / Context".to_string()),
+        };
+        let node = self.add_node(kind, &name, cell_size(cell.get()));
+        self.cells.insert(address, node);
+        self.pending.push((node, cell));
+        node
+    }
+
+    fn add_edge(&mut self, from: usize, kind: usize, name: &str, to: Handle<Cell>) {
+        let name_or_index = self.string(name);
+        let to = self.node_for_cell(to);
+        self.nodes[from].edges.push(Edge {
+            kind,
+            name_or_index,
+            to,
+        });
+    }
+
+    fn add_value_edge(&mut self, from: usize, kind: usize, name: &str, value: JSValue) {
+        if is_heap_value(value) {
+            self.add_edge(from, kind, name, value.as_cell());
+        }
+    }
+
+    fn add_indexed_edge(&mut self, from: usize, kind: usize, index: usize, to: Handle<Cell>) {
+        let to = self.node_for_cell(to);
+        self.nodes[from].edges.push(Edge {
+            kind,
+            name_or_index: index,
+            to,
+        });
+    }
+
+    /// Adds a synthetic node with hidden edges to every cell `trace` reports.
+    fn add_root_group(&mut self, from: usize, name: &str, trace: impl FnOnce(&mut Tracer)) {
+        let group = self.add_node(NODE_SYNTHETIC, name, 0);
+        let index = self.nodes[from].edges.len();
+        self.nodes[from].edges.push(Edge {
+            kind: EDGE_ELEMENT,
+            name_or_index: index,
+            to: group,
+        });
+        let mut tracer = Tracer::default();
+        trace(&mut tracer);
+        let mut index = 0;
+        tracer.for_each(|pointer| {
+            self.add_indexed_edge(group, EDGE_HIDDEN, index, traced_cell(pointer));
+            index += 1;
+        });
+    }
+
+    fn add_object_edges(&mut self, node: usize, object: &JSObject) {
+        for (key, property) in object.property.iter() {
+            if let Some(value) = property.value {
+                self.add_value_edge(node, EDGE_PROPERTY, key, value);
+            }
+            if let Some(getter) = property.getter {
+                self.add_value_edge(node, EDGE_PROPERTY, &format!("get {}", key), getter);
+            }
+            if let Some(setter) = property.setter {
+                self.add_value_edge(node, EDGE_PROPERTY, &format!("set {}", key), setter);
+            }
+        }
+        for (key, value) in object.internal.iter() {
+            self.add_value_edge(node, EDGE_INTERNAL, key, *value);
+        }
+        if let Some(prototype) = object.prototype {
+            self.add_value_edge(node, EDGE_PROPERTY, "__proto__", prototype);
+        }
+        match &object.kind {
+            JSObjectKind::Array(elements) => {
+                for (index, element) in elements.iter().enumerate() {
+                    if is_heap_value(*element) {
+                        self.add_indexed_edge(node, EDGE_ELEMENT, index, element.as_cell());
+                    }
+                }
+            }
+            JSObjectKind::Arguments(map) => {
+                self.add_value_edge(node, EDGE_INTERNAL, "environment", map.env);
+            }
+            JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) => {
+                for (key, value) in map.iter() {
+                    self.add_value_edge(node, EDGE_WEAK, "key", key);
+                    self.add_value_edge(node, EDGE_INTERNAL, "value", value);
+                }
+            }
+            JSObjectKind::WeakRef(target) => {
+                self.add_value_edge(node, EDGE_WEAK, "target", *target);
+            }
+            JSObjectKind::FinalizationRegistry(registry) => {
+                self.add_value_edge(node, EDGE_INTERNAL, "cleanup", registry.cleanup);
+                for cell in registry.cells.iter() {
+                    self.add_value_edge(node, EDGE_WEAK, "target", cell.target);
+                    self.add_value_edge(node, EDGE_INTERNAL, "holdings", cell.holdings);
+                    if let Some(token) = cell.unregister_token {
+                        self.add_value_edge(node, EDGE_WEAK, "unregister_token", token);
+                    }
+                }
+            }
+            JSObjectKind::Host(data) => {
+                let mut tracer = Tracer::default();
+                data.trace_with(&mut tracer);
+                let mut index = 0;
+                tracer.for_each(|pointer| {
+                    self.add_indexed_edge(node, EDGE_HIDDEN, index, traced_cell(pointer));
+                    index += 1;
+                });
+            }
+            _ => (),
+        }
+    }
+
+    fn add_declarative_edges(&mut self, node: usize, record: &DeclarativeRecord) {
+        for (name, binding) in record.bindings.iter() {
+            if let Some(value) = record.slots.get(binding.slot as usize) {
+                self.add_value_edge(node, EDGE_CONTEXT, name, *value);
+            }
+        }
+    }
+
+    fn add_environment_edges(&mut self, node: usize, env: &Environment) {
+        if let Some(parent) = env.parent {
+            self.add_value_edge(node, EDGE_INTERNAL, "parent", parent);
+        }
+        match &env.record {
+            EnvironmentRecord::Declarative(record) => self.add_declarative_edges(node, record),
+            EnvironmentRecord::Object(record) => {
+                self.add_value_edge(node, EDGE_INTERNAL, "binding_object", record.binding_object);
+            }
+            EnvironmentRecord::Function(record) => {
+                self.add_declarative_edges(node, &record.declarative);
+                self.add_value_edge(node, EDGE_INTERNAL, "this", record.this_value);
+                self.add_value_edge(node, EDGE_INTERNAL, "function", record.function_object);
+                self.add_value_edge(node, EDGE_INTERNAL, "home_object", record.home_object);
+                self.add_value_edge(node, EDGE_INTERNAL, "new_target", record.new_target);
+            }
+            EnvironmentRecord::Global(record) => {
+                self.add_declarative_edges(node, &record.declarative);
+                let object = record.object.binding_object;
+                self.add_value_edge(node, EDGE_INTERNAL, "binding_object", object);
+                self.add_value_edge(node, EDGE_INTERNAL, "this", record.this_value);
+            }
+        }
+    }
+
+    fn add_cell_edges(&mut self, node: usize, cell: Handle<Cell>) {
+        match cell.get() {
+            Cell::Object(object) => self.add_object_edges(node, object),
+            Cell::Environment(env) => self.add_environment_edges(node, env),
+            Cell::String(string) => {
+                let names: &[&str] = if string.is_rope() {
+                    &["first", "second"]
+                } else {
+                    &["parent"]
+                };
+                let mut children = vec![];
+                for_each_child(cell.get(), |child| children.push(child));
+                for (name, child) in names.iter().zip(children) {
+                    self.add_edge(node, EDGE_INTERNAL, name, child);
+                }
+            }
+            Cell::BigInt(_) => (),
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        let edge_count: usize = self.nodes.iter().map(|node| node.edges.len()).sum();
+        write!(out, "{{\"snapshot\":{{\"meta\":{{\"node_fields\":")?;
+        write_strings(out, &NODE_FIELDS)?;
+        write!(out, ",\"node_types\":[")?;
+        write_strings(out, &NODE_TYPES)?;
+        write!(
+            out,
+            ",\"string\",\"number\",\"number\",\"number\",\"number\"]"
+        )?;
+        write!(
+            out,
+            ",\"edge_fields\":[\"type\",\"name_or_index\",\"to_node\"]"
+        )?;
+        write!(out, ",\"edge_types\":[")?;
+        write_strings(out, &EDGE_TYPES)?;
+        write!(out, ",\"string_or_number\",\"node\"]")?;
+        write!(
+            out,
+            ",\"trace_function_info_fields\":[\"function_id\",\"name\",\"script_name\",\
+             \"script_id\",\"line\",\"column\"]"
+        )?;
+        write!(
+            out,
+            ",\"trace_node_fields\":[\"id\",\"function_info_index\",\"count\",\"size\",\
+             \"children\"]"
+        )?;
+        write!(
+            out,
+            ",\"sample_fields\":[\"timestamp_us\",\"last_assigned_id\"]\
+             ,\"location_fields\":[\"object_index\",\"script_id\",\"line\",\"column\"]}}"
+        )?;
+        write!(
+            out,
+            ",\"node_count\":{},\"edge_count\":{},\"trace_function_count\":0}}",
+            self.nodes.len(),
+            edge_count
+        )?;
+        write!(out, ",\n\"nodes\":[")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ",\n" };
+            // Ids of JavaScript objects are odd in V8 snapshots.
+            write!(
+                out,
+                "{}{},{},{},{},{},0",
+                separator,
+                node.kind,
+                node.name,
+                index * 2 + 1,
+                node.self_size,
+                node.edges.len()
+            )?;
+        }
+        write!(out, "],\n\"edges\":[")?;
+        let edges = self.nodes.iter().flat_map(|node| node.edges.iter());
+        for (index, edge) in edges.enumerate() {
+            let separator = if index == 0 { "" } else { ",\n" };
+            let to = edge.to * NODE_FIELDS.len();
+            write!(
+                out,
+                "{}{},{},{}",
+                separator, edge.kind, edge.name_or_index, to
+            )?;
+        }
+        write!(
+            out,
+            "],\n\"trace_function_infos\":[],\"trace_tree\":[],\"samples\":[]"
+        )?;
+        write!(out, ",\"locations\":[],\n\"strings\":[")?;
+        for (index, s) in self.strings.iter().enumerate() {
+            if index != 0 {
+                writeln!(out, ",")?;
+            }
+            write_json_string(out, s)?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+fn write_strings(out: &mut dyn Write, strings: &[&str]) -> io::Result<()> {
+    write!(out, "[")?;
+    for (index, s) in strings.iter().enumerate() {
+        if index != 0 {
+            write!(out, ",")?;
+        }
+        write_json_string(out, s)?;
+    }
+    write!(out, "]")
+}
+
+fn write_json_string(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+impl Runtime {
+    /// Writes a snapshot of every cell reachable from the roots to `path`, in the format of
+    /// Chrome DevTools heap snapshots. See [heap_snapshot](self) module.
+    pub fn write_heap_snapshot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_heap_snapshot_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Writes a heap snapshot to `out`, see [Runtime::write_heap_snapshot].
+    pub fn write_heap_snapshot_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut snapshot = Snapshot::default();
+        let root = snapshot.add_node(NODE_SYNTHETIC, "", 0);
+        let gc_roots = snapshot.add_node(NODE_SYNTHETIC, "(GC roots)", 0);
+        snapshot.nodes[root].edges.push(Edge {
+            kind: EDGE_ELEMENT,
+            name_or_index: 0,
+            to: gc_roots,
+        });
+        let intrinsics = snapshot.add_node(NODE_SYNTHETIC, "(Runtime)", 0);
+        snapshot.nodes[gc_roots].edges.push(Edge {
+            kind: EDGE_ELEMENT,
+            name_or_index: 0,
+            to: intrinsics,
+        });
+        let fields = [
+            ("global", self.global),
+            ("global_environment", self.global_environment),
+            ("Object.prototype", self.object),
+            ("Number.prototype", self.number),
+            ("BigInt.prototype", self.bigint),
+            ("Boolean.prototype", self.boolean),
+            ("String.prototype", self.string),
+            ("Symbol.prototype", self.symbol),
+            ("Function.prototype", self.function),
+            ("Math", self.math),
+            ("Error.prototype", self.error),
+            ("EvalError.prototype", self.eval_error),
+            ("RangeError.prototype", self.range_error),
+            ("ReferenceError.prototype", self.reference_error),
+            ("SyntaxError.prototype", self.syntax_error),
+            ("TypeError.prototype", self.type_error),
+            ("URIError.prototype", self.uri_error),
+            ("WeakMap.prototype", self.weak_map),
+            ("WeakSet.prototype", self.weak_set),
+            ("WeakRef.prototype", self.weak_ref),
+            ("FinalizationRegistry.prototype", self.finalization_registry),
+            ("%ThrowTypeError%", self.throw_type_error),
+        ];
+        for (name, value) in fields.iter() {
+            snapshot.add_value_edge(intrinsics, EDGE_PROPERTY, name, *value);
+        }
+        // The remembered set is left out, its cells are reachable from the other roots unless
+        // they are garbage waiting for a collection.
+        snapshot.add_root_group(gc_roots, "(Stack roots)", |tracer| {
+            self.frames.trace_with(tracer)
+        });
+        snapshot.add_root_group(gc_roots, "(Atoms)", |tracer| self.atoms.trace_with(tracer));
+        snapshot.add_root_group(gc_roots, "(Jobs)", |tracer| {
+            self.jobs.iter().for_each(|job| job.trace_with(tracer));
+            self.kept_alive.trace_with(tracer);
+        });
+        snapshot.add_root_group(gc_roots, "(Handles)", |tracer| {
+            self.roots.borrow().trace_with(tracer)
+        });
+        while let Some((node, cell)) = snapshot.pending.pop() {
+            snapshot.add_cell_edges(node, cell);
+        }
+        snapshot.write(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::rooting::*;
+    use crate::runtime::vtable::*;
+
+    /// Returns the numbers of JSON array `name` in `snapshot`.
+    fn numbers(snapshot: &str, name: &str) -> Vec<usize> {
+        let start = snapshot.find(&format!("\"{}\":[", name)).unwrap() + name.len() + 4;
+        let end = start + snapshot[start..].find(']').unwrap();
+        snapshot[start..end]
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_graph() {
+        let mut rt = Runtime::new();
+        let leak = rt.new_object_with_kind(JSObjectKind::Normal, "Leak");
+        let leak = Persistent::new(&rt, leak);
+        let value = rt.new_string("leaked \"value\"\n");
+        object_put(&mut rt, leak.get(), "payload", value, true)
+            .ok()
+            .unwrap();
+        let mut out = vec![];
+        rt.write_heap_snapshot_to(&mut out).unwrap();
+        let snapshot = String::from_utf8(out).unwrap();
+
+        let node_count = numbers(&snapshot, "nodes").len() / NODE_FIELDS.len();
+        let edges = numbers(&snapshot, "edges");
+        assert!(snapshot.contains(&format!("\"node_count\":{},", node_count)));
+        let edge_count = format!("\"edge_count\":{},", edges.len() / 3);
+        assert!(snapshot.contains(&edge_count));
+        assert!(edges
+            .chunks(3)
+            .all(|edge| edge[2] % NODE_FIELDS.len() == 0 && edge[2] / 6 < node_count));
+        for name in &["\"Leak\"", "\"payload\"", "\"(Handles)\"", "\"global\""] {
+            assert!(snapshot.contains(name), "{} is missing", name);
+        }
+        assert!(snapshot.contains("\"leaked \\\"value\\\"\\n\""));
+    }
+
+    #[test]
+    fn write_to_file() {
+        let rt = Runtime::new();
+        let path = std::env::temp_dir().join("jsrs-test.heapsnapshot");
+        rt.write_heap_snapshot(&path).unwrap();
+        let snapshot = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(snapshot.starts_with("{\"snapshot\":{\"meta\":"));
+        assert!(snapshot.contains("\"Object.prototype\""));
+    }
+}
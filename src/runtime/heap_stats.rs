@@ -0,0 +1,381 @@
+//! Heap statistics and the heap size limit.
+//!
+//! The collector does not report sizes, so the runtime accounts approximate cell sizes itself:
+//! bytes of cells allocated since the last collection are summed up in [Runtime::allocate], and
+//! every collection recounts the cells that survived it while the collector traces them, see
+//! [count_traced_cell]. Buffers growing after their cell was allocated, such as properties added
+//! to an object, are counted at the next collection.
+//!
+//! With [Runtime::max_heap_size] set, an allocation that would exceed the limit collects garbage
+//! first. If the heap is still too big, [Runtime::try_allocate] returns [OutOfMemory], while
+//! infallible allocations proceed and leave the error pending until native code or the
+//! interpreter reaches [Runtime::check_out_of_memory], which throws it into the script as a
+//! RangeError. Allocations whose size scripts control (concatenation, substrings, strings built
+//! from code units and arrays) are fallible and throw the RangeError right away.
+use super::cell::*;
+use super::environment::*;
+use super::error::StackFrame;
+use super::jsobject::*;
+use super::jsproperty::*;
+use super::jsvalue::*;
+use super::jsweak::FinalizationCell;
+use super::Runtime;
+use cgc::heap::HeapInner;
+use std::cell::RefCell;
+use std::fmt;
+use std::mem::{size_of, size_of_val};
+use std::time::Duration;
+
+/// Allocation that did not fit under [Runtime::max_heap_size] even after a collection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfMemory {
+    /// Approximate size of the allocation in bytes.
+    pub requested: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of memory: allocation of {} bytes exceeds heap limit of {} bytes",
+            self.requested, self.limit
+        )
+    }
+}
+
+impl std::error::Error for OutOfMemory {}
+
+/// Number and approximate size of live cells of one kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CellStatistics {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl CellStatistics {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Heap usage as of the last collection, see [Runtime::heap_statistics].
+#[derive(Clone, Debug, Default)]
+pub struct HeapStatistics {
+    pub strings: CellStatistics,
+    pub bigints: CellStatistics,
+    pub objects: CellStatistics,
+    pub environments: CellStatistics,
+    /// Bytes allocated since the last collection.
+    pub allocated_bytes: usize,
+    pub collections: usize,
+    pub last_pause: Duration,
+    pub max_pause: Duration,
+    pub total_pause: Duration,
+    /// Allocations that failed because of [Runtime::max_heap_size].
+    pub out_of_memory_errors: usize,
+}
+
+impl HeapStatistics {
+    /// Bytes of cells that survived the last collection.
+    pub fn live_bytes(&self) -> usize {
+        self.strings.bytes + self.bigints.bytes + self.objects.bytes + self.environments.bytes
+    }
+
+    /// Upper bound of the current heap size, live bytes plus bytes allocated since.
+    pub fn heap_size(&self) -> usize {
+        self.live_bytes() + self.allocated_bytes
+    }
+
+    pub(crate) fn count_cell(&mut self, cell: &Cell) {
+        let bytes = cell_size(cell);
+        match cell {
+            Cell::String(_) => self.strings.add(bytes),
+            Cell::BigInt(_) => self.bigints.add(bytes),
+            Cell::Object(_) => self.objects.add(bytes),
+            Cell::Environment(_) => self.environments.add(bytes),
+        }
+    }
+
+    /// Replaces live cell counts with `live` counted by a collection that took `pause`.
+    pub(crate) fn record_collection(&mut self, live: HeapStatistics, pause: Duration) {
+        self.strings = live.strings;
+        self.bigints = live.bigints;
+        self.objects = live.objects;
+        self.environments = live.environments;
+        self.allocated_bytes = 0;
+        self.collections += 1;
+        self.last_pause = pause;
+        self.max_pause = self.max_pause.max(pause);
+        self.total_pause += pause;
+    }
+}
+
+thread_local! {
+    /// Live cells counted by the collection running on this thread.
+    static TRACED: RefCell<Option<HeapStatistics>> = const { RefCell::new(None) };
+}
+
+/// Counts `cell` as live if a collection is counting, called when the collector traces a cell.
+/// The collector traces every reachable cell once per pass, old cells included.
+pub(crate) fn count_traced_cell(cell: &Cell) {
+    TRACED.with(|traced| {
+        if let Some(live) = traced.borrow_mut().as_mut() {
+            live.count_cell(cell);
+        }
+    });
+}
+
+/// Starts counting live cells from zero, called when a collection pass starts tracing the roots.
+/// cgc may follow a young generation pass by an old generation one, only the last pass counts.
+pub(crate) fn restart_counting() {
+    TRACED.with(|traced| {
+        if let Some(live) = traced.borrow_mut().as_mut() {
+            *live = HeapStatistics::default();
+        }
+    });
+}
+
+/// Counts cells traced while `collect` runs.
+pub(crate) fn count_live_cells(collect: impl FnOnce()) -> HeapStatistics {
+    TRACED.with(|traced| *traced.borrow_mut() = Some(HeapStatistics::default()));
+    collect();
+    TRACED.with(|traced| traced.borrow_mut().take().unwrap_or_default())
+}
+
+fn entries_size<K, V>(len: usize) -> usize {
+    len * (size_of::<K>() + size_of::<V>())
+}
+
+fn object_size(object: &JSObject) -> usize {
+    let keys: usize = object
+        .property
+        .keys()
+        .chain(object.internal.keys())
+        .map(String::len)
+        .sum();
+    let kind = match &object.kind {
+        JSObjectKind::Array(elements) => elements.capacity() * size_of::<JSValue>(),
+        JSObjectKind::Error(stack) => stack.capacity() * size_of::<StackFrame>(),
+        JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) => {
+            entries_size::<usize, (JSValue, JSValue)>(map.len())
+        }
+        JSObjectKind::FinalizationRegistry(registry) => {
+            registry.cells.capacity() * size_of::<FinalizationCell>()
+        }
+        JSObjectKind::NativeFunction(function) => size_of_val(&*function.function),
+        JSObjectKind::Host(data) => size_of_val(&**data),
+        _ => 0,
+    };
+    keys + kind
+        + entries_size::<String, JSProperty>(object.property.len())
+        + entries_size::<String, JSValue>(object.internal.len())
+        + object.class.len()
+}
+
+fn declarative_size(record: &DeclarativeRecord) -> usize {
+    let keys: usize = record.bindings.keys().map(String::len).sum();
+    keys + entries_size::<String, Binding>(record.bindings.len())
+        + record.slots.capacity() * size_of::<JSValue>()
+}
+
+fn environment_size(env: &Environment) -> usize {
+    match &env.record {
+        EnvironmentRecord::Declarative(record) => declarative_size(record),
+        EnvironmentRecord::Function(record) => declarative_size(&record.declarative),
+        EnvironmentRecord::Global(record) => declarative_size(&record.declarative),
+        EnvironmentRecord::Object(_) => 0,
+    }
+}
+
+/// Approximate size of `cell` in bytes, including the collector header and buffers owned by the
+/// cell.
+pub fn cell_size(cell: &Cell) -> usize {
+    let buffers = match cell {
+        Cell::String(string) => size_of_val(&**string) + string.heap_size(),
+        Cell::BigInt(bigint) => size_of_val(&**bigint) + bigint.heap_size(),
+        Cell::Object(object) => size_of_val(&**object) + object_size(object),
+        Cell::Environment(env) => size_of_val(&**env) + environment_size(env),
+    };
+    size_of::<HeapInner<Cell>>() + buffers
+}
+
+impl Runtime {
+    pub fn heap_statistics(&self) -> HeapStatistics {
+        self.heap_stats.clone()
+    }
+
+    /// Returns true if allocating `bytes` more would exceed [Runtime::max_heap_size].
+    pub(crate) fn exceeds_heap_limit(&self, bytes: usize) -> bool {
+        match self.max_heap_size {
+            Some(limit) => self.heap_stats.heap_size() + bytes > limit,
+            None => false,
+        }
+    }
+
+    fn out_of_memory(&mut self, requested: usize) -> OutOfMemory {
+        self.heap_stats.out_of_memory_errors += 1;
+        OutOfMemory {
+            requested,
+            limit: self.max_heap_size.unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Makes room for a `pending` cell of `bytes` bytes, collecting garbage if the heap limit
+    /// would be exceeded.
+    pub(crate) fn reserve_heap(&mut self, bytes: usize, pending: &Cell) -> Result<(), OutOfMemory> {
+        if self.exceeds_heap_limit(bytes) {
+            self.collect_garbage(Some(pending));
+            if self.exceeds_heap_limit(bytes) {
+                return Err(self.out_of_memory(bytes));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves `cell` into the GC heap unless that exceeds [Runtime::max_heap_size].
+    pub fn try_allocate(&mut self, cell: Cell) -> Result<JSValue, OutOfMemory> {
+        self.reserve_heap(cell_size(&cell), &cell)?;
+        Ok(self.allocate(cell))
+    }
+
+    /// Returns the error of an allocation that exceeded [Runtime::max_heap_size] since the last
+    /// check.
+    pub fn take_out_of_memory(&mut self) -> Option<OutOfMemory> {
+        self.pending_out_of_memory.take()
+    }
+
+    /// Throws RangeError if an allocation exceeded [Runtime::max_heap_size] since the last check.
+    pub fn check_out_of_memory(&mut self) -> Result<(), JSValue> {
+        match self.pending_out_of_memory {
+            Some(_) => Err(self.new_out_of_memory_error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates the RangeError thrown for [OutOfMemory] and clears the pending error. The error
+    /// object is allocated over the limit, so scripts catching it can drop references and go on.
+    pub fn new_out_of_memory_error(&mut self) -> JSValue {
+        let limit = self.max_heap_size.take();
+        let error = self.new_range_error("Out of memory");
+        self.max_heap_size = limit;
+        self.pending_out_of_memory = None;
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::jsstring::JSString;
+    use crate::runtime::rooting::*;
+    use crate::runtime::vtable::*;
+
+    #[test]
+    fn statistics() {
+        let mut rt = Runtime::new();
+        rt.gc();
+        let before = rt.heap_statistics();
+        assert_eq!(before.collections, 1);
+        assert_eq!(before.allocated_bytes, 0);
+        assert!(before.objects.count > 0);
+
+        let string = rt.new_string(&"x".repeat(1000));
+        let string = Persistent::new(&rt, string);
+        let _garbage = rt.new_string(&"y".repeat(1000));
+        let stats = rt.heap_statistics();
+        assert!(stats.allocated_bytes >= 2000);
+        assert_eq!(
+            stats.heap_size(),
+            stats.live_bytes() + stats.allocated_bytes
+        );
+
+        rt.gc();
+        let stats = rt.heap_statistics();
+        assert_eq!(stats.collections, 2);
+        assert_eq!(stats.strings.count, before.strings.count + 1);
+        let grown = stats.strings.bytes - before.strings.bytes;
+        assert!((1000..2000).contains(&grown));
+        assert!(stats.max_pause >= stats.last_pause);
+        assert!(stats.total_pause >= stats.last_pause);
+        assert_eq!(string.get().as_cell().unchecked_string().len(), 1000);
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let mut rt = Runtime::new();
+        rt.gc();
+        let live = rt.heap_statistics().live_bytes();
+        rt.max_heap_size = Some(live + 64 * 1024);
+
+        // Garbage is collected to make room.
+        for _ in 0..200 {
+            rt.try_allocate(Cell::String(Box::new(JSString::new(&"x".repeat(1024)))))
+                .unwrap();
+        }
+        assert!(rt.heap_statistics().collections > 1);
+        assert!(rt.check_out_of_memory().is_ok());
+
+        let array = rt.new_array(vec![]).ok().unwrap();
+        let array = Persistent::new(&rt, array);
+        let mut length = 0;
+        let error = loop {
+            let cell = Cell::String(Box::new(JSString::new(&"x".repeat(1024))));
+            match rt.try_allocate(cell) {
+                Ok(string) => {
+                    array_set_element(&mut rt, array.get(), length, string);
+                    length += 1;
+                }
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(error.limit, live + 64 * 1024);
+        assert_eq!(rt.heap_statistics().out_of_memory_errors, 1);
+
+        // Infallible allocations leave the error pending.
+        rt.new_string(&"x".repeat(1024));
+        let error = rt.check_out_of_memory().err().unwrap();
+        let message = object_get(&mut rt, error, "message").ok().unwrap();
+        assert_eq!(
+            message.as_cell().unchecked_string().to_string(),
+            "Out of memory"
+        );
+        assert!(rt.check_out_of_memory().is_ok());
+
+        // Strings and arrays sized by scripts throw instead of going over the limit.
+        let first = match &array.get().as_cell().unchecked_object_mut().kind {
+            JSObjectKind::Array(elements) => elements[0],
+            _ => unreachable!(),
+        };
+        let first = Persistent::new(&rt, first);
+        rt.gc();
+        rt.max_heap_size = Some(rt.heap_statistics().live_bytes());
+        let range_error = |rt: &mut Runtime, result: Result<JSValue, JSValue>| {
+            let error = result.err().unwrap();
+            let message = object_get(rt, error, "message").ok().unwrap();
+            assert_eq!(
+                message.as_cell().unchecked_string().to_string(),
+                "Out of memory"
+            );
+        };
+        let units = vec!['x' as u16; 1024];
+        let result = rt.try_new_string_from_utf16(&units);
+        range_error(&mut rt, result);
+        let elements = vec![JSValue::undefined(); 1024];
+        let result = rt.new_array(elements);
+        range_error(&mut rt, result);
+        let result = rt.concat_strings(first.get(), first.get());
+        range_error(&mut rt, result);
+        let result = rt.new_substring(first.get(), 0, 10);
+        range_error(&mut rt, result);
+        assert!(rt.check_out_of_memory().is_ok());
+        drop(first);
+        rt.max_heap_size = Some(live + 64 * 1024);
+
+        // Dropping references makes room again.
+        drop(array);
+        rt.try_allocate(Cell::String(Box::new(JSString::new(&"x".repeat(1024)))))
+            .unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+//! Host objects: JavaScript objects carrying Rust data of the embedder.
+//!
+//! Host data is stored in [JSObjectKind::Host] and traced through its [Traceable] impl, so it may
+//! keep JavaScript values alive. Like weak containers, host objects are registered with the
+//! runtime: the marking pass before each collection finds the dead ones and runs their
+//! [Finalizer], and the runtime finalizes the remaining ones when it is dropped. The collector
+//! itself does not finalize every dead cell, so file handles, cursors and buffers must be released
+//! from `finalize` or `Drop`, both run exactly once.
+//!
+//! Values stored into host data after the object was created need [Runtime::write_barrier].
+use super::cell::*;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::rooting::cell_address;
+use super::Runtime;
+use cgc::api::Traceable;
+use fxhash::FxHashSet;
+use std::any::Any;
+
+/// Rust data attached to a host object, implemented for every `Traceable` type.
+pub trait HostObject: Traceable + Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Traceable + Any> HostObject for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl JSObject {
+    /// Returns host data of type `T`, `None` if this is not a host object of that type.
+    pub fn host_data<T: HostObject>(&self) -> Option<&T> {
+        match &self.kind {
+            JSObjectKind::Host(data) => data.as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn host_data_mut<T: HostObject>(&mut self) -> Option<&mut T> {
+        match &mut self.kind {
+            JSObjectKind::Host(data) => data.as_any_mut().downcast_mut(),
+            _ => None,
+        }
+    }
+}
+
+/// Takes host data out of `object` and finalizes it.
+fn finalize_host_object(object: JSValue) {
+    let mut cell = object.as_cell();
+    let kind = &mut cell.unchecked_object_mut().kind;
+    if let JSObjectKind::Host(mut data) = std::mem::replace(kind, JSObjectKind::Normal) {
+        data.finalize();
+    }
+}
+
+impl Runtime {
+    /// Creates an object holding `data`. Host objects must be created here, the runtime finalizes
+    /// only registered ones.
+    pub fn new_host_object<T: HostObject>(
+        &mut self,
+        data: T,
+        prototype: Option<JSValue>,
+        class: &str,
+    ) -> JSValue {
+        let kind = JSObjectKind::Host(Box::new(data));
+        let object = self.allocate(Cell::Object(Box::new(JSObject::new(
+            kind, prototype, class,
+        ))));
+        self.host_objects.push(object);
+        object
+    }
+
+    /// Finalizes host objects that are not `marked` by [Runtime::mark_reachable]. Their cells are
+    /// still valid until the collection that follows, afterwards they are ordinary objects.
+    pub(crate) fn finalize_host_objects(&mut self, marked: &FxHashSet<usize>) {
+        let (live, dead) = std::mem::take(&mut self.host_objects)
+            .into_iter()
+            .partition(|object| marked.contains(&cell_address(object.as_cell())));
+        self.host_objects = live;
+        dead.into_iter().for_each(finalize_host_object);
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        std::mem::take(&mut self.host_objects)
+            .into_iter()
+            .for_each(finalize_host_object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::rooting::*;
+    use cgc::api::{Finalizer, Tracer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Host data logging its finalization, keeps `value` alive.
+    struct Resource {
+        name: &'static str,
+        value: JSValue,
+        log: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl Traceable for Resource {
+        fn trace_with(&self, tracer: &mut Tracer) {
+            self.value.trace_with(tracer);
+        }
+    }
+
+    impl Finalizer for Resource {
+        fn finalize(&mut self) {
+            self.log.borrow_mut().push(self.name);
+        }
+    }
+
+    fn resource(
+        rt: &mut Runtime,
+        name: &'static str,
+        log: &Rc<RefCell<Vec<&'static str>>>,
+    ) -> JSValue {
+        let value = rt.new_string(name);
+        let resource = Resource {
+            name,
+            value,
+            log: log.clone(),
+        };
+        rt.new_host_object(resource, Some(rt.object), "Resource")
+    }
+
+    #[test]
+    fn host_data_is_traced_and_finalized() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut rt = Runtime::new();
+        let kept = resource(&mut rt, "kept", &log);
+        let kept = Persistent::new(&rt, kept);
+        resource(&mut rt, "dropped", &log);
+
+        rt.gc();
+        assert_eq!(*log.borrow(), ["dropped"]);
+        let mut cell = kept.get().as_cell();
+        let data = cell.unchecked_object_mut().host_data::<Resource>().unwrap();
+        rt.assert_live(data.value);
+        assert_eq!(data.value.as_cell().unchecked_string().to_string(), "kept");
+        assert!(cell.unchecked_object_mut().host_data::<String>().is_none());
+
+        rt.gc();
+        assert_eq!(rt.host_objects.len(), 1);
+        drop(rt);
+        assert_eq!(*log.borrow(), ["dropped", "kept"]);
+    }
+}
@@ -0,0 +1,54 @@
+//! Job queue.
+//!
+//! Jobs run after the current script or job finished, the embedder drains the queue with
+//! [Runtime::run_jobs]. FinalizationRegistry cleanup callbacks are the only jobs for now.
+use super::jsvalue::*;
+use super::Runtime;
+use cgc::api::{Finalizer, Traceable, Tracer};
+
+/// Call of `callback` with `arguments` and undefined `this`.
+pub struct Job {
+    pub callback: JSValue,
+    pub arguments: Vec<JSValue>,
+}
+
+impl Traceable for Job {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.callback.trace_with(tracer);
+        self.arguments.trace_with(tracer);
+    }
+}
+
+impl Finalizer for Job {}
+
+impl Runtime {
+    pub fn enqueue_job(&mut self, callback: JSValue, arguments: Vec<JSValue>) {
+        self.jobs.push_back(Job {
+            callback,
+            arguments,
+        });
+    }
+
+    /// Runs jobs until the queue is empty, including jobs enqueued meanwhile. Stops at the first
+    /// job that throws and returns its exception, the remaining jobs stay queued.
+    pub fn run_jobs(&mut self) -> Result<(), JSValue> {
+        while let Some(job) = self.jobs.pop_front() {
+            let result = self.execute(job.callback, JSValue::undefined(), &job.arguments);
+            self.clear_kept_objects();
+            result?;
+        }
+        Ok(())
+    }
+
+    /// [ClearKeptObjects](https://tc39.es/ecma262/#sec-clear-kept-objects), called when a job
+    /// finishes. Embedders running scripts without [Runtime::run_jobs] call it after each script.
+    pub fn clear_kept_objects(&mut self) {
+        self.kept_alive.clear();
+    }
+
+    /// [AddToKeptObjects](https://tc39.es/ecma262/#sec-addtokeptobjects), keeps `value` alive
+    /// until the current job finishes.
+    pub fn add_to_kept_objects(&mut self, value: JSValue) {
+        self.kept_alive.push(value);
+    }
+}
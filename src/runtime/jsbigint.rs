@@ -0,0 +1,862 @@
+//! Arbitrary precision integers for BigInt values.
+//!
+//! A BigInt is stored as sign and magnitude, the magnitude is little endian base 2^32 digits without
+//! leading zeros, so zero has no digits and is never negative. Bitwise operations and `asIntN`/
+//! `asUintN` behave as if values were in infinite two's complement.
+use super::conversions::is_js_whitespace;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Maximum bit length of a BigInt, operations producing longer values fail with
+/// [BigIntError::TooBig].
+pub const MAX_BIGINT_BITS: u64 = 1 << 30;
+
+/// Failure of a BigInt operation, all of these are thrown as RangeError.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BigIntError {
+    DivisionByZero,
+    NegativeExponent,
+    TooBig,
+}
+
+impl BigIntError {
+    pub fn message(self) -> &'static str {
+        match self {
+            BigIntError::DivisionByZero => "Division by zero",
+            BigIntError::NegativeExponent => "Exponent must be non-negative",
+            BigIntError::TooBig => "Maximum BigInt size exceeded",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct JSBigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+    for (i, &x) in a.iter().enumerate() {
+        let sum = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// `a - b`, `a` must not be less than `b`.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        result.push(diff as u32);
+        borrow = (diff < 0) as i64;
+    }
+    debug_assert_eq!(borrow, 0);
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+fn div_rem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut rem = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = (rem << 32) | a[i] as u64;
+        quotient[i] = (cur / divisor as u64) as u32;
+        rem = cur % divisor as u64;
+    }
+    trim(&mut quotient);
+    (quotient, rem as u32)
+}
+
+fn shl_magnitude(a: &[u32], shift: u64) -> Vec<u32> {
+    if a.is_empty() {
+        return vec![];
+    }
+    let (limbs, bits) = ((shift / 32) as usize, (shift % 32) as u32);
+    let mut result = vec![0u32; limbs];
+    if bits == 0 {
+        result.extend_from_slice(a);
+    } else {
+        let mut carry = 0u32;
+        for &x in a {
+            result.push((x << bits) | carry);
+            carry = x >> (32 - bits);
+        }
+        if carry != 0 {
+            result.push(carry);
+        }
+    }
+    result
+}
+
+fn shr_magnitude(a: &[u32], shift: u64) -> Vec<u32> {
+    let limbs = (shift / 32) as usize;
+    if limbs >= a.len() {
+        return vec![];
+    }
+    let bits = (shift % 32) as u32;
+    let a = &a[limbs..];
+    let mut result = Vec::with_capacity(a.len());
+    for i in 0..a.len() {
+        let high = if bits == 0 {
+            0
+        } else {
+            a.get(i + 1).map_or(0, |&x| x << (32 - bits))
+        };
+        result.push((a[i] >> bits) | high);
+    }
+    trim(&mut result);
+    result
+}
+
+/// Long division (Knuth's algorithm D), `b` must not be zero.
+fn div_rem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, rem) = div_rem_small(a, b[0]);
+        let mut rem = vec![rem];
+        trim(&mut rem);
+        return (quotient, rem);
+    }
+    // Normalize so the top digit of the divisor has its high bit set, then quotient digit
+    // estimates are off by at most two.
+    let shift = b.last().unwrap().leading_zeros() as u64;
+    let b = shl_magnitude(b, shift);
+    let mut a = shl_magnitude(a, shift);
+    a.resize(a.len().max(b.len()) + 1, 0);
+    let n = b.len();
+    let m = a.len() - n;
+    let (top, second) = (b[n - 1] as u64, b[n - 2] as u64);
+    let mut quotient = vec![0u32; m];
+    for j in (0..m).rev() {
+        let numerator = ((a[j + n] as u64) << 32) | a[j + n - 1] as u64;
+        let mut qhat = numerator / top;
+        let mut rhat = numerator % top;
+        while qhat >> 32 != 0 || qhat * second > ((rhat << 32) | a[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += top;
+            if rhat >> 32 != 0 {
+                break;
+            }
+        }
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let product = qhat * b[i] as u64 + carry;
+            carry = product >> 32;
+            let t = a[i + j] as i64 - borrow - (product & 0xffff_ffff) as i64;
+            a[i + j] = t as u32;
+            borrow = (t < 0) as i64;
+        }
+        let t = a[j + n] as i64 - borrow - carry as i64;
+        a[j + n] = t as u32;
+        if t < 0 {
+            // Estimate was one too large, add the divisor back.
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = a[i + j] as u64 + b[i] as u64 + carry;
+                a[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            a[j + n] = a[j + n].wrapping_add(carry as u32);
+        }
+        quotient[j] = qhat as u32;
+    }
+    trim(&mut quotient);
+    (quotient, shr_magnitude(&a[..n], shift))
+}
+
+fn digit_value(c: u8, radix: u32) -> Option<u32> {
+    (c as char).to_digit(radix)
+}
+
+impl JSBigInt {
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    fn from_parts(negative: bool, mut digits: Vec<u32>) -> Self {
+        trim(&mut digits);
+        Self {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    pub fn from_u64(x: u64) -> Self {
+        Self::from_parts(false, vec![x as u32, (x >> 32) as u32])
+    }
+
+    pub fn from_i64(x: i64) -> Self {
+        let mut result = Self::from_u64(x.unsigned_abs());
+        result.negative = x < 0;
+        result
+    }
+
+    /// [NumberToBigInt](https://tc39.es/ecma262/#sec-numbertobigint), returns `None` if `x` is not
+    /// an integer.
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() || x.trunc() != x {
+            return None;
+        }
+        if x == 0.0 {
+            return Some(Self::zero());
+        }
+        let bits = x.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mantissa = if exponent == 0 {
+            (bits & ((1 << 52) - 1)) << 1
+        } else {
+            (bits & ((1 << 52) - 1)) | (1 << 52)
+        };
+        // x = mantissa * 2^(exponent - 1075), integral so the shift is exact.
+        let shift = exponent - 1075;
+        let magnitude = if shift >= 0 {
+            shl_magnitude(&Self::from_u64(mantissa).digits, shift as u64)
+        } else {
+            Self::from_u64(mantissa >> -shift).digits
+        };
+        Some(Self::from_parts(x < 0.0, magnitude))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Bytes of the digit buffer.
+    pub fn heap_size(&self) -> usize {
+        self.digits.capacity() * std::mem::size_of::<u32>()
+    }
+
+    /// Number of bits of the magnitude.
+    pub fn bit_length(&self) -> u64 {
+        match self.digits.last() {
+            Some(top) => self.digits.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /// Converts to the nearest double, ties to even.
+    pub fn to_f64(&self) -> f64 {
+        let length = self.bit_length();
+        let magnitude = if length <= 64 {
+            self.to_u64_magnitude() as f64
+        } else {
+            // Keep the top 64 bits and fold the rest into a sticky bit, there are 11 bits below
+            // the double precision so rounding of the u64 is the rounding of the whole value.
+            let shift = length - 64;
+            let top = shr_magnitude(&self.digits, shift);
+            let mut top = top[0] as u64 | (top[1] as u64) << 32;
+            let rest = shl_magnitude(&shr_magnitude(&self.digits, shift), shift);
+            if rest != self.digits {
+                top |= 1;
+            }
+            if shift > 1023 {
+                f64::INFINITY
+            } else {
+                top as f64 * f64::from_bits((shift + 1023) << 52)
+            }
+        };
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Low 64 bits of the magnitude.
+    fn to_u64_magnitude(&self) -> u64 {
+        let low = *self.digits.first().unwrap_or(&0) as u64;
+        let high = *self.digits.get(1).unwrap_or(&0) as u64;
+        low | high << 32
+    }
+
+    /// Returns the value if it fits in i64.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.bit_length() > 64 {
+            return None;
+        }
+        let magnitude = self.to_u64_magnitude();
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            (magnitude <= i64::MAX as u64).then_some(magnitude as i64)
+        }
+    }
+
+    /// Value modulo 2^64 as returned by `BigInt.asUintN(64, x)`.
+    pub fn to_u64_wrapping(&self) -> u64 {
+        let magnitude = self.to_u64_magnitude();
+        if self.negative {
+            magnitude.wrapping_neg()
+        } else {
+            magnitude
+        }
+    }
+
+    /// Parses digits in `radix`, `text` must not be empty and consist of digits only.
+    fn parse_digits(text: &[u8], radix: u32) -> Option<Self> {
+        if text.is_empty() {
+            return None;
+        }
+        // Process as many digits at a time as fit in a u32.
+        let mut chunk = 1;
+        while (radix as u64).pow(chunk + 1) <= u32::MAX as u64 {
+            chunk += 1;
+        }
+        let mut digits: Vec<u32> = vec![];
+        for piece in text.chunks(chunk as usize) {
+            let mut value = 0u32;
+            for &c in piece {
+                value = value * radix + digit_value(c, radix)?;
+            }
+            let scale = radix.pow(piece.len() as u32);
+            let mut carry = value as u64;
+            for digit in digits.iter_mut() {
+                let t = *digit as u64 * scale as u64 + carry;
+                *digit = t as u32;
+                carry = t >> 32;
+            }
+            if carry != 0 {
+                digits.push(carry as u32);
+            }
+        }
+        Some(Self::from_parts(false, digits))
+    }
+
+    /// Parses a BigInt literal like `123n`, `0x1fn` or `1_000n`. The `n` suffix is required and a
+    /// sign is not part of the literal.
+    pub fn parse_literal(text: &str) -> Option<Self> {
+        let text = text.strip_suffix('n')?;
+        let (radix, digits) = match text.get(..2) {
+            Some("0x") | Some("0X") => (16, &text[2..]),
+            Some("0o") | Some("0O") => (8, &text[2..]),
+            Some("0b") | Some("0B") => (2, &text[2..]),
+            _ => {
+                // Legacy octal and decimals with leading zeros have no BigInt form.
+                if text.len() > 1 && text.starts_with('0') {
+                    return None;
+                }
+                (10, text)
+            }
+        };
+        // Numeric separators must be between two digits.
+        if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            return None;
+        }
+        let digits: Vec<u8> = digits.bytes().filter(|&c| c != b'_').collect();
+        Self::parse_digits(&digits, radix)
+    }
+
+    /// [StringToBigInt](https://tc39.es/ecma262/#sec-stringtobigint), returns `None` where the spec
+    /// returns undefined. Whitespace around the number is allowed and an empty string is `0n`.
+    pub fn from_string(s: &str) -> Option<Self> {
+        let s = s.trim_matches(is_js_whitespace);
+        if s.is_empty() {
+            return Some(Self::zero());
+        }
+        let radix = match s.get(..2) {
+            Some("0x") | Some("0X") => 16,
+            Some("0o") | Some("0O") => 8,
+            Some("0b") | Some("0B") => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            return Self::parse_digits(&s.as_bytes()[2..], radix);
+        }
+        let (negative, digits) = match s.as_bytes()[0] {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let mut result = Self::parse_digits(digits.as_bytes(), 10)?;
+        result.negative = negative && !result.is_zero();
+        Some(result)
+    }
+
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        assert!((2..=36).contains(&radix));
+        if self.is_zero() {
+            return "0".to_owned();
+        }
+        let mut chunk = 1;
+        while (radix as u64).pow(chunk + 1) <= u32::MAX as u64 {
+            chunk += 1;
+        }
+        let divisor = radix.pow(chunk);
+        let mut out = vec![];
+        let mut magnitude = self.digits.clone();
+        while !magnitude.is_empty() {
+            let (quotient, mut rem) = div_rem_small(&magnitude, divisor);
+            for _ in 0..chunk {
+                out.push(std::char::from_digit(rem % radix, radix).unwrap());
+                rem /= radix;
+                if quotient.is_empty() && rem == 0 {
+                    break;
+                }
+            }
+            magnitude = quotient;
+        }
+        if self.negative {
+            out.push('-');
+        }
+        out.iter().rev().collect()
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::from_parts(!self.negative, self.digits.clone())
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return Self::from_parts(self.negative, add_magnitude(&self.digits, &other.digits));
+        }
+        match cmp_magnitude(&self.digits, &other.digits) {
+            Ordering::Less => {
+                Self::from_parts(other.negative, sub_magnitude(&other.digits, &self.digits))
+            }
+            _ => Self::from_parts(self.negative, sub_magnitude(&self.digits, &other.digits)),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, BigIntError> {
+        if self.bit_length() + other.bit_length() > MAX_BIGINT_BITS + 1 {
+            return Err(BigIntError::TooBig);
+        }
+        let digits = mul_magnitude(&self.digits, &other.digits);
+        Ok(Self::from_parts(self.negative != other.negative, digits))
+    }
+
+    /// Quotient truncated toward zero and remainder with the sign of `self`.
+    pub fn div_rem(&self, other: &Self) -> Result<(Self, Self), BigIntError> {
+        if other.is_zero() {
+            return Err(BigIntError::DivisionByZero);
+        }
+        let (quotient, rem) = div_rem_magnitude(&self.digits, &other.digits);
+        Ok((
+            Self::from_parts(self.negative != other.negative, quotient),
+            Self::from_parts(self.negative, rem),
+        ))
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, BigIntError> {
+        Ok(self.div_rem(other)?.0)
+    }
+
+    pub fn rem(&self, other: &Self) -> Result<Self, BigIntError> {
+        Ok(self.div_rem(other)?.1)
+    }
+
+    pub fn pow(&self, exponent: &Self) -> Result<Self, BigIntError> {
+        if exponent.negative {
+            return Err(BigIntError::NegativeExponent);
+        }
+        if exponent.is_zero() {
+            return Ok(Self::from_u64(1));
+        }
+        if self.is_zero() || self.digits == [1] {
+            let odd = exponent.digits[0] & 1 == 1;
+            return Ok(Self::from_parts(self.negative && odd, self.digits.clone()));
+        }
+        let exponent = match exponent.to_i64() {
+            Some(e) if (e as u64) <= MAX_BIGINT_BITS => e as u64,
+            _ => return Err(BigIntError::TooBig),
+        };
+        if (self.bit_length() - 1) * exponent + 1 > MAX_BIGINT_BITS {
+            return Err(BigIntError::TooBig);
+        }
+        let mut result = Self::from_u64(1);
+        let mut base = self.clone();
+        let mut e = exponent;
+        loop {
+            if e & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+            e >>= 1;
+            if e == 0 {
+                break;
+            }
+            base = base.mul(&base)?;
+        }
+        Ok(result)
+    }
+
+    /// `self << shift`, a negative shift shifts right.
+    pub fn shl(&self, shift: &Self) -> Result<Self, BigIntError> {
+        if shift.negative {
+            return Ok(self.shr_by(shift.neg().to_i64().map_or(u64::MAX, |s| s as u64)));
+        }
+        if self.is_zero() {
+            return Ok(Self::zero());
+        }
+        match shift.to_i64() {
+            Some(s) if self.bit_length() + s as u64 <= MAX_BIGINT_BITS => Ok(Self::from_parts(
+                self.negative,
+                shl_magnitude(&self.digits, s as u64),
+            )),
+            _ => Err(BigIntError::TooBig),
+        }
+    }
+
+    /// `self >> shift`, rounds toward negative infinity like an arithmetic shift. A negative shift
+    /// shifts left.
+    pub fn shr(&self, shift: &Self) -> Result<Self, BigIntError> {
+        if shift.negative {
+            return self.shl(&shift.neg());
+        }
+        Ok(self.shr_by(shift.to_i64().map_or(u64::MAX, |s| s as u64)))
+    }
+
+    fn shr_by(&self, shift: u64) -> Self {
+        if !self.negative {
+            return Self::from_parts(false, shr_magnitude(&self.digits, shift));
+        }
+        // floor(-m / 2^s) = -((m - 1) / 2^s) - 1
+        let one = Self::from_u64(1);
+        let m = self.neg().sub(&one);
+        Self::from_parts(false, shr_magnitude(&m.digits, shift))
+            .add(&one)
+            .neg()
+    }
+
+    /// Two's complement digits of `self` sign extended to `length` digits.
+    fn to_twos_complement(&self, length: usize) -> Vec<u32> {
+        let mut digits = self.digits.clone();
+        digits.resize(length, 0);
+        if self.negative {
+            let mut carry = 1u64;
+            for digit in digits.iter_mut() {
+                let t = (!*digit) as u64 + carry;
+                *digit = t as u32;
+                carry = t >> 32;
+            }
+        }
+        digits
+    }
+
+    /// Inverse of [JSBigInt::to_twos_complement], the top bit of `digits` is the sign.
+    fn from_twos_complement(mut digits: Vec<u32>) -> Self {
+        let negative = digits.last().is_some_and(|&top| top >> 31 == 1);
+        if negative {
+            let mut carry = 1u64;
+            for digit in digits.iter_mut() {
+                let t = (!*digit) as u64 + carry;
+                *digit = t as u32;
+                carry = t >> 32;
+            }
+        }
+        Self::from_parts(negative, digits)
+    }
+
+    fn bitwise(&self, other: &Self, op: impl Fn(u32, u32) -> u32) -> Self {
+        let length = self.digits.len().max(other.digits.len()) + 1;
+        let x = self.to_twos_complement(length);
+        let y = other.to_twos_complement(length);
+        let digits = x.iter().zip(y.iter()).map(|(&x, &y)| op(x, y)).collect();
+        Self::from_twos_complement(digits)
+    }
+
+    pub fn bitand(&self, other: &Self) -> Self {
+        self.bitwise(other, |x, y| x & y)
+    }
+
+    pub fn bitor(&self, other: &Self) -> Self {
+        self.bitwise(other, |x, y| x | y)
+    }
+
+    pub fn bitxor(&self, other: &Self) -> Self {
+        self.bitwise(other, |x, y| x ^ y)
+    }
+
+    /// `~self`, which is `-self - 1`.
+    pub fn bitnot(&self) -> Self {
+        self.neg().sub(&Self::from_u64(1))
+    }
+
+    /// `self` modulo 2^bits.
+    pub fn as_uint_n(&self, bits: u64) -> Result<Self, BigIntError> {
+        if !self.negative && self.bit_length() <= bits {
+            return Ok(self.clone());
+        }
+        if bits > MAX_BIGINT_BITS {
+            return Err(BigIntError::TooBig);
+        }
+        let length = (bits as usize).div_ceil(32).max(self.digits.len());
+        let mut digits = self.to_twos_complement(length);
+        digits.truncate((bits as usize).div_ceil(32));
+        if !bits.is_multiple_of(32) {
+            if let Some(top) = digits.last_mut() {
+                *top &= (1 << (bits % 32)) - 1;
+            }
+        }
+        Ok(Self::from_parts(false, digits))
+    }
+
+    /// `self` modulo 2^bits interpreted as a signed `bits` wide integer.
+    pub fn as_int_n(&self, bits: u64) -> Self {
+        if bits == 0 {
+            return Self::zero();
+        }
+        if self.bit_length() < bits {
+            return self.clone();
+        }
+        // bits is at most the bit length here, so it is within the size limit.
+        let unsigned = self.as_uint_n(bits).unwrap();
+        if unsigned.bit_length() == bits {
+            unsigned.sub(&Self::from_u64(1).shl_bits(bits))
+        } else {
+            unsigned
+        }
+    }
+
+    fn shl_bits(&self, shift: u64) -> Self {
+        Self::from_parts(self.negative, shl_magnitude(&self.digits, shift))
+    }
+
+    /// Compares with a Number, returns `None` if `other` is NaN.
+    pub fn cmp_f64(&self, other: f64) -> Option<Ordering> {
+        if other.is_nan() {
+            return None;
+        }
+        if other.is_infinite() {
+            return Some(if other > 0.0 {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            });
+        }
+        let floor = Self::from_f64(other.floor()).unwrap();
+        Some(match self.cmp(&floor) {
+            Ordering::Equal if other.floor() != other => Ordering::Less,
+            ordering => ordering,
+        })
+    }
+}
+
+impl PartialOrd for JSBigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JSBigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.digits, &other.digits),
+            (true, true) => cmp_magnitude(&other.digits, &self.digits),
+        }
+    }
+}
+
+/// Decimal representation without the `n` suffix, as returned by `String(x)`.
+impl fmt::Display for JSBigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_radix(10))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn big(x: i128) -> JSBigInt {
+        JSBigInt::from_string(&x.to_string()).unwrap()
+    }
+
+    fn check(result: JSBigInt, expected: i128) -> Result<(), TestCaseError> {
+        prop_assert_eq!(result.to_string(), expected.to_string());
+        prop_assert_eq!(result, big(expected));
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn arithmetic_matches_i128(x in any::<i64>(), y in any::<i64>()) {
+            let (a, b) = (x as i128, y as i128);
+            check(big(a).add(&big(b)), a + b)?;
+            check(big(a).sub(&big(b)), a - b)?;
+            check(big(a).mul(&big(b)).unwrap(), a * b)?;
+            prop_assert_eq!(big(a).cmp(&big(b)), a.cmp(&b));
+        }
+
+        #[test]
+        fn division_matches_i128(x in any::<i128>(), y in any::<i128>(), shift in 0u32..127) {
+            let y = if y == 0 { 1 } else { y >> shift };
+            let y = if y == 0 { -7 } else { y };
+            if x != i128::MIN {
+                check(big(x).div(&big(y)).unwrap(), x / y)?;
+                check(big(x).rem(&big(y)).unwrap(), x % y)?;
+            }
+        }
+
+        #[test]
+        fn bitwise_matches_i128(x in any::<i64>(), y in any::<i64>(), shift in 0i64..64) {
+            let (a, b) = (x as i128, y as i128);
+            check(big(a).bitand(&big(b)), a & b)?;
+            check(big(a).bitor(&big(b)), a | b)?;
+            check(big(a).bitxor(&big(b)), a ^ b)?;
+            check(big(a).bitnot(), !a)?;
+            check(big(a).shl(&big(shift as i128)).unwrap(), a << shift)?;
+            check(big(a).shr(&big(shift as i128)).unwrap(), a >> shift)?;
+            check(big(a).shl(&big(-shift as i128)).unwrap(), a >> shift)?;
+        }
+
+        #[test]
+        fn as_int_n_matches_casts(x in any::<i128>()) {
+            let x = big(x);
+            check(x.as_uint_n(64).unwrap(), x.to_u64_wrapping() as i128)?;
+            check(x.as_int_n(64), x.to_u64_wrapping() as i64 as i128)?;
+            check(x.as_int_n(32), x.to_u64_wrapping() as i32 as i128)?;
+            check(x.as_uint_n(8).unwrap(), x.to_u64_wrapping() as u8 as i128)?;
+            check(x.as_int_n(1), -((x.to_u64_wrapping() & 1) as i128))?;
+        }
+
+        #[test]
+        fn to_f64_rounds_like_i128(x in any::<i128>()) {
+            prop_assert_eq!(big(x).to_f64().to_bits(), (x as f64).to_bits());
+        }
+
+        #[test]
+        fn f64_round_trips(bits in any::<u64>()) {
+            let x = f64::from_bits(bits);
+            match JSBigInt::from_f64(x) {
+                Some(n) => prop_assert_eq!(n.to_f64(), x),
+                None => prop_assert!(!x.is_finite() || x.trunc() != x),
+            }
+        }
+
+        #[test]
+        fn radix_strings_round_trip(x in any::<u128>(), radix in 2u32..=36) {
+            let n = JSBigInt::from_string(&x.to_string()).unwrap();
+            let s = n.to_string_radix(radix);
+            prop_assert_eq!(u128::from_str_radix(&s, radix).unwrap(), x);
+        }
+    }
+
+    #[test]
+    fn literals() {
+        let parse = |s| JSBigInt::parse_literal(s).map(|n| n.to_string());
+        assert_eq!(parse("123n").unwrap(), "123");
+        assert_eq!(parse("0n").unwrap(), "0");
+        assert_eq!(parse("0x1Fn").unwrap(), "31");
+        assert_eq!(parse("0o777n").unwrap(), "511");
+        assert_eq!(parse("0B101n").unwrap(), "5");
+        assert_eq!(parse("1_000_000n").unwrap(), "1000000");
+        assert_eq!(
+            parse("18446744073709551617n").unwrap(),
+            "18446744073709551617"
+        );
+        for bad in [
+            "123", "n", "0123n", "1.5n", "1e3n", "_1n", "1_n", "1__0n", "0xn",
+        ] {
+            assert!(parse(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn string_to_bigint() {
+        let parse = |s| JSBigInt::from_string(s).map(|n| n.to_string());
+        assert_eq!(parse("  -42\n").unwrap(), "-42");
+        assert_eq!(parse("").unwrap(), "0");
+        assert_eq!(parse("-0").unwrap(), "0");
+        assert_eq!(parse("0xff").unwrap(), "255");
+        for bad in ["1n", "1.0", "-0x1", "1e3", "Infinity", "- 1", "+"] {
+            assert!(parse(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn size_limits() {
+        let one = JSBigInt::from_u64(1);
+        let big_shift = JSBigInt::from_u64(MAX_BIGINT_BITS);
+        assert_eq!(one.shl(&big_shift), Err(BigIntError::TooBig));
+        assert_eq!(
+            one.shl(&JSBigInt::from_u64(u64::MAX)),
+            Err(BigIntError::TooBig)
+        );
+        assert!(JSBigInt::zero().shl(&big_shift).unwrap().is_zero());
+        assert!(one.shr(&JSBigInt::from_u64(u64::MAX)).unwrap().is_zero());
+        assert_eq!(one.neg().shr(&big_shift).unwrap(), one.neg());
+        let two = JSBigInt::from_u64(2);
+        assert_eq!(two.pow(&big_shift), Err(BigIntError::TooBig));
+        assert_eq!(two.pow(&one.neg()), Err(BigIntError::NegativeExponent));
+        assert_eq!(one.neg().pow(&big_shift).unwrap(), one);
+        assert_eq!(
+            two.pow(&JSBigInt::from_u64(100)).unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(one.div(&JSBigInt::zero()), Err(BigIntError::DivisionByZero));
+        assert_eq!(
+            one.neg().as_uint_n(u64::MAX >> 11),
+            Err(BigIntError::TooBig)
+        );
+        assert_eq!(one.neg().as_int_n(u64::MAX >> 11), one.neg());
+    }
+
+    #[test]
+    fn comparison_with_numbers() {
+        let x = JSBigInt::from_i64(-3);
+        assert_eq!(x.cmp_f64(-3.0), Some(Ordering::Equal));
+        assert_eq!(x.cmp_f64(-2.5), Some(Ordering::Less));
+        assert_eq!(x.cmp_f64(-3.5), Some(Ordering::Greater));
+        assert_eq!(x.cmp_f64(f64::NAN), None);
+        assert_eq!(x.cmp_f64(f64::NEG_INFINITY), Some(Ordering::Greater));
+        let huge = JSBigInt::from_u64(1).shl_bits(1100);
+        assert_eq!(huge.to_f64(), f64::INFINITY);
+        assert_eq!(huge.cmp_f64(f64::MAX), Some(Ordering::Greater));
+        let exact = JSBigInt::from_u64(1 << 53).add(&JSBigInt::from_u64(1));
+        assert_eq!(exact.cmp_f64(9007199254740992.0), Some(Ordering::Greater));
+    }
+}
@@ -1,9 +1,7 @@
-use super::cell::*;
 use super::jsproperty::*;
 use super::jsvalue::*;
 use cgc::api::{Finalizer, Traceable, Tracer};
 use fxhash::*;
-use std::collections::HashMap;
 pub trait ObjectTrait {
     fn get_own_property(&self, key: &JSValue) -> JSProperty;
     fn define_own_property(&mut self, key: String, prop: JSProperty) -> bool;
@@ -35,13 +33,34 @@ pub struct JSObject {
 }
 
 impl JSObject {
-    pub fn is_array(&self) -> bool {
-        match self.kind {
-            JSObjectKind::Array(_) => true,
-            _ => false,
+    pub fn new(kind: JSObjectKind, prototype: Option<JSValue>, class: &str) -> Self {
+        Self {
+            kind,
+            internal: FxHashMap::default(),
+            property: hashlink::LinkedHashMap::new(),
+            prototype,
+            extensible: true,
+            class: class.to_owned(),
+            class_object: &super::vtable::OBJECT_VTABLE,
         }
     }
 
+    pub fn is_array(&self) -> bool {
+        matches!(self.kind, JSObjectKind::Array(_))
+    }
+
+    pub fn get_internal(&self, name: &str) -> JSValue {
+        self.internal
+            .get(name)
+            .copied()
+            .unwrap_or(JSValue::undefined())
+    }
+
+    /// Stores into objects in the heap need [Runtime::write_barrier](super::Runtime::write_barrier).
+    pub fn set_internal(&mut self, name: &str, value: JSValue) {
+        self.internal.insert(name.to_owned(), value);
+    }
+
     pub(crate) fn _read(&self, name: &str) -> Option<JSProperty> {
         self.property.get(name).cloned()
     }
@@ -60,22 +79,56 @@ impl JSObject {
 
 pub enum JSObjectKind {
     Array(Vec<JSValue>),
+    /// Mapped arguments object, see `arguments` module.
+    Arguments(super::arguments::ArgumentsMap),
     String,
     Symbol,
-    Error,
+    /// Error object, keeps stack trace captured on construction.
+    Error(Vec<super::error::StackFrame>),
     Boolean,
     Number,
+    BigInt,
+    /// Ephemeron table of WeakMap, see `jsweak` module.
+    WeakMap(super::jsweak::WeakMapData),
+    /// WeakSet entries, values are unused.
+    WeakSet(super::jsweak::WeakMapData),
+    /// WeakRef target, undefined once the target was collected.
+    WeakRef(JSValue),
+    FinalizationRegistry(super::jsweak::FinalizationRegistryData),
+    /// Function calling a Rust closure, see `function` module.
+    NativeFunction(super::function::NativeFunction),
+    /// Rust data of the embedder, see `host` module.
+    Host(Box<dyn super::host::HostObject>),
     Normal,
 }
 
+impl JSObject {
+    /// Traces internal slots, properties and prototype, everything except data of `kind`.
+    pub(crate) fn trace_fields(&self, tracer: &mut Tracer) {
+        self.internal
+            .values()
+            .for_each(|value| value.trace_with(tracer));
+        self.property
+            .values()
+            .for_each(|property| property.trace_with(tracer));
+        self.prototype.trace_with(tracer);
+    }
+}
+
+/// Weak references are traced like strong ones: entries whose keys or targets are unreachable are
+/// removed before each collection, see `jsweak` module.
 impl Traceable for JSObject {
     fn trace_with(&self, tracer: &mut Tracer) {
         match &self.kind {
-            JSObjectKind::Array(array) => {
-                array.trace_with(tracer);
-            }
+            JSObjectKind::Array(array) => array.trace_with(tracer),
+            JSObjectKind::Arguments(map) => map.env.trace_with(tracer),
+            JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) => map.trace_with(tracer),
+            JSObjectKind::WeakRef(target) => target.trace_with(tracer),
+            JSObjectKind::FinalizationRegistry(registry) => registry.trace_with(tracer),
+            JSObjectKind::Host(data) => data.trace_with(tracer),
             _ => (),
         }
+        self.trace_fields(tracer);
     }
 }
 impl Finalizer for JSObject {}
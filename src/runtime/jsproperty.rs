@@ -1,6 +1,5 @@
-use super::cell::Cell;
 use super::jsvalue::*;
-use cgc::api::Handle;
+use cgc::api::{Finalizer, Traceable, Tracer};
 
 #[derive(Clone)]
 /// The property descriptor.
@@ -81,3 +80,13 @@ impl Default for JSProperty {
         Self::new()
     }
 }
+
+impl Traceable for JSProperty {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.value.trace_with(tracer);
+        self.getter.trace_with(tracer);
+        self.setter.trace_with(tracer);
+    }
+}
+
+impl Finalizer for JSProperty {}
@@ -0,0 +1,523 @@
+//! JS string values.
+//!
+//! A JS string is a sequence of UTF-16 code units. Flat strings are stored as Latin-1 when every
+//! code unit fits in a byte and as UTF-16 otherwise. Concatenation creates ropes and substrings
+//! share the buffer of their base string, ropes are flattened on demand when code units are accessed
+//! by index.
+use super::cell::*;
+use cgc::api::{Finalizer, Handle, Traceable, Tracer};
+use fxhash::FxHasher32;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::Hasher;
+
+/// Maximum length of a string in code units, creating a longer string throws RangeError.
+pub const MAX_STRING_LENGTH: usize = i32::MAX as usize;
+/// Concatenations shorter than this are copied instead of creating a rope.
+pub const MIN_ROPE_LENGTH: usize = 13;
+/// Substrings shorter than this are copied instead of sharing buffer of the base string.
+pub const MIN_SUBSTRING_LENGTH: usize = 13;
+
+/// Hash of a string with code units `units`. Latin-1 and UTF-16 strings with the same code units
+/// hash the same.
+pub fn hash_code_units(units: impl Iterator<Item = u16>) -> u32 {
+    let mut hasher = FxHasher32::default();
+    for unit in units {
+        hasher.write_u16(unit);
+    }
+    hasher.finish() as u32
+}
+
+/// Borrowed code units of a flat string.
+#[derive(Copy, Clone)]
+pub enum StringView<'a> {
+    Latin1(&'a [u8]),
+    Utf16(&'a [u16]),
+}
+
+impl<'a> StringView<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            StringView::Latin1(units) => units.len(),
+            StringView::Utf16(units) => units.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn at(&self, index: usize) -> u16 {
+        match self {
+            StringView::Latin1(units) => units[index] as u16,
+            StringView::Utf16(units) => units[index],
+        }
+    }
+
+    fn slice(&self, start: usize, end: usize) -> StringView<'a> {
+        match self {
+            StringView::Latin1(units) => StringView::Latin1(&units[start..end]),
+            StringView::Utf16(units) => StringView::Utf16(&units[start..end]),
+        }
+    }
+}
+
+enum Repr {
+    Latin1(Box<[u8]>),
+    Utf16(Box<[u16]>),
+    /// Concatenation of two strings, replaced by flat representation on [JSString::flatten].
+    Rope {
+        left: Handle<Cell>,
+        right: Handle<Cell>,
+    },
+    /// Code units of flat `base` starting at `start`.
+    Substring {
+        base: Handle<Cell>,
+        start: usize,
+    },
+}
+
+pub struct JSString {
+    repr: Repr,
+    length: usize,
+    /// True when code units are stored as Latin-1. For ropes this means that all leaves are
+    /// Latin-1, so flattening produces a Latin-1 string.
+    latin1: bool,
+    /// Interned in the atom table of the runtime, equal atoms are the same cell.
+    atom: bool,
+    /// Computed on first use, see [JSString::hash_code].
+    hash: std::cell::Cell<Option<u32>>,
+}
+
+fn string_of(cell: &Handle<Cell>) -> &JSString {
+    match cell.get() {
+        Cell::String(s) => s,
+        _ => unreachable!(),
+    }
+}
+
+impl JSString {
+    pub fn new(s: &str) -> Self {
+        if s.chars().all(|c| (c as u32) <= 0xff) {
+            Self::from_latin1(s.chars().map(|c| c as u8).collect())
+        } else {
+            let units: Vec<u16> = s.encode_utf16().collect();
+            let length = units.len();
+            Self {
+                repr: Repr::Utf16(units.into_boxed_slice()),
+                length,
+                latin1: false,
+                atom: false,
+                hash: Default::default(),
+            }
+        }
+    }
+
+    pub fn from_latin1(units: Vec<u8>) -> Self {
+        Self {
+            length: units.len(),
+            repr: Repr::Latin1(units.into_boxed_slice()),
+            latin1: true,
+            atom: false,
+            hash: Default::default(),
+        }
+    }
+
+    /// Creates a flat string, stored as Latin-1 if all code units fit in a byte.
+    pub fn from_utf16(units: &[u16]) -> Self {
+        if units.iter().all(|&unit| unit <= 0xff) {
+            Self::from_latin1(units.iter().map(|&unit| unit as u8).collect())
+        } else {
+            Self {
+                repr: Repr::Utf16(units.into()),
+                length: units.len(),
+                latin1: false,
+                atom: false,
+                hash: Default::default(),
+            }
+        }
+    }
+
+    /// Creates a rope, `left` and `right` must be string cells and the sum of their lengths must not
+    /// exceed [MAX_STRING_LENGTH].
+    pub fn rope(left: Handle<Cell>, right: Handle<Cell>) -> Self {
+        let (l, r) = (string_of(&left), string_of(&right));
+        Self {
+            length: l.length + r.length,
+            latin1: l.latin1 && r.latin1,
+            repr: Repr::Rope { left, right },
+            atom: false,
+            hash: Default::default(),
+        }
+    }
+
+    /// Creates a substring of `base`. `base` must be a flat string cell, not a rope or substring.
+    pub fn substring(base: Handle<Cell>, start: usize, end: usize) -> Self {
+        let s = string_of(&base);
+        assert!(s.is_flat() && start <= end && end <= s.length);
+        Self {
+            latin1: s.latin1,
+            length: end - start,
+            repr: Repr::Substring { base, start },
+            atom: false,
+            hash: Default::default(),
+        }
+    }
+
+    /// Length in UTF-16 code units, a character outside the BMP counts as two.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_latin1(&self) -> bool {
+        self.latin1
+    }
+
+    pub fn is_atom(&self) -> bool {
+        self.atom
+    }
+
+    /// Marks a flat string as atom, only the atom table of the runtime should do this.
+    pub(crate) fn mark_atom(&mut self) {
+        debug_assert!(self.is_flat());
+        self.atom = true;
+    }
+
+    /// Hash of code units, cached after the first call.
+    pub fn hash_code(&self) -> u32 {
+        if let Some(hash) = self.hash.get() {
+            return hash;
+        }
+        let hash = hash_code_units(self.code_units());
+        self.hash.set(Some(hash));
+        hash
+    }
+
+    pub fn is_rope(&self) -> bool {
+        matches!(self.repr, Repr::Rope { .. })
+    }
+
+    pub fn is_substring(&self) -> bool {
+        matches!(self.repr, Repr::Substring { .. })
+    }
+
+    /// True if code units are stored in this string itself.
+    pub fn is_flat(&self) -> bool {
+        matches!(self.repr, Repr::Latin1(_) | Repr::Utf16(_))
+    }
+
+    /// Bytes of code units owned by this string, ropes and substrings share the units of other
+    /// cells.
+    pub fn heap_size(&self) -> usize {
+        match &self.repr {
+            Repr::Latin1(units) => units.len(),
+            Repr::Utf16(units) => units.len() * 2,
+            Repr::Rope { .. } | Repr::Substring { .. } => 0,
+        }
+    }
+
+    /// Returns base string cell and offset of a substring.
+    pub fn substring_base(&self) -> Option<(Handle<Cell>, usize)> {
+        match self.repr {
+            Repr::Substring { base, start } => Some((base, start)),
+            _ => None,
+        }
+    }
+
+    /// Replaces a rope by flat string holding the same code units, does nothing for other strings.
+    pub fn flatten(&mut self) {
+        if !self.is_rope() {
+            return;
+        }
+        self.repr = if self.latin1 {
+            let mut units = Vec::with_capacity(self.length);
+            for view in self.views() {
+                match view {
+                    StringView::Latin1(view) => units.extend_from_slice(view),
+                    StringView::Utf16(_) => unreachable!(),
+                }
+            }
+            Repr::Latin1(units.into_boxed_slice())
+        } else {
+            let mut units = Vec::with_capacity(self.length);
+            for view in self.views() {
+                match view {
+                    StringView::Latin1(view) => units.extend(view.iter().map(|&u| u as u16)),
+                    StringView::Utf16(view) => units.extend_from_slice(view),
+                }
+            }
+            Repr::Utf16(units.into_boxed_slice())
+        };
+    }
+
+    /// Returns code units of a string that is not a rope, see [JSString::flatten].
+    pub fn view(&self) -> StringView<'_> {
+        match &self.repr {
+            Repr::Latin1(units) => StringView::Latin1(units),
+            Repr::Utf16(units) => StringView::Utf16(units),
+            Repr::Substring { base, start } => {
+                string_of(base).view().slice(*start, *start + self.length)
+            }
+            Repr::Rope { .. } => panic!("rope must be flattened before viewing it"),
+        }
+    }
+
+    /// Code unit at `index`, flattens the string if it is a rope.
+    pub fn code_unit_at(&mut self, index: usize) -> u16 {
+        self.flatten();
+        self.view().at(index)
+    }
+
+    /// Code point at `index` as returned by `String.prototype.codePointAt`: a surrogate pair
+    /// starting at `index` is combined, a lone surrogate is returned as is.
+    pub fn code_point_at(&mut self, index: usize) -> u32 {
+        self.flatten();
+        let view = self.view();
+        let first = view.at(index);
+        if (0xd800..0xdc00).contains(&first) && index + 1 < view.len() {
+            let second = view.at(index + 1);
+            if (0xdc00..0xe000).contains(&second) {
+                return 0x10000 + (((first as u32) - 0xd800) << 10) + ((second as u32) - 0xdc00);
+            }
+        }
+        first as u32
+    }
+
+    /// Iterates over flat pieces of the string in order, walking ropes without flattening them.
+    pub fn views(&self) -> impl Iterator<Item = StringView<'_>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || loop {
+            let s = stack.pop()?;
+            match &s.repr {
+                Repr::Rope { left, right } => {
+                    stack.push(string_of(right));
+                    stack.push(string_of(left));
+                }
+                _ => return Some(s.view()),
+            }
+        })
+    }
+
+    pub fn code_units(&self) -> impl Iterator<Item = u16> + '_ {
+        self.views()
+            .flat_map(|view| (0..view.len()).map(move |index| view.at(index)))
+    }
+
+    pub fn to_utf16(&self) -> Vec<u16> {
+        self.code_units().collect()
+    }
+}
+
+/// Converts to UTF-8, lone surrogates are replaced by U+FFFD.
+impl fmt::Display for JSString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in char::decode_utf16(self.code_units()) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for JSString {
+    fn eq(&self, other: &Self) -> bool {
+        if self.atom && other.atom {
+            return std::ptr::eq(self, other);
+        }
+        if let (Some(x), Some(y)) = (self.hash.get(), other.hash.get()) {
+            if x != y {
+                return false;
+            }
+        }
+        self.length == other.length && self.code_units().eq(other.code_units())
+    }
+}
+
+impl Eq for JSString {}
+
+/// Strings are ordered by UTF-16 code units like the JS relational operators do, not by code points.
+impl PartialOrd for JSString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JSString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.code_units().cmp(other.code_units())
+    }
+}
+
+impl Traceable for JSString {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        match &self.repr {
+            Repr::Rope { left, right } => {
+                left.trace_with(tracer);
+                right.trace_with(tracer);
+            }
+            Repr::Substring { base, .. } => base.trace_with(tracer),
+            _ => (),
+        }
+    }
+}
+
+impl Finalizer for JSString {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::jsvalue::JSValue;
+    use crate::runtime::Runtime;
+
+    fn to_string(value: JSValue) -> String {
+        value.as_cell().unchecked_string().to_string()
+    }
+
+    #[test]
+    fn length_counts_utf16_code_units() {
+        let mut rt = Runtime::new();
+        let mut cell = rt.new_string("a\u{e9}\u{1F600}b").as_cell();
+        let s = cell.unchecked_string_mut();
+        assert_eq!(s.len(), 5);
+        assert!(!s.is_latin1());
+        assert_eq!(s.code_unit_at(0), 'a' as u16);
+        assert_eq!(s.code_unit_at(1), 0xe9);
+        assert_eq!(s.code_unit_at(2), 0xd83d);
+        assert_eq!(s.code_unit_at(3), 0xde00);
+        assert_eq!(s.code_point_at(2), 0x1F600);
+        assert_eq!(s.code_point_at(3), 0xde00);
+        assert_eq!(s.to_string(), "a\u{e9}\u{1F600}b");
+        let latin1 = rt.new_string("caf\u{e9}");
+        assert!(latin1.as_cell().unchecked_string().is_latin1());
+    }
+
+    #[test]
+    fn length_property_and_char_code_at() {
+        use crate::runtime::string::*;
+        let mut rt = Runtime::new();
+        let s = rt.new_string("x\u{1F600}");
+        let length = s.get_property(&rt, "length").unwrap().value.unwrap();
+        assert_eq!(length.as_int32(), 3);
+        let code = |rt: &mut Runtime, f: fn(&mut Runtime, JSValue, &[JSValue]) -> _, i| {
+            let result: Result<JSValue, JSValue> = f(rt, s, &[JSValue::new_int(i)]);
+            result.ok().unwrap()
+        };
+        assert_eq!(
+            code(&mut rt, string_proto_char_code_at, 1).as_int32(),
+            0xd83d
+        );
+        assert_eq!(
+            code(&mut rt, string_proto_char_code_at, 2).as_int32(),
+            0xde00
+        );
+        assert!(code(&mut rt, string_proto_char_code_at, 3)
+            .as_number()
+            .is_nan());
+        assert_eq!(
+            code(&mut rt, string_proto_code_point_at, 1).as_int32(),
+            0x1F600
+        );
+        assert!(code(&mut rt, string_proto_code_point_at, -1).is_undefined());
+        let c = code(&mut rt, string_proto_char_at, 2);
+        assert_eq!(c.as_cell().unchecked_string().to_utf16(), [0xde00]);
+    }
+
+    #[test]
+    fn lone_surrogates_survive() {
+        let mut rt = Runtime::new();
+        let mut cell = rt.new_string_from_utf16(&[0xd800, 'x' as u16]).as_cell();
+        let s = cell.unchecked_string_mut();
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.code_point_at(0), 0xd800);
+        assert_eq!(s.to_utf16(), [0xd800, 'x' as u16]);
+        assert_eq!(s.to_string(), "\u{FFFD}x");
+    }
+
+    #[test]
+    fn concatenation_in_a_loop_builds_a_rope() {
+        let mut rt = Runtime::new();
+        let mut acc = rt.new_string("");
+        let mut expected = String::new();
+        for i in 0..1000 {
+            let piece = if i % 100 == 99 {
+                "\u{1F600}".to_owned()
+            } else {
+                i.to_string()
+            };
+            let value = rt.new_string(&piece);
+            acc = rt.concat_strings(acc, value).ok().unwrap();
+            expected.push_str(&piece);
+        }
+        let mut cell = acc.as_cell();
+        let s = cell.unchecked_string_mut();
+        assert!(s.is_rope() && !s.is_latin1());
+        assert_eq!(s.len(), expected.encode_utf16().count());
+        assert_eq!(s.to_string(), expected);
+        s.flatten();
+        assert!(s.is_flat());
+        assert_eq!(s.to_string(), expected);
+        assert_eq!(s.to_utf16(), expected.encode_utf16().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn short_concatenation_is_flat() {
+        let mut rt = Runtime::new();
+        let (a, b) = (rt.new_string("ab"), rt.new_string("cd"));
+        let s = rt.concat_strings(a, b).ok().unwrap();
+        assert!(s.as_cell().unchecked_string().is_flat());
+        assert!(s.as_cell().unchecked_string().is_latin1());
+        assert_eq!(to_string(s), "abcd");
+    }
+
+    #[test]
+    fn substrings_share_base() {
+        let mut rt = Runtime::new();
+        let a = rt.new_string("the quick brown fox ");
+        let b = rt.new_string("jumps over the lazy \u{3b4}og");
+        let rope = rt.concat_strings(a, b).ok().unwrap();
+        let sub = rt.new_substring(rope, 4, 30).ok().unwrap();
+        assert!(rope.as_cell().unchecked_string().is_flat());
+        assert!(sub.as_cell().unchecked_string().is_substring());
+        assert_eq!(to_string(sub), "quick brown fox jumps over");
+        let nested = rt.new_substring(sub, 6, 26).ok().unwrap();
+        let (base, start) = nested
+            .as_cell()
+            .unchecked_string()
+            .substring_base()
+            .unwrap();
+        assert!(std::ptr::eq(base.get(), rope.as_cell().get()));
+        assert_eq!(start, 10);
+        assert_eq!(to_string(nested), "brown fox jumps over");
+        let short = rt.new_substring(rope, 4, 9).ok().unwrap();
+        assert!(short.as_cell().unchecked_string().is_flat());
+        assert_eq!(to_string(short), "quick");
+        let length = rope.as_cell().unchecked_string().len();
+        assert!(rt.new_substring(rope, 0, length).ok().unwrap() == rope);
+    }
+
+    #[test]
+    fn ordering_uses_code_units() {
+        let mut rt = Runtime::new();
+        let bmp = rt.new_string("\u{ff61}");
+        let astral = rt.new_string("\u{1F600}");
+        assert!(astral.as_cell().unchecked_string() < bmp.as_cell().unchecked_string());
+        let (a, b) = (rt.new_string("\u{1F600}"), rt.new_string("\u{1F600}"));
+        let rope = rt.concat_strings(a, b).ok().unwrap();
+        let flat = rt.new_string("\u{1F600}\u{1F600}");
+        assert!(rope.as_cell().unchecked_string() == flat.as_cell().unchecked_string());
+    }
+
+    #[test]
+    fn too_long_strings_throw() {
+        let mut rt = Runtime::new();
+        let mut acc = rt.new_string(&"x".repeat(1 << 20));
+        while acc.as_cell().unchecked_string().len() * 2 <= MAX_STRING_LENGTH {
+            acc = rt.concat_strings(acc, acc).ok().unwrap();
+        }
+        assert!(rt.concat_strings(acc, acc).is_err());
+    }
+}
@@ -1,76 +1,48 @@
 //! JSValue implementation is exactly the same as in JSC and uses NaN-boxing.
+//!
+//! `jsvalue64` (default) packs everything into 64 bits using pointers directly. `jsvalue32-64` uses
+//! a 32-bit tag and a 32-bit payload, it is meant for 32-bit targets and takes precedence when both
+//! features are enabled. On 64-bit targets `jsvalue32-64` keeps cell pointers outside of the
+//! payload, which is only useful for testing the encoding.
 use super::cell::*;
+use super::conversions::*;
+use super::dtoa::*;
+use super::jsbigint::*;
 use super::jsobject::*;
 use super::jsproperty::*;
 use super::pure_nan::*;
+use super::value_repr::*;
+use super::Runtime;
 use cgc::api::Handle;
-#[cfg(all(target_pointer_width = "64", feature = "jsvalue32-64"))]
-compile_error!("Cannot use jsvalue32-64 feature on 64 target");
-
-#[derive(Copy, Clone)]
-#[repr(C, align(8))]
-union EncodedValueDescriptor {
-    as_int64: i64,
-    #[cfg(feature = "jsvalue32-64")]
-    as_double: f64,
-    cell: Handle<Cell>,
-    as_bits: AsBits,
-}
-#[derive(Copy, Clone, PartialEq, Eq)]
-#[repr(C)]
-#[cfg(target_endian = "big")]
-pub struct AsBits {
-    pub tag: i32,
-    pub payload: i32,
-}
-#[derive(Copy, Clone, PartialEq, Eq)]
-#[repr(C)]
-#[cfg(target_endian = "little")]
-pub struct AsBits {
-    pub payload: i32,
-    pub tag: i32,
-}
-
-pub fn tag_offset() -> usize {
-    offset_of!(AsBits, tag)
-}
-pub fn payload_offset() -> usize {
-    offset_of!(AsBits, payload)
-}
+#[cfg(not(feature = "jsvalue32-64"))]
+pub type Repr = super::value64::Value64;
+#[cfg(feature = "jsvalue32-64")]
+pub type Repr = super::value32_64::Value32_64;
 
 pub fn cell_payload_offset() -> usize {
-    #[cfg(feature = "jsvalue64")]
+    #[cfg(not(feature = "jsvalue32-64"))]
     {
         0
     }
-    #[cfg(not(feature = "jsvalue64"))]
+    #[cfg(feature = "jsvalue32-64")]
     {
-        payload_offset()
+        super::value32_64::payload_offset()
     }
 }
 
+/// Hint passed to [JSValue::to_primitive].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PreferredType {
+    Default,
+    Number,
+    String,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum WhichValueWord {
     Tag = 0,
     Payload,
 }
-#[cfg(feature = "jsvalue32-64")]
-pub const INT32_TAG: i32 = 0xffffffffu32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const BOOL_TAG: i32 = 0xfffffffeu32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const UNDEFINED_TAG: i32 = 0xfffffffdu32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const NULL_TAG: i32 = 0xfffffffcu32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const CELL_TAG: i32 = 0xfffffffbu32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const EMPTY_TAG: i32 = 0xfffffffau32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const DELETED_TAG: i32 = 0xfffffff9u32 as i32;
-#[cfg(feature = "jsvalue32-64")]
-pub const LOWEST_TAG: i32 = DELETED_TAG;
-
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum JSTag {
     Null,
@@ -81,379 +53,137 @@ pub enum JSTag {
     AsDouble,
 }
 
-#[derive(Copy, Clone)]
+/// JS value in the encoding selected by features, see [ValueRepr] for the operations.
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct JSValue {
-    u: EncodedValueDescriptor,
+    repr: Repr,
 }
 
-#[cfg(feature = "jsvalue32-64")]
 impl JSValue {
-    /*
-     * On 32-bit platforms `jsvalue32-64` feature should be enabled, and we use a NaN-encoded
-     * form for immediates.
-     *
-     * The encoding makes use of unused NaN space in the IEEE754 representation.  Any value
-     * with the top 13 bits set represents a QNaN (with the sign bit set).  QNaN values
-     * can encode a 51-bit payload.  Hardware produced and C-library payloads typically
-     * have a payload of zero.  We assume that non-zero payloads are available to encode
-     * pointer and integer values.  Since any 64-bit bit pattern where the top 15 bits are
-     * all set represents a NaN with a non-zero payload, we can use this space in the NaN
-     * ranges to encode other values (however there are also other ranges of NaN space that
-     * could have been selected).
-     *
-     * For JSValues that do not contain a double value, the high 32 bits contain the tag
-     * values listed in the enums below, which all correspond to NaN-space. In the case of
-     * cell, integer and bool values the lower 32 bits (the 'payload') contain the pointer
-     * integer or boolean value; in the case of all other tags the payload is 0.
-     */
-    pub fn tag(self) -> u32 {
-        unsafe { self.u.as_bits.tag }
-    }
-    pub fn payload(self) -> i32 {
-        unsafe { self.u.as_bits.payload }
-    }
-
-    pub(crate) fn with_tag_payload(tag: i32, payload: i32) -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_bits: AsBits { tag, payload },
-            },
-        }
+    pub fn from_repr(repr: Repr) -> Self {
+        Self { repr }
     }
 
-    pub fn default() -> Self {
-        Self::with_tag_payload(EMPTY_TAG, 0)
+    pub fn repr(&self) -> Repr {
+        self.repr
     }
 
-    pub fn null() -> Self {
-        Self::with_tag_payload(NULL_TAG, 0)
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Self {
+        Self::from_repr(Repr::empty())
     }
 
     pub fn undefined() -> Self {
-        Self::with_tag_payload(UNDEFINED_TAG, 0)
-    }
-    pub fn true_() -> Self {
-        Self::with_tag_payload(BOOL_TAG, 1)
-    }
-    pub fn false_() -> Self {
-        Self::with_tag_payload(BOOL_TAG, 0)
-    }
-
-    pub fn cell(cell: Handle<Cell>) -> Self {
-        Self::with_tag_payload(CELL_TAG, unsafe {
-            std::mem::transmute::<i32>(cell) /* sizeof(void*) == sizeof(i32) on 32 bit machines,this cast is safe*/
-        })
-    }
-
-    pub fn is_empty(&self) -> bool {
-        self.tag() == EMPTY_TAG
-    }
-
-    pub fn is_null(&self) -> bool {
-        self.tag() == NULL_TAG
-    }
-
-    pub fn is_undefined(&self) -> bool {
-        self.tag() == UNDEFINED_TAG
-    }
-
-    pub fn is_undefined_or_null(&self) -> bool {
-        self.is_undefined() || self.is_null()
-    }
-    pub fn is_cell(&self) -> bool {
-        self.tag() == CELL_TAG
-    }
-
-    pub fn is_int32(&self) -> bool {
-        self.tag() == INT32_TAG
+        Self::from_repr(Repr::undefined())
     }
 
-    pub fn is_double(&self) -> bool {
-        self.tag() < LOWEST_TAG
-    }
-
-    pub fn is_true(&self) -> bool {
-        self.tag() == BOOL_TAG && self.payload() != 0
-    }
-
-    pub fn is_false(&self) -> bool {
-        self.tag() == BOOL_TAG && self.payload() == 0
-    }
-
-    pub fn as_int32(&self) -> bool {
-        self.payload()
+    pub fn null() -> Self {
+        Self::from_repr(Repr::null())
     }
 
-    pub fn as_double(&self) -> bool {
-        unsafe { self.u.as_double }
+    pub fn true_() -> Self {
+        Self::from_repr(Repr::boolean(true))
     }
 
-    pub fn as_cell(&self) -> Handle<Cell> {
-        assert!(self.is_cell(), "Value payload is not a cell!");
-        unsafe { std::mem::transmute(self.payload()) }
-    }
-    pub fn as_cell_ref(&self) -> &Handle<Cell> {
-        assert!(self.is_cell(), "Value payload is not a cell!");
-        unsafe { std::mem::transmute(&self.payload()) }
+    pub fn false_() -> Self {
+        Self::from_repr(Repr::boolean(false))
     }
 
-    pub fn new_double(f: f64) -> Self {
-        assert!(!is_impure_nan(f));
-        Self {
-            u: EncodedValueDescriptor { as_double: f },
-        }
+    pub fn new_bool(x: bool) -> Self {
+        Self::from_repr(Repr::boolean(x))
     }
 
     pub fn new_int(x: i32) -> Self {
-        Self::with_tag_payload(INT32_TAG, x)
+        Self::from_repr(Repr::int32(x))
     }
 
-    pub fn is_number(&self) -> bool {
-        self.is_int32() || self.is_double()
-    }
-
-    pub fn is_boolean(&self) -> bool {
-        self.tag() == BOOL_TAG
-    }
-
-    pub fn as_boolean(&self) -> bool {
-        assert!(self.is_boolean());
-        self.payload() != 0
-    }
-
-    const _XX: () = {
-        impl PartialEq for JSValue {
-            fn eq(&self, other: &Self) -> bool {
-                unsafe { self.u.as_int64 == other.u.as_int64 }
-            }
-        }
-
-        impl Eq for JSValue {}
-    };
-}
-
-#[cfg(feature = "jsvalue64")]
-impl JSValue {
-    /*
-     * On 64-bit platforms `jsvalue64` feature should be enabled, and we use a NaN-encoded
-     * form for immediates.
-     *
-     * The encoding makes use of unused NaN space in the IEEE754 representation.  Any value
-     * with the top 13 bits set represents a QNaN (with the sign bit set).  QNaN values
-     * can encode a 51-bit payload.  Hardware produced and C-library payloads typically
-     * have a payload of zero.  We assume that non-zero payloads are available to encode
-     * pointer and integer values.  Since any 64-bit bit pattern where the top 15 bits are
-     * all set represents a NaN with a non-zero payload, we can use this space in the NaN
-     * ranges to encode other values (however there are also other ranges of NaN space that
-     * could have been selected).
-     *
-     * This range of NaN space is represented by 64-bit numbers begining with the 15-bit
-     * hex patterns 0xFFFC and 0xFFFE - we rely on the fact that no valid double-precision
-     * numbers will fall in these ranges.
-     *
-     * The top 15-bits denote the type of the encoded JSValue:
-     *
-     *     Pointer {  0000:PPPP:PPPP:PPPP
-     *              / 0002:****:****:****
-     *     Double  {         ...
-     *              \ FFFC:****:****:****
-     *     Integer {  FFFE:0000:IIII:IIII
-     *
-     * The scheme we have implemented encodes double precision values by performing a
-     * 64-bit integer addition of the value 2^49 to the number. After this manipulation
-     * no encoded double-precision value will begin with the pattern 0x0000 or 0xFFFE.
-     * Values must be decoded by reversing this operation before subsequent floating point
-     * operations may be peformed.
-     *
-     * 32-bit signed integers are marked with the 16-bit tag 0xFFFE.
-     *
-     * The tag 0x0000 denotes a pointer, or another form of tagged immediate. Boolean,
-     * null and undefined values are represented by specific, invalid pointer values:
-     *
-     *     False:     0x06
-     *     True:      0x07
-     *     Undefined: 0x0a
-     *     Null:      0x02
-     *
-     * These values have the following properties:
-     * - Bit 1 (OtherTag) is set for all four values, allowing real pointers to be
-     *   quickly distinguished from all immediate values, including these invalid pointers.
-     * - With bit 3 masked out (UndefinedTag), Undefined and Null share the
-     *   same value, allowing null & undefined to be quickly detected.
-     *
-     * No valid JSValue will have the bit pattern 0x0, this is used to represent array
-     * holes, and as a C++ 'no value' result (e.g. JSValue() has an internal value of 0).
-     *
-     * This representation works because of the following things:
-     * - It cannot be confused with a Double or Integer thanks to the top bits
-     * - It cannot be confused with a pointer to a Cell, thanks to bit 1 which is set to true
-     * - It cannot be confused with a pointer to wasm thanks to bit 0 which is set to false
-     * - It cannot be confused with true/false because bit 2 is set to false
-     * - It cannot be confused for null/undefined because bit 4 is set to true
-     */
-
-    /// This value is 2^49, used to encode doubles such that the encoded value will begin
-    /// with a 15-bit pattern within the range 0x0002..0xFFFC.
-    pub const DOUBLE_ENCODE_OFFSET_BIT: i64 = 49;
-    pub const DOUBLE_ENCODE_OFFSET: i64 = 1 << Self::DOUBLE_ENCODE_OFFSET_BIT;
-    /// If all bits in the mask are set, this indicates an integer number,
-    /// if any but not all are set this value is a double precision number.
-    pub const NUMBER_TAG: i64 = 0xfffe000000000000u64 as i64;
-    /// The following constant is used for a trick in the implementation of strictEq, to detect if either of the arguments is a double
-    pub const LOWEST_OF_HIGH_BITS: i64 = 1 << 49;
-    /// All non-numeric (bool, null, undefined) immediates have bit 2 set.
-    pub const OTHER_TAG: i64 = 0x2;
-    pub const BOOL_TAG: i64 = 0x4;
-    pub const UNDEFINED_TAG: i64 = 0x8;
-    pub const VALUE_FALSE: i64 = Self::OTHER_TAG | Self::BOOL_TAG | 0; // `0` stands for `false`.
-    pub const VALUE_TRUE: i64 = Self::OTHER_TAG | Self::BOOL_TAG | 1; // `1` stands for `true`.
-    pub const VALUE_UNDEFINED: i64 = Self::OTHER_TAG | Self::UNDEFINED_TAG;
-    pub const VALUE_NULL: i64 = Self::OTHER_TAG;
-    pub const MISC_TAG: i64 = Self::OTHER_TAG | Self::BOOL_TAG | Self::UNDEFINED_TAG;
-    /// NOT_CELL_MASK is used to check for all types of immediate values (either number or 'other').
-    pub const NOT_CELL_MASK: i64 = Self::NUMBER_TAG | Self::OTHER_TAG;
-    /// These special values are never visible to JavaScript code; Empty is used to represent
-    /// Array holes, and for uninitialized JSValues. Deleted is used in hash table code.
-    /// These values would map to cell types in the JSValue encoding, but not valid GC cell
-    /// pointer should have either of these values (Empty is null, deleted is at an invalid
-    /// alignment for a GC cell, and in the zero page).
-    pub const VALUE_EMPTY: i64 = 0x0;
-    pub const VALUE_DELETED: i64 = 0x4;
-    // 0x0 can never occur naturally because it has a tag of 00, indicating a pointer value, but a payload of 0x0, which is in the (invalid) zero page.
-    pub fn default() -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: Self::VALUE_EMPTY,
-            },
-        }
+    /// NaNs are purified, an impure NaN would be indistinguishable from a tagged value.
+    pub fn new_double(x: f64) -> Self {
+        Self::from_repr(Repr::double(x))
     }
 
     pub fn cell(x: Handle<Cell>) -> Self {
-        Self {
-            u: EncodedValueDescriptor { cell: x },
-        }
+        Self::from_repr(Repr::cell(x))
     }
 
     pub fn is_empty(&self) -> bool {
-        unsafe { self.u.as_int64 == Self::VALUE_EMPTY }
+        self.repr.is_empty()
     }
 
-    pub fn undefined() -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: Self::VALUE_UNDEFINED,
-            },
-        }
+    pub fn is_undefined(&self) -> bool {
+        self.repr.is_undefined()
     }
 
-    pub fn null() -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: Self::VALUE_NULL,
-            },
-        }
-    }
-    pub fn false_() -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: Self::VALUE_TRUE,
-            },
-        }
-    }
-    pub fn true_() -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: Self::VALUE_FALSE,
-            },
-        }
+    pub fn is_null(&self) -> bool {
+        self.repr.is_null()
     }
 
-    pub fn is_number(&self) -> bool {
-        unsafe { self.u.as_int64 & Self::NUMBER_TAG != 0 }
+    pub fn is_undefined_or_null(&self) -> bool {
+        self.repr.is_undefined_or_null()
     }
 
-    pub fn is_int32(&self) -> bool {
-        unsafe { (self.u.as_int64 & Self::NUMBER_TAG) == Self::NUMBER_TAG }
+    pub fn is_boolean(&self) -> bool {
+        self.repr.is_boolean()
     }
 
-    pub fn is_undefined(&self) -> bool {
-        *self == Self::undefined()
+    pub fn is_true(&self) -> bool {
+        self.repr.is_true()
     }
 
-    pub fn is_null(&self) -> bool {
-        *self == Self::null()
+    pub fn is_false(&self) -> bool {
+        self.repr.is_false()
     }
 
-    pub fn is_true(&self) -> bool {
-        *self == Self::true_()
+    pub fn is_number(&self) -> bool {
+        self.repr.is_number()
     }
 
-    pub fn is_false(&self) -> bool {
-        *self == Self::false_()
+    pub fn is_int32(&self) -> bool {
+        self.repr.is_int32()
     }
 
-    pub fn is_undefined_or_null(&self) -> bool {
-        unsafe { (self.u.as_int64 & !Self::UNDEFINED_TAG) == Self::VALUE_NULL }
+    pub fn is_double(&self) -> bool {
+        self.repr.is_double()
     }
 
-    pub fn is_boolean(&self) -> bool {
-        unsafe { self.u.as_int64 & !Self::UNDEFINED_TAG == Self::VALUE_FALSE }
-    }
+    /// Also true for the empty value with the default encoding.
     pub fn is_cell(&self) -> bool {
-        unsafe { (self.u.as_int64 & Self::NOT_CELL_MASK) != 0 }
+        self.repr.is_cell()
     }
 
-    pub fn new_double(x: f64) -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: x.to_bits() as i64 + Self::DOUBLE_ENCODE_OFFSET,
-            },
-        }
+    pub fn as_int32(&self) -> i32 {
+        self.repr.as_int32()
     }
 
-    pub fn new_int(x: i32) -> Self {
-        Self {
-            u: EncodedValueDescriptor {
-                as_int64: JSValue::NUMBER_TAG | (x as u32 as i64),
-            },
-        }
-    }
-    pub fn is_double(&self) -> bool {
-        !self.is_int32() && self.is_number()
-    }
     pub fn as_double(&self) -> f64 {
-        assert!(self.is_double());
-        unsafe { f64::from_bits((self.u.as_int64 - Self::DOUBLE_ENCODE_OFFSET) as u64) }
+        self.repr.as_double()
     }
 
     pub fn as_cell(&self) -> Handle<Cell> {
-        assert!(self.is_cell());
-        unsafe { self.u.cell }
+        self.repr.as_cell()
     }
+
     pub fn as_cell_ref(&self) -> &Handle<Cell> {
-        assert!(self.is_cell());
-        unsafe { &self.u.cell }
+        self.repr.as_cell_ref()
     }
+}
 
+impl JSValue {
     pub fn is_any_int(&self) -> bool {
         if self.is_int32() {
             true
         } else if !self.is_number() {
             false
         } else {
-            try_convert_to_i52(self.as_double()) != NOT_INT52 as i64
+            try_convert_to_i52(self.as_double()) != NOT_INT52
         }
     }
-    pub fn as_int32(&self) -> i32 {
-        assert!(self.is_int32());
-        unsafe { self.u.as_int64 as i32 }
-    }
     pub fn as_any_int(&self) -> i64 {
         assert!(self.is_any_int());
         if self.is_int32() {
             return self.as_int32() as i64;
         }
-        return self.as_double().trunc() as i64;
+        self.as_double().trunc() as i64
     }
 
     pub fn is_int32_as_any_int(&self) -> bool {
@@ -461,7 +191,7 @@ impl JSValue {
             return false;
         }
         let value = self.as_any_int();
-        return value >= i32::min_value() as i64 && value <= i32::max_value() as i64;
+        value >= i32::MIN as i64 && value <= i32::MAX as i64
     }
 
     pub fn as_int32_as_any_int(&self) -> i32 {
@@ -477,65 +207,236 @@ impl JSValue {
             return false;
         }
         let value = self.as_any_int();
-        return value >= 0 as i64 && value <= u32::max_value() as i64;
+        value >= 0 && value <= u32::MAX as i64
     }
 
     pub fn as_uint32_as_any_int(&self) -> u32 {
-        assert!(self.is_int32_as_any_int());
+        assert!(self.is_uint32_as_any_int());
         if self.is_int32() {
             return self.as_int32() as u32;
         }
         self.as_double().trunc() as u32
     }
 
-    const _XX: () = {
-        impl PartialEq for JSValue {
-            fn eq(&self, other: &Self) -> bool {
-                unsafe { self.u.as_int64 == other.u.as_int64 }
-            }
-        }
-
-        impl Eq for JSValue {}
-    };
-}
-
-impl JSValue {
     pub fn is_uint32(&self) -> bool {
         self.is_int32() && self.as_int32() >= 0
     }
 
-    pub fn to_int32(&self) -> i32 {
-        let d = self.to_number();
-        d as i32
+    /// [ToInt32](https://tc39.es/ecma262/#sec-toint32)
+    #[inline(always)]
+    pub fn to_int32(&self, rt: &mut Runtime) -> Result<i32, JSValue> {
+        if self.is_int32() {
+            return Ok(self.as_int32());
+        }
+        Ok(f64_to_int32(self.to_number(rt)?))
     }
-    pub fn to_uint32(&self) -> u32 {
+    /// [ToUint32](https://tc39.es/ecma262/#sec-touint32)
+    #[inline(always)]
+    pub fn to_uint32(&self, rt: &mut Runtime) -> Result<u32, JSValue> {
         // The only difference between to_int32 and to_uint32 is that to_uint32 reinterprets resulted i32 value as u32.
-        // https://tc39.es/ecma262/#sec-touint32
-        self.to_int32() as u32
+        Ok(self.to_int32(rt)? as u32)
+    }
+    /// [ToInt16](https://tc39.es/ecma262/#sec-toint16)
+    pub fn to_int16(&self, rt: &mut Runtime) -> Result<i16, JSValue> {
+        Ok(self.to_int32(rt)? as i16)
+    }
+    /// [ToUint16](https://tc39.es/ecma262/#sec-touint16)
+    pub fn to_uint16(&self, rt: &mut Runtime) -> Result<u16, JSValue> {
+        Ok(self.to_int32(rt)? as u16)
+    }
+    /// [ToInt8](https://tc39.es/ecma262/#sec-toint8)
+    pub fn to_int8(&self, rt: &mut Runtime) -> Result<i8, JSValue> {
+        Ok(self.to_int32(rt)? as i8)
+    }
+    /// [ToUint8](https://tc39.es/ecma262/#sec-touint8)
+    pub fn to_uint8(&self, rt: &mut Runtime) -> Result<u8, JSValue> {
+        Ok(self.to_int32(rt)? as u8)
     }
+    /// [ToUint8Clamp](https://tc39.es/ecma262/#sec-touint8clamp)
+    pub fn to_uint8_clamp(&self, rt: &mut Runtime) -> Result<u8, JSValue> {
+        if self.is_int32() {
+            return Ok(self.as_int32().clamp(0, 255) as u8);
+        }
+        Ok(f64_to_uint8_clamp(self.to_number(rt)?))
+    }
+    /// [ToNumber](https://tc39.es/ecma262/#sec-tonumber)
     #[inline(always)]
-    pub fn to_number(&self) -> f64 {
+    pub fn to_number(&self, rt: &mut Runtime) -> Result<f64, JSValue> {
         if self.is_int32() {
-            return self.as_int32() as f64;
+            return Ok(self.as_int32() as f64);
         }
         if self.is_double() {
-            return self.as_double();
+            return Ok(self.as_double());
         }
-        self.to_number_slow_case()
+        self.to_number_slow_case(rt)
     }
-    pub fn to_number_slow_case(&self) -> f64 {
+    pub fn to_number_slow_case(&self, rt: &mut Runtime) -> Result<f64, JSValue> {
         assert!(!self.is_int32() && !self.is_double());
         if self.is_cell() {
-            unimplemented!()
+            match &*self.as_cell() {
+                Cell::String(s) => return Ok(string_to_number(&s.to_string())),
+                Cell::BigInt(_) => {
+                    return Err(rt.new_type_error("Cannot convert a BigInt value to a number"))
+                }
+                _ => (),
+            }
+            debug_assert!(self.is_object());
+            return self.to_primitive(rt, PreferredType::Number)?.to_number(rt);
         }
         if self.is_true() {
-            return 1.0;
+            return Ok(1.0);
+        }
+        if self.is_undefined() {
+            Ok(pure_nan())
+        } else {
+            Ok(0.0) // null and false both convert to 0.
+        }
+    }
+
+    /// [ToIntegerOrInfinity](https://tc39.es/ecma262/#sec-tointegerorinfinity)
+    pub fn to_integer_or_infinity(&self, rt: &mut Runtime) -> Result<f64, JSValue> {
+        if self.is_int32() {
+            return Ok(self.as_int32() as f64);
+        }
+        let number = self.to_number(rt)?;
+        if number.is_nan() || number == 0.0 {
+            return Ok(0.0);
+        }
+        Ok(number.trunc())
+    }
+
+    /// [ToString](https://tc39.es/ecma262/#sec-tostring)
+    pub fn to_string(&self, rt: &mut Runtime) -> Result<String, JSValue> {
+        if self.is_int32() {
+            return Ok(self.as_int32().to_string());
+        }
+        if self.is_number() {
+            return Ok(number_to_string(self.as_double()));
         }
         if self.is_undefined() {
-            return pure_nan();
+            return Ok("undefined".to_owned());
+        }
+        if self.is_null() {
+            return Ok("null".to_owned());
+        }
+        if self.is_boolean() {
+            return Ok(if self.is_true() { "true" } else { "false" }.to_owned());
+        }
+        match &*self.as_cell() {
+            Cell::String(s) => return Ok(s.to_string()),
+            Cell::BigInt(n) => return Ok(n.to_string()),
+            _ => (),
+        }
+        debug_assert!(self.is_object());
+        self.to_primitive(rt, PreferredType::String)?.to_string(rt)
+    }
+
+    /// [ToNumeric](https://tc39.es/ecma262/#sec-tonumeric), returns a Number or a BigInt.
+    pub fn to_numeric(&self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        if self.is_number() || self.is_bigint() {
+            return Ok(*self);
+        }
+        let primitive = self.to_primitive(rt, PreferredType::Number)?;
+        if primitive.is_bigint() {
+            return Ok(primitive);
+        }
+        Ok(JSValue::new_double(primitive.to_number(rt)?))
+    }
+
+    /// [ToBigInt](https://tc39.es/ecma262/#sec-tobigint)
+    pub fn to_bigint(&self, rt: &mut Runtime) -> Result<JSBigInt, JSValue> {
+        let primitive = self.to_primitive(rt, PreferredType::Number)?;
+        if primitive.is_bigint() {
+            return Ok(primitive.as_bigint().clone());
+        }
+        if primitive.is_boolean() {
+            return Ok(JSBigInt::from_u64(primitive.is_true() as u64));
+        }
+        if primitive.is_string() {
+            let s = primitive.to_string(rt)?;
+            return match JSBigInt::from_string(&s) {
+                Some(n) => Ok(n),
+                None => {
+                    let msg = format!("Cannot convert {} to a BigInt", s);
+                    Err(rt.new_syntax_error(&msg))
+                }
+            };
+        }
+        let msg = if primitive.is_number() {
+            format!("Cannot convert {} to a BigInt", primitive.to_string(rt)?)
+        } else if primitive.is_undefined() {
+            "Cannot convert undefined to a BigInt".to_owned()
         } else {
-            0.0 // null and false both convert to 0.
+            "Cannot convert null to a BigInt".to_owned()
+        };
+        Err(rt.new_type_error(&msg))
+    }
+
+    /// [ToString](https://tc39.es/ecma262/#sec-tostring) returning a JS string. Unlike
+    /// [JSValue::to_string] it keeps lone surrogates.
+    pub fn to_js_string(&self, rt: &mut Runtime) -> Result<JSValue, JSValue> {
+        if self.is_string() {
+            return Ok(*self);
+        }
+        let primitive = self.to_primitive(rt, PreferredType::String)?;
+        if primitive.is_string() {
+            return Ok(primitive);
+        }
+        let s = primitive.to_string(rt)?;
+        Ok(rt.new_string(&s))
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.is_cell() && !self.is_empty() && matches!(&*self.as_cell(), Cell::Object(_))
+    }
+
+    /// [IsCallable](https://tc39.es/ecma262/#sec-iscallable)
+    pub fn is_callable(&self) -> bool {
+        self.is_object()
+            && matches!(
+                self.as_cell().unchecked_object_mut().kind,
+                JSObjectKind::NativeFunction(_)
+            )
+    }
+
+    /// [ToPrimitive](https://tc39.es/ecma262/#sec-toprimitive)
+    ///
+    /// The runtime has no symbols, so there is no `@@toPrimitive` method to look up and objects
+    /// always convert through [JSValue::ordinary_to_primitive].
+    pub fn to_primitive(&self, rt: &mut Runtime, hint: PreferredType) -> Result<JSValue, JSValue> {
+        if !self.is_object() {
+            return Ok(*self);
+        }
+        let hint = match hint {
+            PreferredType::Default => PreferredType::Number,
+            hint => hint,
+        };
+        self.ordinary_to_primitive(rt, hint)
+    }
+
+    /// [OrdinaryToPrimitive](https://tc39.es/ecma262/#sec-ordinarytoprimitive)
+    pub fn ordinary_to_primitive(
+        &self,
+        rt: &mut Runtime,
+        hint: PreferredType,
+    ) -> Result<JSValue, JSValue> {
+        let method_names = match hint {
+            PreferredType::String => ["toString", "valueOf"],
+            _ => ["valueOf", "toString"],
+        };
+        let get = self.as_cell().unchecked_object_mut().class_object.get;
+        let scope = super::rooting::HandleScope::new(rt);
+        let object = scope.root(*self);
+        for name in method_names.iter() {
+            let method = get(rt, object.get(), name)?;
+            if method.is_callable() {
+                let result = rt.execute(method, object.get(), &[])?;
+                if !result.is_object() {
+                    return Ok(result);
+                }
+            }
         }
+        Err(rt.new_type_error("Cannot convert object to primitive value"))
     }
 
     pub fn as_number(&self) -> f64 {
@@ -549,15 +450,23 @@ impl JSValue {
 
     pub fn is_string(&self) -> bool {
         if self.is_cell() {
-            match &*self.as_cell() {
-                Cell::String(_) => true,
-                _ => false,
-            }
+            matches!(&*self.as_cell(), Cell::String(_))
         } else {
             false
         }
     }
 
+    pub fn is_bigint(&self) -> bool {
+        self.is_cell() && !self.is_empty() && matches!(&*self.as_cell(), Cell::BigInt(_))
+    }
+
+    pub fn as_bigint(&self) -> &JSBigInt {
+        match self.as_cell_ref().get() {
+            Cell::BigInt(n) => n,
+            _ => unreachable!(),
+        }
+    }
+
     pub fn is_array(&self) -> bool {
         if self.is_cell() {
             match &*self.as_cell() {
@@ -576,14 +485,14 @@ impl JSValue {
                 Cell::String(s) => {
                     return Some(JSProperty::default().value(JSValue::new_int(s.len() as i32)))
                 }
-                Cell::Object(obj) => match obj.kind {
-                    JSObjectKind::Array(ref arr) => {
+                Cell::Object(obj) => {
+                    if let JSObjectKind::Array(ref arr) = obj.kind {
                         return Some(
                             JSProperty::default().value(JSValue::new_int(arr.len() as i32)),
-                        )
+                        );
                     }
-                    _ => (),
-                },
+                }
+                Cell::BigInt(_) | Cell::Environment(_) => (),
             }
         }
 
@@ -592,15 +501,14 @@ impl JSValue {
             return None;
         }
         let cell = object.as_cell();
-        match &*cell {
-            Cell::Object(object) => match object.property.get(name) {
-                Some(val) => return Some(val.clone()),
+        if let Cell::Object(object) = &*cell {
+            return match object.property.get(name) {
+                Some(val) => Some(val.clone()),
                 None => match object.internal.get("__proto__") {
-                    Some(value) => return value.get_property(rt, name),
-                    _ => return None,
+                    Some(value) => value.get_property(rt, name),
+                    _ => None,
                 },
-            },
-            _ => (),
+            };
         }
         None
     }
@@ -613,7 +521,9 @@ impl JSValue {
         } else if self.is_cell() {
             match &*self.as_cell() {
                 Cell::String(_) => rt.string,
+                Cell::BigInt(_) => rt.bigint,
                 Cell::Object(_) => *self,
+                Cell::Environment(_) => JSValue::undefined(),
             }
         } else {
             JSValue::undefined()
@@ -621,42 +531,25 @@ impl JSValue {
     }
 }
 
-macro_rules! signbit {
-    ($x: expr) => {{
-        if $x < 0.0 {
-            false
-        } else {
-            true
-        }
-    }};
-}
-
-pub const NOT_INT52: usize = 1 << 52;
+pub const NOT_INT52: i64 = 1 << 52;
 
+/// Returns `number` as integer if it is an integer in the int52 range and is not `-0`, otherwise
+/// returns [NOT_INT52].
 #[inline]
 pub fn try_convert_to_i52(number: f64) -> i64 {
-    if number != number {
-        return NOT_INT52 as i64;
+    if number.is_nan() || number.is_infinite() {
+        return NOT_INT52;
     }
-    if number.is_infinite() {
-        return NOT_INT52 as i64;
-    }
-
-    let as_int64 = number.to_bits() as i64;
+    let as_int64 = number as i64;
     if as_int64 as f64 != number {
-        return NOT_INT52 as _;
-    }
-    if !as_int64 != 0 && signbit!(number) {
-        return NOT_INT52 as _;
+        return NOT_INT52;
     }
-
-    if as_int64 >= (1 << (52 - 1)) {
-        return NOT_INT52 as _;
+    if as_int64 == 0 && number.is_sign_negative() {
+        return NOT_INT52;
     }
-    if as_int64 < (1 << (52 - 1)) {
-        return NOT_INT52 as _;
+    if !(-(1 << (52 - 1))..(1 << (52 - 1))).contains(&as_int64) {
+        return NOT_INT52;
     }
-
     as_int64
 }
 
@@ -664,10 +557,114 @@ use cgc::api::{Finalizer, Traceable, Tracer};
 
 impl Traceable for JSValue {
     fn trace_with(&self, tracer: &mut Tracer) {
-        if self.is_cell() {
+        // Empty value is encoded as null pointer, it marks holes and uninitialized bindings.
+        if self.is_cell() && !self.is_empty() {
             tracer.trace(self.as_cell_ref());
         }
     }
 }
 
 impl Finalizer for JSValue {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::error::JSError;
+    use crate::runtime::rooting::{HandleScope, Persistent};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn int32_is_any_int(x in any::<i32>()) {
+            let value = JSValue::new_int(x);
+            prop_assert!(value.is_any_int());
+            prop_assert_eq!(value.as_any_int(), x as i64);
+            prop_assert_eq!(value.as_number(), x as f64);
+        }
+
+        #[test]
+        fn integral_doubles_are_any_int(x in -(1i64 << 51)..(1i64 << 51)) {
+            let value = JSValue::new_double(x as f64);
+            prop_assert!(value.is_any_int());
+            prop_assert_eq!(value.as_any_int(), x);
+            prop_assert_eq!(value.is_int32_as_any_int(), x as i32 as i64 == x);
+            prop_assert_eq!(value.is_uint32_as_any_int(), x as u32 as i64 == x);
+        }
+
+        #[test]
+        fn fractional_doubles_are_not_any_int(x in any::<f64>()) {
+            prop_assume!(x.fract() != 0.0 || !x.is_finite());
+            prop_assert!(!JSValue::new_double(x).is_any_int());
+        }
+    }
+
+    #[test]
+    fn any_int_boundaries() {
+        let limit = (1i64 << 51) as f64;
+        assert!(JSValue::new_double(limit - 1.0).is_any_int());
+        assert!(JSValue::new_double(-limit).is_any_int());
+        assert!(!JSValue::new_double(limit).is_any_int());
+        assert!(!JSValue::new_double(-limit - 1.0).is_any_int());
+        assert!(!JSValue::new_double(-0.0).is_any_int());
+        assert!(JSValue::new_double(0.0).is_any_int());
+        assert!(!JSValue::new_double(f64::INFINITY).is_any_int());
+        assert!(!JSValue::new_double(f64::NAN).is_any_int());
+        assert_eq!(
+            JSValue::new_double(4294967295.0).as_uint32_as_any_int(),
+            u32::MAX
+        );
+    }
+
+    fn with_methods(rt: &mut Runtime, value_of: JSValue, to_string: JSValue) -> JSValue {
+        let value_of = Persistent::new(rt, value_of);
+        let to_string = Persistent::new(rt, to_string);
+        let scope = HandleScope::new(rt);
+        let object = scope.root(rt.new_object());
+        rt.define_native_method(
+            object.get(),
+            "valueOf",
+            0,
+            move |_, _, _| Ok(value_of.get()),
+        );
+        rt.define_native_method(object.get(), "toString", 0, move |_, _, _| {
+            Ok(to_string.get())
+        });
+        object.get()
+    }
+
+    #[test]
+    fn objects_to_number() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let text = scope.root(rt.new_string(" 0x10 "));
+        let object = with_methods(&mut rt, JSValue::new_int(7), text.get());
+        assert_eq!(object.to_number(&mut rt).ok(), Some(7.0));
+        // valueOf returning an object falls back to toString.
+        let inner = scope.root(rt.new_object());
+        let object = scope.root(with_methods(&mut rt, inner.get(), text.get()));
+        assert_eq!(object.get().to_number(&mut rt).ok(), Some(16.0));
+        // Strings prefer toString.
+        let string = object
+            .get()
+            .to_primitive(&mut rt, PreferredType::String)
+            .ok()
+            .unwrap();
+        assert!(string.is_string());
+
+        let object = with_methods(&mut rt, inner.get(), inner.get());
+        let error = object.to_number(&mut rt).err().unwrap();
+        let error = JSError::from_value(&mut rt, error);
+        assert_eq!(error.name, "TypeError");
+        assert_eq!(error.message, "Cannot convert object to primitive value");
+
+        let big = rt.new_bigint(JSBigInt::from_i64(1));
+        assert!(big.to_number(&mut rt).is_err());
+        assert!(JSValue::undefined()
+            .to_number(&mut rt)
+            .ok()
+            .unwrap()
+            .is_nan());
+        assert_eq!(JSValue::null().to_number(&mut rt).ok(), Some(0.0));
+        assert_eq!(JSValue::true_().to_number(&mut rt).ok(), Some(1.0));
+    }
+}
@@ -0,0 +1,454 @@
+//! Weak collections, weak references and finalization registries.
+//!
+//! cgc has no weak pointers, every pointer a cell traces is strong and gets relocated. Weak
+//! semantics come from a marking pass that runs before each collection: it finds the cells
+//! reachable from the roots, treating WeakMap and WeakSet entries as ephemerons (an entry keeps its
+//! value alive only while its key is reachable) and WeakRef and FinalizationRegistry targets as
+//! weak. Entries whose keys or targets were not reached are removed, so everything left in weak
+//! containers is alive and the collection traces and relocates it like ordinary fields.
+use super::cell::*;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::rooting::{cell_address, for_each_child, traced_cell};
+use super::Runtime;
+use cgc::api::{Finalizer, Handle, Traceable, Tracer};
+use fxhash::{FxHashMap, FxHashSet};
+
+/// [CanBeHeldWeakly](https://tc39.es/ecma262/#sec-canbeheldweakly)
+///
+/// TODO: Accept symbols not in the global symbol registry once symbols are implemented.
+pub fn can_be_held_weakly(value: JSValue) -> bool {
+    value.is_object()
+}
+
+/// Entries of WeakMap or WeakSet, keyed by cell address. Addresses change when cells move, so the
+/// table is rehashed after every collection.
+#[derive(Default)]
+pub struct WeakMapData {
+    entries: FxHashMap<usize, (JSValue, JSValue)>,
+}
+
+impl WeakMapData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: JSValue) -> Option<JSValue> {
+        if !can_be_held_weakly(key) {
+            return None;
+        }
+        self.entries
+            .get(&cell_address(key.as_cell()))
+            .map(|(_, value)| *value)
+    }
+
+    pub fn has(&self, key: JSValue) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// `key` must satisfy [can_be_held_weakly].
+    pub fn set(&mut self, key: JSValue, value: JSValue) {
+        self.entries
+            .insert(cell_address(key.as_cell()), (key, value));
+    }
+
+    pub fn delete(&mut self, key: JSValue) -> bool {
+        can_be_held_weakly(key) && self.entries.remove(&cell_address(key.as_cell())).is_some()
+    }
+
+    /// Iterates over `(key, value)` entries in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (JSValue, JSValue)> + '_ {
+        self.entries.values().copied()
+    }
+
+    fn rehash(&mut self) {
+        self.entries = self
+            .entries
+            .drain()
+            .map(|(_, (key, value))| (cell_address(key.as_cell()), (key, value)))
+            .collect();
+    }
+}
+
+impl Traceable for WeakMapData {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        for (key, value) in self.entries.values() {
+            key.trace_with(tracer);
+            value.trace_with(tracer);
+        }
+    }
+}
+
+impl Finalizer for WeakMapData {}
+
+/// Record of `FinalizationRegistry.prototype.register`.
+pub struct FinalizationCell {
+    /// Weak, the cell is removed and cleanup is scheduled once it becomes unreachable.
+    pub target: JSValue,
+    pub holdings: JSValue,
+    /// Weak, `None` if not given or collected.
+    pub unregister_token: Option<JSValue>,
+}
+
+pub struct FinalizationRegistryData {
+    pub cleanup: JSValue,
+    pub cells: Vec<FinalizationCell>,
+}
+
+impl FinalizationRegistryData {
+    pub fn new(cleanup: JSValue) -> Self {
+        Self {
+            cleanup,
+            cells: vec![],
+        }
+    }
+
+    /// Removes cells registered with `token`, returns true if there were any.
+    pub fn unregister(&mut self, token: JSValue) -> bool {
+        let len = self.cells.len();
+        self.cells
+            .retain(|cell| cell.unregister_token != Some(token));
+        self.cells.len() != len
+    }
+}
+
+impl Traceable for FinalizationRegistryData {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.cleanup.trace_with(tracer);
+        for cell in self.cells.iter() {
+            cell.target.trace_with(tracer);
+            cell.holdings.trace_with(tracer);
+            cell.unregister_token.trace_with(tracer);
+        }
+    }
+}
+
+impl Finalizer for FinalizationRegistryData {}
+
+/// Calls `f` with the cells `cell` refers to strongly.
+fn for_each_strong_child(cell: &Cell, mut f: impl FnMut(Handle<Cell>)) {
+    let object = match cell {
+        Cell::Object(object) => object,
+        _ => return for_each_child(cell, f),
+    };
+    let mut tracer = Tracer::default();
+    match &object.kind {
+        JSObjectKind::WeakMap(_) | JSObjectKind::WeakSet(_) | JSObjectKind::WeakRef(_) => (),
+        JSObjectKind::FinalizationRegistry(registry) => {
+            registry.cleanup.trace_with(&mut tracer);
+            for cell in registry.cells.iter() {
+                cell.holdings.trace_with(&mut tracer);
+            }
+        }
+        _ => return for_each_child(cell, f),
+    }
+    object.trace_fields(&mut tracer);
+    tracer.for_each(|pointer| f(traced_cell(pointer)));
+}
+
+fn is_marked(marked: &FxHashSet<usize>, value: JSValue) -> bool {
+    marked.contains(&cell_address(value.as_cell()))
+}
+
+impl Runtime {
+    /// Creates a WeakMap, WeakSet, WeakRef or FinalizationRegistry object. Objects of these kinds
+    /// must be created here, the collector only clears weak references of registered containers.
+    pub fn new_weak_container(&mut self, kind: JSObjectKind) -> JSValue {
+        let (proto, class) = match &kind {
+            JSObjectKind::WeakMap(_) => (self.weak_map, "WeakMap"),
+            JSObjectKind::WeakSet(_) => (self.weak_set, "WeakSet"),
+            JSObjectKind::WeakRef(_) => (self.weak_ref, "WeakRef"),
+            JSObjectKind::FinalizationRegistry(_) => {
+                (self.finalization_registry, "FinalizationRegistry")
+            }
+            _ => unreachable!("not a weak container kind"),
+        };
+        let object = self.allocate(Cell::Object(Box::new(JSObject::new(
+            kind,
+            Some(proto),
+            class,
+        ))));
+        self.weak_containers.push(object);
+        object
+    }
+
+    /// Marks cells reachable from the roots and `pending` without following weak references,
+    /// runs before collections that have weak containers or host objects to sweep.
+    pub(crate) fn mark_reachable(&self, pending: Option<&Cell>) -> FxHashSet<usize> {
+        let mut marked = FxHashSet::default();
+        let mut stack = vec![];
+        let mut ephemerons = vec![];
+        let mut tracer = Tracer::default();
+        self.trace_roots(&mut tracer);
+        if let Some(cell) = pending {
+            cell.trace_with(&mut tracer);
+        }
+        tracer.for_each(|pointer| stack.push(traced_cell(pointer)));
+        loop {
+            while let Some(cell) = stack.pop() {
+                if !marked.insert(cell_address(cell)) {
+                    continue;
+                }
+                if let Cell::Object(object) = cell.get() {
+                    if let JSObjectKind::WeakMap(_) = object.kind {
+                        ephemerons.push(cell);
+                    }
+                }
+                for_each_strong_child(cell.get(), |child| stack.push(child));
+            }
+            // Values of entries whose keys were reached are reachable too, which may make more
+            // keys reachable.
+            for map in ephemerons.iter() {
+                let map = match map.get() {
+                    Cell::Object(object) => match &object.kind {
+                        JSObjectKind::WeakMap(map) => map,
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                for (key, value) in map.entries.values() {
+                    if is_marked(&marked, *key)
+                        && value.is_cell()
+                        && !value.is_empty()
+                        && !is_marked(&marked, *value)
+                    {
+                        stack.push(value.as_cell());
+                    }
+                }
+            }
+            if stack.is_empty() {
+                break;
+            }
+        }
+        marked
+    }
+
+    /// Removes weak references to cells that are not `marked` by [Runtime::mark_reachable].
+    /// Cleanup jobs are enqueued for collected targets of live registries.
+    pub(crate) fn sweep_weak_references(&mut self, marked: &FxHashSet<usize>) {
+        let mut jobs = vec![];
+        self.weak_containers
+            .retain(|container| is_marked(marked, *container));
+        for container in self.weak_containers.iter() {
+            match &mut container.as_cell().unchecked_object_mut().kind {
+                JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) => {
+                    map.entries.retain(|_, (key, _)| is_marked(marked, *key))
+                }
+                JSObjectKind::WeakRef(target) => {
+                    if target.is_object() && !is_marked(marked, *target) {
+                        *target = JSValue::undefined();
+                    }
+                }
+                JSObjectKind::FinalizationRegistry(registry) => {
+                    let cleanup = registry.cleanup;
+                    registry.cells.retain(|cell| {
+                        let alive = is_marked(marked, cell.target);
+                        if !alive {
+                            jobs.push((cleanup, cell.holdings));
+                        }
+                        alive
+                    });
+                    for cell in registry.cells.iter_mut() {
+                        if let Some(token) = cell.unregister_token {
+                            if !is_marked(marked, token) {
+                                cell.unregister_token = None;
+                            }
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        for (cleanup, holdings) in jobs {
+            self.enqueue_job(cleanup, vec![holdings]);
+        }
+    }
+
+    /// Rehashes WeakMap and WeakSet tables after their keys moved.
+    pub(crate) fn rehash_weak_maps(&mut self) {
+        for container in self.weak_containers.iter() {
+            if let JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) =
+                &mut container.as_cell().unchecked_object_mut().kind
+            {
+                map.rehash();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::jsproperty::JSProperty;
+    use crate::runtime::rooting::*;
+    use crate::runtime::weak::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn entries(container: JSValue) -> usize {
+        match &container.as_cell().unchecked_object_mut().kind {
+            JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) => map.len(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn call(
+        rt: &mut Runtime,
+        f: fn(&mut Runtime, JSValue, &[JSValue]) -> Result<JSValue, JSValue>,
+        this: JSValue,
+        args: &[JSValue],
+    ) -> JSValue {
+        f(rt, this, args).ok().unwrap()
+    }
+
+    fn string(value: JSValue) -> String {
+        value.as_cell().unchecked_string().to_string()
+    }
+
+    /// Calls global constructor `name` with `new`.
+    fn construct(rt: &mut Runtime, name: &str, args: &[JSValue]) -> JSValue {
+        let global = rt.global;
+        let constructor = crate::runtime::vtable::object_get(rt, global, name)
+            .ok()
+            .unwrap();
+        rt.construct(constructor, args, None).ok().unwrap()
+    }
+
+    #[test]
+    fn weak_map_entries_die_with_their_keys() {
+        let mut rt = Runtime::new();
+        let map = construct(&mut rt, "WeakMap", &[]);
+        let map = Persistent::new(&rt, map);
+        let set = construct(&mut rt, "WeakSet", &[]);
+        let set = Persistent::new(&rt, set);
+        let (live, dead) = (rt.new_object(), rt.new_object());
+        let live = Persistent::new(&rt, live);
+        let value = rt.new_string("value");
+        call(&mut rt, weak_map_proto_set, map.get(), &[live.get(), value]);
+        call(&mut rt, weak_map_proto_set, map.get(), &[dead, value]);
+        call(&mut rt, weak_set_proto_add, set.get(), &[live.get()]);
+        call(&mut rt, weak_set_proto_add, set.get(), &[dead]);
+        assert_eq!(entries(map.get()), 2);
+        assert!(weak_map_proto_set(&mut rt, map.get(), &[value, value]).is_err());
+
+        rt.gc();
+        assert_eq!(entries(map.get()), 1);
+        assert_eq!(entries(set.get()), 1);
+        let value = call(&mut rt, weak_map_proto_get, map.get(), &[live.get()]);
+        rt.assert_live(value);
+        assert_eq!(string(value), "value");
+        let has = call(&mut rt, weak_set_proto_has, set.get(), &[live.get()]);
+        assert!(has == JSValue::true_());
+
+        // Keys moved, lookups must still find them.
+        rt.gc();
+        let deleted = call(&mut rt, weak_map_proto_delete, map.get(), &[live.get()]);
+        assert!(deleted == JSValue::true_());
+        assert_eq!(entries(map.get()), 0);
+    }
+
+    #[test]
+    fn ephemerons() {
+        let mut rt = Runtime::new();
+        let map = construct(&mut rt, "WeakMap", &[]);
+        assert!(weak_map_constructor(&mut rt, JSValue::undefined(), &[]).is_err());
+        let map = Persistent::new(&rt, map);
+        // key1 -> value1 refers to key2 -> value2, only key1 is rooted.
+        let (key1, key2) = (rt.new_object(), rt.new_object());
+        let key1 = Persistent::new(&rt, key1);
+        let (value1, value2) = (rt.new_object(), rt.new_string("value2"));
+        value1
+            .as_cell()
+            .unchecked_object_mut()
+            ._write("key", JSProperty::new().value(key2));
+        call(&mut rt, weak_map_proto_set, map.get(), &[key2, value2]);
+        call(
+            &mut rt,
+            weak_map_proto_set,
+            map.get(),
+            &[key1.get(), value1],
+        );
+        // A value referring to its own key does not keep the entry alive.
+        let cyclic = rt.new_object();
+        cyclic
+            .as_cell()
+            .unchecked_object_mut()
+            ._write("self", JSProperty::new().value(cyclic));
+        call(&mut rt, weak_map_proto_set, map.get(), &[cyclic, cyclic]);
+
+        rt.gc();
+        assert_eq!(entries(map.get()), 2);
+        let value1 = call(&mut rt, weak_map_proto_get, map.get(), &[key1.get()]);
+        let key2 = value1.as_cell().unchecked_object_mut()._read("key");
+        let key2 = key2.unwrap().value.unwrap();
+        let value2 = call(&mut rt, weak_map_proto_get, map.get(), &[key2]);
+        assert_eq!(string(value2), "value2");
+
+        drop(key1);
+        rt.gc();
+        assert_eq!(entries(map.get()), 0);
+    }
+
+    #[test]
+    fn weak_refs_keep_targets_for_the_current_job() {
+        let mut rt = Runtime::new();
+        let target = rt.new_object();
+        let weak_ref = construct(&mut rt, "WeakRef", &[target]);
+        let weak_ref = Persistent::new(&rt, weak_ref);
+        rt.gc();
+        let target = call(&mut rt, weak_ref_proto_deref, weak_ref.get(), &[]);
+        assert!(target.is_object());
+        rt.assert_live(target);
+        rt.clear_kept_objects();
+        rt.gc();
+        let target = call(&mut rt, weak_ref_proto_deref, weak_ref.get(), &[]);
+        assert!(target.is_undefined());
+    }
+
+    #[test]
+    fn finalization_registry_enqueues_cleanup() {
+        let mut rt = Runtime::new();
+        let log = Rc::new(RefCell::new(vec![]));
+        let cleaned = log.clone();
+        let cleanup = rt.new_native_function("cleanup", 1, false, move |_, _, args| {
+            cleaned.borrow_mut().push(string(args[0]));
+            Ok(JSValue::undefined())
+        });
+        let registry = construct(&mut rt, "FinalizationRegistry", &[cleanup]);
+        let registry = Persistent::new(&rt, registry);
+        let (target, kept, token) = (rt.new_object(), rt.new_object(), rt.new_object());
+        let (kept, token) = (Persistent::new(&rt, kept), Persistent::new(&rt, token));
+        let holdings = rt.new_string("holdings");
+        let register = finalization_registry_proto_register;
+        call(&mut rt, register, registry.get(), &[target, holdings]);
+        call(
+            &mut rt,
+            register,
+            registry.get(),
+            &[kept.get(), holdings, token.get()],
+        );
+        assert!(register(&mut rt, registry.get(), &[target, target]).is_err());
+
+        rt.gc();
+        assert_eq!(rt.jobs.len(), 1);
+        assert_eq!(string(rt.jobs[0].arguments[0]), "holdings");
+        rt.run_jobs().ok().unwrap();
+        assert!(rt.jobs.is_empty());
+        assert_eq!(*log.borrow(), ["holdings"]);
+
+        let unregister = finalization_registry_proto_unregister;
+        let removed = call(&mut rt, unregister, registry.get(), &[token.get()]);
+        assert!(removed == JSValue::true_());
+        drop(kept);
+        rt.gc();
+        assert!(rt.jobs.is_empty());
+    }
+}
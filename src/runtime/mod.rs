@@ -1,27 +1,374 @@
+pub mod arguments;
+pub mod atom;
+pub mod barrier;
+pub mod bigint;
 pub mod cell;
+pub mod comparison;
+pub mod conversions;
+pub mod convert;
+pub mod dtoa;
 pub mod environment;
+pub mod error;
+pub mod eval;
+pub mod function;
+pub mod heap_snapshot;
+pub mod heap_stats;
+pub mod host;
+pub mod job;
+pub mod jsbigint;
 pub mod jsobject;
 pub mod jsproperty;
+pub mod jsstring;
 pub mod jsvalue;
+pub mod jsweak;
+pub mod number;
+pub mod operations;
 pub mod pure_nan;
+pub mod rooting;
+pub mod string;
+pub mod value32_64;
+pub mod value64;
+pub mod value_repr;
 pub mod vtable;
+pub mod weak;
+use atom::*;
+use barrier::*;
+use cell::*;
 use cgc::heap::Heap;
+use error::*;
+use heap_stats::*;
+use job::*;
+use jsbigint::*;
 use jsobject::*;
-use jsproperty::*;
+use jsstring::*;
 use jsvalue::*;
+use rooting::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
 pub struct Runtime {
     pub heap: Heap,
     pub number: JSValue,
+    pub bigint: JSValue,
     pub boolean: JSValue,
     pub math: JSValue,
     pub object: JSValue,
     pub string: JSValue,
     pub symbol: JSValue,
+    /// `Function.prototype`, prototype of native functions.
+    pub function: JSValue,
     pub global: JSValue,
+    /// Outermost lexical environment, its object record is backed by `global`.
+    pub global_environment: JSValue,
+    pub error: JSValue,
+    pub eval_error: JSValue,
+    pub range_error: JSValue,
+    pub reference_error: JSValue,
+    pub syntax_error: JSValue,
+    pub type_error: JSValue,
+    pub uri_error: JSValue,
+    pub weak_map: JSValue,
+    pub weak_set: JSValue,
+    pub weak_ref: JSValue,
+    pub finalization_registry: JSValue,
+    /// `%ThrowTypeError%`, see [arguments].
+    pub throw_type_error: JSValue,
+    /// Interpreter frames, the innermost one is last.
+    pub frames: Vec<CallFrame>,
+    /// Interned strings, see [Runtime::intern].
+    pub atoms: AtomTable,
+    /// Slots of handle scopes and persistent handles, see [rooting].
+    pub roots: Rc<RefCell<Roots>>,
+    /// Collect garbage before every allocation, makes values that native code forgot to root fail
+    /// fast instead of when the heap happens to fill up.
+    pub gc_stress: bool,
+    /// Old cells pointing at young ones, see [barrier].
+    pub remembered_set: RememberedSet,
+    /// WeakMap, WeakSet, WeakRef and FinalizationRegistry objects, see [jsweak].
+    pub weak_containers: Vec<JSValue>,
+    /// Objects with host data, see [host].
+    pub host_objects: Vec<JSValue>,
+    pub jobs: VecDeque<Job>,
+    /// Targets of WeakRefs created or dereferenced by the current job.
+    pub kept_alive: Vec<JSValue>,
+    /// `new.target` of active native calls, see [Runtime::new_target].
+    pub new_targets: Vec<JSValue>,
+    /// Heap usage, see [heap_stats].
+    pub heap_stats: HeapStatistics,
+    /// Approximate limit of the heap size in bytes, unlimited if `None`.
+    pub max_heap_size: Option<usize>,
+    /// Error of an infallible allocation that exceeded `max_heap_size`, thrown by
+    /// [Runtime::check_out_of_memory].
+    pub pending_out_of_memory: Option<OutOfMemory>,
 }
 
 impl Runtime {
-    pub fn execute(&mut self, _: JSValue, _: JSValue, _: &[JSValue]) -> Result<JSValue, JSValue> {
-        Ok(JSValue::undefined())
+    pub fn new() -> Self {
+        let mut rt = Self {
+            heap: Heap::new(4 * 1024 * 1024, 16 * 1024 * 1024, true),
+            number: JSValue::undefined(),
+            bigint: JSValue::undefined(),
+            boolean: JSValue::undefined(),
+            math: JSValue::undefined(),
+            object: JSValue::undefined(),
+            string: JSValue::undefined(),
+            symbol: JSValue::undefined(),
+            function: JSValue::undefined(),
+            global: JSValue::undefined(),
+            global_environment: JSValue::undefined(),
+            error: JSValue::undefined(),
+            eval_error: JSValue::undefined(),
+            range_error: JSValue::undefined(),
+            reference_error: JSValue::undefined(),
+            syntax_error: JSValue::undefined(),
+            type_error: JSValue::undefined(),
+            uri_error: JSValue::undefined(),
+            weak_map: JSValue::undefined(),
+            weak_set: JSValue::undefined(),
+            weak_ref: JSValue::undefined(),
+            finalization_registry: JSValue::undefined(),
+            throw_type_error: JSValue::undefined(),
+            frames: vec![],
+            atoms: AtomTable::new(),
+            roots: Rc::new(RefCell::new(Roots::new())),
+            gc_stress: false,
+            remembered_set: RememberedSet::new(),
+            weak_containers: vec![],
+            host_objects: vec![],
+            jobs: VecDeque::new(),
+            kept_alive: vec![],
+            new_targets: vec![],
+            heap_stats: HeapStatistics::default(),
+            max_heap_size: None,
+            pending_out_of_memory: None,
+        };
+        rt.object = rt.allocate(Cell::Object(Box::new(JSObject::new(
+            JSObjectKind::Normal,
+            None,
+            "Object",
+        ))));
+        rt.function = rt.new_native_function("", 0, false, |_, _, _| Ok(JSValue::undefined()));
+        let (function, object) = (rt.function, rt.object);
+        vtable::object_set_prototype_of(&mut rt, function, object);
+        rt.throw_type_error = arguments::new_throw_type_error(&mut rt);
+        rt.number = rt.new_object_with_kind(JSObjectKind::Number, "Number");
+        rt.bigint = rt.new_object_with_kind(JSObjectKind::Normal, "BigInt");
+        rt.boolean = rt.new_object_with_kind(JSObjectKind::Boolean, "Boolean");
+        rt.string = rt.new_object_with_kind(JSObjectKind::String, "String");
+        rt.symbol = rt.new_object_with_kind(JSObjectKind::Symbol, "Symbol");
+        rt.math = rt.new_object_with_kind(JSObjectKind::Normal, "Math");
+        rt.weak_map = rt.new_object_with_kind(JSObjectKind::Normal, "WeakMap");
+        rt.weak_set = rt.new_object_with_kind(JSObjectKind::Normal, "WeakSet");
+        rt.weak_ref = rt.new_object_with_kind(JSObjectKind::Normal, "WeakRef");
+        rt.finalization_registry =
+            rt.new_object_with_kind(JSObjectKind::Normal, "FinalizationRegistry");
+        rt.global = rt.new_object_with_kind(JSObjectKind::Normal, "global");
+        let object = rt.object;
+        rt.error = new_error_prototype(&mut rt, ErrorKind::Error, object);
+        let error = rt.error;
+        rt.eval_error = new_error_prototype(&mut rt, ErrorKind::EvalError, error);
+        rt.range_error = new_error_prototype(&mut rt, ErrorKind::RangeError, error);
+        rt.reference_error = new_error_prototype(&mut rt, ErrorKind::ReferenceError, error);
+        rt.syntax_error = new_error_prototype(&mut rt, ErrorKind::SyntaxError, error);
+        rt.type_error = new_error_prototype(&mut rt, ErrorKind::TypeError, error);
+        rt.uri_error = new_error_prototype(&mut rt, ErrorKind::URIError, error);
+        let global = rt.global;
+        rt.global_environment = environment::new_global_environment(&mut rt, global, global);
+        error::install(&mut rt);
+        number::install(&mut rt);
+        string::install(&mut rt);
+        bigint::install(&mut rt);
+        weak::install(&mut rt);
+        rt
+    }
+
+    /// Moves `cell` into the GC heap. Exceeding [Runtime::max_heap_size] leaves an error pending,
+    /// see [Runtime::try_allocate] for the fallible version.
+    pub fn allocate(&mut self, cell: Cell) -> JSValue {
+        let size = cell_size(&cell);
+        if self.gc_stress {
+            self.collect_garbage(Some(&cell));
+        } else if self.pending_out_of_memory.is_none() {
+            if let Err(error) = self.reserve_heap(size, &cell) {
+                self.pending_out_of_memory = Some(error);
+            }
+        }
+        let value = JSValue::cell(self.heap.allocate(cell).to_heap());
+        self.heap_stats.allocated_bytes += size;
+        #[cfg(debug_assertions)]
+        self.register_cell(value);
+        value
+    }
+
+    /// [Runtime::try_allocate] for allocations whose size depends on the script, such as string
+    /// concatenation, substrings and arrays. Throws RangeError if the heap limit is exceeded.
+    pub(crate) fn allocate_or_throw(&mut self, cell: Cell) -> Result<JSValue, JSValue> {
+        self.try_allocate(cell)
+            .map_err(|_| self.new_out_of_memory_error())
+    }
+
+    pub fn new_string(&mut self, s: &str) -> JSValue {
+        self.allocate(Cell::String(Box::new(JSString::new(s))))
+    }
+
+    pub fn new_bigint(&mut self, value: JSBigInt) -> JSValue {
+        self.allocate(Cell::BigInt(Box::new(value)))
+    }
+
+    /// Creates a string from UTF-16 code units, which may include lone surrogates.
+    pub fn new_string_from_utf16(&mut self, units: &[u16]) -> JSValue {
+        self.allocate(Cell::String(Box::new(JSString::from_utf16(units))))
+    }
+
+    /// [Runtime::new_string] for strings of any length, throws RangeError if the string does not
+    /// fit under [Runtime::max_heap_size].
+    pub fn try_new_string(&mut self, s: &str) -> Result<JSValue, JSValue> {
+        self.allocate_or_throw(Cell::String(Box::new(JSString::new(s))))
+    }
+
+    /// [Runtime::new_string_from_utf16] for strings built from script data, throws RangeError if
+    /// the string does not fit under [Runtime::max_heap_size].
+    pub fn try_new_string_from_utf16(&mut self, units: &[u16]) -> Result<JSValue, JSValue> {
+        self.allocate_or_throw(Cell::String(Box::new(JSString::from_utf16(units))))
+    }
+
+    /// Concatenates strings `left` and `right`, throws RangeError if the result would be longer
+    /// than [MAX_STRING_LENGTH]. Long results are ropes, so `+=` in a loop does not copy the
+    /// accumulated string every iteration.
+    pub fn concat_strings(&mut self, left: JSValue, right: JSValue) -> Result<JSValue, JSValue> {
+        self.assert_live(left);
+        self.assert_live(right);
+        let (l, r) = (left.as_cell(), right.as_cell());
+        let (l, r) = (l.unchecked_string(), r.unchecked_string());
+        if r.is_empty() {
+            return Ok(left);
+        }
+        if l.is_empty() {
+            return Ok(right);
+        }
+        let length = l.len() + r.len();
+        if length > MAX_STRING_LENGTH {
+            return Err(self.new_range_error("Invalid string length"));
+        }
+        if length < MIN_ROPE_LENGTH {
+            let units: Vec<u16> = l.code_units().chain(r.code_units()).collect();
+            return self.try_new_string_from_utf16(&units);
+        }
+        let rope = JSString::rope(left.as_cell(), right.as_cell());
+        self.allocate_or_throw(Cell::String(Box::new(rope)))
+    }
+
+    /// Creates a string of code units `start..end` of string `s`. Long substrings share the
+    /// buffer of `s`, which is flattened first if it is a rope. Throws RangeError if the substring
+    /// does not fit under [Runtime::max_heap_size].
+    pub fn new_substring(
+        &mut self,
+        s: JSValue,
+        start: usize,
+        end: usize,
+    ) -> Result<JSValue, JSValue> {
+        self.assert_live(s);
+        let mut cell = s.as_cell();
+        let string = cell.unchecked_string_mut();
+        assert!(start <= end && end <= string.len());
+        if start == 0 && end == string.len() {
+            return Ok(s);
+        }
+        string.flatten();
+        if end - start < MIN_SUBSTRING_LENGTH {
+            let view = string.view();
+            let units: Vec<u16> = (start..end).map(|index| view.at(index)).collect();
+            return self.try_new_string_from_utf16(&units);
+        }
+        let (base, offset) = string.substring_base().unwrap_or((cell, 0));
+        let substring = JSString::substring(base, offset + start, offset + end);
+        self.allocate_or_throw(Cell::String(Box::new(substring)))
+    }
+
+    /// Creates an ordinary object inheriting from `Object.prototype`.
+    pub fn new_object(&mut self) -> JSValue {
+        self.new_object_with_kind(JSObjectKind::Normal, "Object")
+    }
+
+    pub fn new_object_with_kind(&mut self, kind: JSObjectKind, class: &str) -> JSValue {
+        let proto = self.object;
+        self.allocate(Cell::Object(Box::new(JSObject::new(
+            kind,
+            Some(proto),
+            class,
+        ))))
+    }
+
+    /// Creates an array with `elements`, throws RangeError if it does not fit under
+    /// [Runtime::max_heap_size].
+    pub fn new_array(&mut self, elements: Vec<JSValue>) -> Result<JSValue, JSValue> {
+        let proto = self.object;
+        let array = JSObject::new(JSObjectKind::Array(elements), Some(proto), "Array");
+        self.allocate_or_throw(Cell::Object(Box::new(array)))
+    }
+
+    /// [ToObject](https://tc39.es/ecma262/#sec-toobject)
+    ///
+    /// Primitives are wrapped into objects keeping the primitive in `PrimitiveValue` internal slot.
+    pub fn to_object(&mut self, value: JSValue) -> Result<JSValue, JSValue> {
+        if value.is_undefined_or_null() {
+            return Err(self.new_type_error("Cannot convert undefined or null to object"));
+        }
+        self.assert_live(value);
+        let (kind, class) = if value.is_number() {
+            (JSObjectKind::Number, "Number")
+        } else if value.is_boolean() {
+            (JSObjectKind::Boolean, "Boolean")
+        } else {
+            match &*value.as_cell() {
+                Cell::String(_) => (JSObjectKind::String, "String"),
+                Cell::BigInt(_) => (JSObjectKind::BigInt, "BigInt"),
+                _ => return Ok(value),
+            }
+        };
+        let proto = value.get_object(self);
+        let mut wrapper = JSObject::new(kind, Some(proto), class);
+        wrapper.set_internal("PrimitiveValue", value);
+        Ok(self.allocate(Cell::Object(Box::new(wrapper))))
+    }
+
+    /// Returns prototype of error objects of `kind`.
+    pub fn error_prototype(&self, kind: ErrorKind) -> JSValue {
+        match kind {
+            ErrorKind::Error => self.error,
+            ErrorKind::EvalError => self.eval_error,
+            ErrorKind::RangeError => self.range_error,
+            ErrorKind::ReferenceError => self.reference_error,
+            ErrorKind::SyntaxError => self.syntax_error,
+            ErrorKind::TypeError => self.type_error,
+            ErrorKind::URIError => self.uri_error,
+        }
+    }
+
+    /// Creates an error object of `kind` with stack trace of the current frames.
+    pub fn new_error(&mut self, kind: ErrorKind, message: &str) -> JSValue {
+        new_error_object(self, kind, Some(message), None)
+    }
+
+    pub fn new_type_error(&mut self, message: &str) -> JSValue {
+        self.new_error(ErrorKind::TypeError, message)
+    }
+
+    pub fn new_reference_error(&mut self, message: &str) -> JSValue {
+        self.new_error(ErrorKind::ReferenceError, message)
+    }
+
+    pub fn new_range_error(&mut self, message: &str) -> JSValue {
+        self.new_error(ErrorKind::RangeError, message)
+    }
+
+    pub fn new_syntax_error(&mut self, message: &str) -> JSValue {
+        self.new_error(ErrorKind::SyntaxError, message)
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
     }
 }
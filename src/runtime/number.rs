@@ -0,0 +1,184 @@
+//! `Number.prototype` methods converting numbers to strings.
+//!
+//! These take `this` and arguments the way native functions are called and are installed on
+//! `Number.prototype` as they are.
+use super::cell::*;
+use super::dtoa::*;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::Runtime;
+
+fn argument(args: &[JSValue], index: usize) -> JSValue {
+    args.get(index).copied().unwrap_or_else(JSValue::undefined)
+}
+
+/// [thisNumberValue](https://tc39.es/ecma262/#sec-thisnumbervalue)
+pub fn this_number_value(rt: &mut Runtime, this: JSValue, method: &str) -> Result<f64, JSValue> {
+    if this.is_number() {
+        return Ok(this.as_number());
+    }
+    if this.is_object() {
+        let primitive = match &*this.as_cell() {
+            Cell::Object(object) if matches!(object.kind, JSObjectKind::Number) => {
+                object.get_internal("PrimitiveValue")
+            }
+            _ => JSValue::undefined(),
+        };
+        if primitive.is_number() {
+            return Ok(primitive.as_number());
+        }
+    }
+    let msg = format!(
+        "Number.prototype.{} requires that 'this' be a Number",
+        method
+    );
+    Err(rt.new_type_error(&msg))
+}
+
+/// Converts digits argument and checks that it is in `min..=100`.
+fn digits_argument(rt: &mut Runtime, value: f64, min: f64, method: &str) -> Result<u32, JSValue> {
+    if !(min..=100.0).contains(&value) {
+        let msg = format!("{}() argument must be between {} and 100", method, min);
+        return Err(rt.new_range_error(&msg));
+    }
+    Ok(value as u32)
+}
+
+/// [Number.prototype.toString](https://tc39.es/ecma262/#sec-number.prototype.tostring)
+pub fn number_proto_to_string(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let x = this_number_value(rt, this, "toString")?;
+    let radix = argument(args, 0);
+    let radix = if radix.is_undefined() {
+        10.0
+    } else {
+        radix.to_integer_or_infinity(rt)?
+    };
+    if !(2.0..=36.0).contains(&radix) {
+        return Err(rt.new_range_error("toString() radix must be between 2 and 36"));
+    }
+    Ok(rt.new_string(&number_to_radix_string(x, radix as u32)))
+}
+
+/// [Number.prototype.toFixed](https://tc39.es/ecma262/#sec-number.prototype.tofixed)
+pub fn number_proto_to_fixed(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let x = this_number_value(rt, this, "toFixed")?;
+    let f = argument(args, 0).to_integer_or_infinity(rt)?;
+    let f = digits_argument(rt, f, 0.0, "toFixed")?;
+    Ok(rt.new_string(&number_to_fixed(x, f)))
+}
+
+/// [Number.prototype.toExponential](https://tc39.es/ecma262/#sec-number.prototype.toexponential)
+pub fn number_proto_to_exponential(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let x = this_number_value(rt, this, "toExponential")?;
+    let fraction_digits = argument(args, 0);
+    let f = fraction_digits.to_integer_or_infinity(rt)?;
+    if !x.is_finite() {
+        return Ok(rt.new_string(&number_to_string(x)));
+    }
+    let f = digits_argument(rt, f, 0.0, "toExponential")?;
+    let f = if fraction_digits.is_undefined() {
+        None
+    } else {
+        Some(f)
+    };
+    Ok(rt.new_string(&number_to_exponential(x, f)))
+}
+
+/// [Number.prototype.toPrecision](https://tc39.es/ecma262/#sec-number.prototype.toprecision)
+pub fn number_proto_to_precision(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let x = this_number_value(rt, this, "toPrecision")?;
+    let precision = argument(args, 0);
+    if precision.is_undefined() {
+        return Ok(rt.new_string(&number_to_string(x)));
+    }
+    let p = precision.to_integer_or_infinity(rt)?;
+    if !x.is_finite() {
+        return Ok(rt.new_string(&number_to_string(x)));
+    }
+    let p = digits_argument(rt, p, 1.0, "toPrecision")?;
+    Ok(rt.new_string(&number_to_precision(x, p)))
+}
+
+pub(crate) fn install(rt: &mut Runtime) {
+    let proto = rt.number;
+    rt.define_native_method(proto, "toString", 1, number_proto_to_string);
+    rt.define_native_method(proto, "toFixed", 1, number_proto_to_fixed);
+    rt.define_native_method(proto, "toExponential", 1, number_proto_to_exponential);
+    rt.define_native_method(proto, "toPrecision", 1, number_proto_to_precision);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::error::JSError;
+
+    fn string(value: JSValue) -> String {
+        value.as_cell().unchecked_string().to_string()
+    }
+
+    fn range_error(rt: &mut Runtime, result: Result<JSValue, JSValue>) -> String {
+        let error = JSError::from_value(rt, result.err().unwrap());
+        assert_eq!(error.name, "RangeError");
+        error.message
+    }
+
+    #[test]
+    fn number_methods() {
+        let mut rt = Runtime::new();
+        let x = JSValue::new_double(-3.75);
+        let result = number_proto_to_string(&mut rt, x, &[JSValue::new_int(2)]);
+        assert_eq!(string(result.ok().unwrap()), "-11.11");
+        let result =
+            number_proto_to_fixed(&mut rt, JSValue::new_double(1.005), &[JSValue::new_int(2)]);
+        assert_eq!(string(result.ok().unwrap()), "1.00");
+        let result = number_proto_to_exponential(&mut rt, x, &[]);
+        assert_eq!(string(result.ok().unwrap()), "-3.75e+0");
+        let result = number_proto_to_precision(&mut rt, x, &[]);
+        assert_eq!(string(result.ok().unwrap()), "-3.75");
+        // Infinity ignores the digits argument instead of throwing.
+        let infinity = JSValue::new_double(f64::INFINITY);
+        let result = number_proto_to_precision(&mut rt, infinity, &[JSValue::new_int(0)]);
+        assert_eq!(string(result.ok().unwrap()), "Infinity");
+
+        let result = number_proto_to_string(&mut rt, x, &[JSValue::new_int(37)]);
+        assert_eq!(
+            range_error(&mut rt, result),
+            "toString() radix must be between 2 and 36"
+        );
+        let result = number_proto_to_fixed(&mut rt, x, &[JSValue::new_int(101)]);
+        assert_eq!(
+            range_error(&mut rt, result),
+            "toFixed() argument must be between 0 and 100"
+        );
+        let result = number_proto_to_precision(&mut rt, x, &[JSValue::new_int(0)]);
+        assert_eq!(
+            range_error(&mut rt, result),
+            "toPrecision() argument must be between 1 and 100"
+        );
+
+        let this = rt.new_string("1");
+        let error = number_proto_to_fixed(&mut rt, this, &[]).err().unwrap();
+        let error = JSError::from_value(&mut rt, error);
+        assert_eq!(error.name, "TypeError");
+        assert_eq!(
+            error.message,
+            "Number.prototype.toFixed requires that 'this' be a Number"
+        );
+    }
+}
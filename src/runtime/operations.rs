@@ -0,0 +1,401 @@
+//! Arithmetic, bitwise and shift operators.
+//!
+//! These implement the operators of the `Binary` and `ProfiledBinary` opcode groups and the unary
+//! arithmetic opcodes. Operands are converted with ToNumeric, two Numbers use IEEE arithmetic and two
+//! BigInts use arbitrary precision. Mixing a BigInt with a Number throws TypeError.
+use super::conversions::*;
+use super::jsbigint::*;
+use super::jsvalue::*;
+use super::rooting::*;
+use super::Runtime;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    LShift,
+    RShift,
+    URShift,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+/// Boxes a Number, using int32 encoding when it is exact.
+fn number(x: f64) -> JSValue {
+    let int = x as i32;
+    if int as f64 == x && !(x == 0.0 && x.is_sign_negative()) {
+        JSValue::new_int(int)
+    } else {
+        JSValue::new_double(x)
+    }
+}
+
+/// [Number::exponentiate](https://tc39.es/ecma262/#sec-numeric-types-number-exponentiate), differs
+/// from `powf` for NaN exponents and for `(+-1) ** (+-Infinity)`.
+fn exponentiate(base: f64, exponent: f64) -> f64 {
+    if exponent.is_nan() || (base.abs() == 1.0 && exponent.is_infinite()) {
+        return f64::NAN;
+    }
+    base.powf(exponent)
+}
+
+fn number_operator(op: Operator, x: f64, y: f64) -> f64 {
+    let shift = f64_to_uint32(y) & 31;
+    match op {
+        Operator::Add => x + y,
+        Operator::Sub => x - y,
+        Operator::Mul => x * y,
+        Operator::Div => x / y,
+        Operator::Rem => x % y,
+        Operator::Pow => exponentiate(x, y),
+        Operator::LShift => f64_to_int32(x).wrapping_shl(shift) as f64,
+        Operator::RShift => (f64_to_int32(x) >> shift) as f64,
+        Operator::URShift => (f64_to_uint32(x) >> shift) as f64,
+        Operator::BitAnd => (f64_to_int32(x) & f64_to_int32(y)) as f64,
+        Operator::BitOr => (f64_to_int32(x) | f64_to_int32(y)) as f64,
+        Operator::BitXor => (f64_to_int32(x) ^ f64_to_int32(y)) as f64,
+    }
+}
+
+fn bigint_operator(op: Operator, x: &JSBigInt, y: &JSBigInt) -> Result<JSBigInt, BigIntError> {
+    match op {
+        Operator::Add => Ok(x.add(y)),
+        Operator::Sub => Ok(x.sub(y)),
+        Operator::Mul => x.mul(y),
+        Operator::Div => x.div(y),
+        Operator::Rem => x.rem(y),
+        Operator::Pow => x.pow(y),
+        Operator::LShift => x.shl(y),
+        Operator::RShift => x.shr(y),
+        Operator::URShift => unreachable!(),
+        Operator::BitAnd => Ok(x.bitand(y)),
+        Operator::BitOr => Ok(x.bitor(y)),
+        Operator::BitXor => Ok(x.bitxor(y)),
+    }
+}
+
+fn bigint_result(
+    rt: &mut Runtime,
+    result: Result<JSBigInt, BigIntError>,
+) -> Result<JSValue, JSValue> {
+    match result {
+        Ok(n) => Ok(rt.new_bigint(n)),
+        Err(e) => Err(rt.new_range_error(e.message())),
+    }
+}
+
+/// [ApplyStringOrNumericBinaryOperator](https://tc39.es/ecma262/#sec-applystringornumericbinaryoperator)
+/// for everything except string concatenation.
+fn apply(rt: &mut Runtime, op: Operator, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    let scope = HandleScope::new(rt);
+    let y = scope.root(y);
+    let x = scope.root(x.to_numeric(rt)?);
+    let y = y.get().to_numeric(rt)?;
+    let x = x.get();
+    match (x.is_bigint(), y.is_bigint()) {
+        (false, false) => Ok(number(number_operator(op, x.as_number(), y.as_number()))),
+        (true, true) => {
+            if op == Operator::URShift {
+                let msg = "BigInts have no unsigned right shift, use >> instead";
+                return Err(rt.new_type_error(msg));
+            }
+            let result = bigint_operator(op, x.as_bigint(), y.as_bigint());
+            bigint_result(rt, result)
+        }
+        _ => Err(rt.new_type_error("Cannot mix BigInt and other types, use explicit conversions")),
+    }
+}
+
+/// `x + y`, concatenates if either operand is a string after ToPrimitive.
+pub fn add(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    if x.is_int32() && y.is_int32() {
+        return Ok(number(x.as_int32() as f64 + y.as_int32() as f64));
+    }
+    let scope = HandleScope::new(rt);
+    let mut y = scope.root(y);
+    let x = scope.root(x.to_primitive(rt, PreferredType::Default)?);
+    y.set(y.get().to_primitive(rt, PreferredType::Default)?);
+    if x.get().is_string() || y.get().is_string() {
+        let x = scope.root(x.get().to_js_string(rt)?);
+        let y = y.get().to_js_string(rt)?;
+        return rt.concat_strings(x.get(), y);
+    }
+    apply(rt, Operator::Add, x.get(), y.get())
+}
+
+/// `x - y`
+pub fn sub(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::Sub, x, y)
+}
+
+/// `x * y`
+pub fn mul(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::Mul, x, y)
+}
+
+/// `x / y`, BigInt division truncates and throws RangeError on division by zero.
+pub fn div(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::Div, x, y)
+}
+
+/// `x % y`, the result has the sign of `x`.
+pub fn rem(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::Rem, x, y)
+}
+
+/// `x ** y`
+pub fn pow(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::Pow, x, y)
+}
+
+/// `x << y`
+pub fn lshift(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::LShift, x, y)
+}
+
+/// `x >> y`
+pub fn rshift(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::RShift, x, y)
+}
+
+/// `x >>> y`, throws TypeError for BigInts.
+pub fn urshift(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::URShift, x, y)
+}
+
+/// `x & y`
+pub fn bitand(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::BitAnd, x, y)
+}
+
+/// `x | y`
+pub fn bitor(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::BitOr, x, y)
+}
+
+/// `x ^ y`
+pub fn bitxor(rt: &mut Runtime, x: JSValue, y: JSValue) -> Result<JSValue, JSValue> {
+    apply(rt, Operator::BitXor, x, y)
+}
+
+/// `-x`
+pub fn negate(rt: &mut Runtime, x: JSValue) -> Result<JSValue, JSValue> {
+    let x = x.to_numeric(rt)?;
+    if x.is_bigint() {
+        let result = x.as_bigint().neg();
+        return Ok(rt.new_bigint(result));
+    }
+    Ok(number(-x.as_number()))
+}
+
+/// `~x`
+pub fn bitnot(rt: &mut Runtime, x: JSValue) -> Result<JSValue, JSValue> {
+    let x = x.to_numeric(rt)?;
+    if x.is_bigint() {
+        let result = x.as_bigint().bitnot();
+        return Ok(rt.new_bigint(result));
+    }
+    Ok(number(!f64_to_int32(x.as_number()) as f64))
+}
+
+fn increment(rt: &mut Runtime, x: JSValue, delta: i64) -> Result<JSValue, JSValue> {
+    let x = x.to_numeric(rt)?;
+    if x.is_bigint() {
+        let result = x.as_bigint().add(&JSBigInt::from_i64(delta));
+        return Ok(rt.new_bigint(result));
+    }
+    Ok(number(x.as_number() + delta as f64))
+}
+
+/// `x + 1` of `++x` and `x++`, the operand is converted with ToNumeric.
+pub fn inc(rt: &mut Runtime, x: JSValue) -> Result<JSValue, JSValue> {
+    increment(rt, x, 1)
+}
+
+/// `x - 1` of `--x` and `x--`, the operand is converted with ToNumeric.
+pub fn dec(rt: &mut Runtime, x: JSValue) -> Result<JSValue, JSValue> {
+    increment(rt, x, -1)
+}
+
+/// [typeof](https://tc39.es/ecma262/#sec-typeof-operator)
+pub fn type_of(value: JSValue) -> &'static str {
+    if value.is_undefined() {
+        "undefined"
+    } else if value.is_null() {
+        "object"
+    } else if value.is_boolean() {
+        "boolean"
+    } else if value.is_number() {
+        "number"
+    } else if value.is_string() {
+        "string"
+    } else if value.is_bigint() {
+        "bigint"
+    } else if value.is_callable() {
+        "function"
+    } else {
+        "object"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::bigint::*;
+    use crate::runtime::comparison::*;
+    use crate::runtime::error::JSError;
+
+    /// Literal with optional minus sign, like `-7n`.
+    fn bigint(rt: &mut Runtime, literal: &str) -> JSValue {
+        let n = match literal.strip_prefix('-') {
+            Some(literal) => JSBigInt::parse_literal(literal).unwrap().neg(),
+            None => JSBigInt::parse_literal(literal).unwrap(),
+        };
+        rt.new_bigint(n)
+    }
+
+    /// Result of `f` as string, or name of the thrown error.
+    fn run(rt: &mut Runtime, f: impl FnOnce(&mut Runtime) -> Result<JSValue, JSValue>) -> String {
+        match f(rt) {
+            Ok(value) => value.to_string(rt).ok().unwrap(),
+            Err(error) => JSError::from_value(rt, error).name,
+        }
+    }
+
+    #[test]
+    fn bigint_arithmetic() {
+        let mut rt = Runtime::new();
+        let rt = &mut rt;
+        let x = bigint(rt, "9007199254740993n");
+        let two = bigint(rt, "2n");
+        let minus_seven = bigint(rt, "-7n");
+        let minus_one = bigint(rt, "-1n");
+        let sixty_four = bigint(rt, "64n");
+        assert_eq!(run(rt, |rt| mul(rt, x, two)), "18014398509481986");
+        assert_eq!(run(rt, |rt| add(rt, x, two)), "9007199254740995");
+        assert_eq!(run(rt, |rt| sub(rt, two, x)), "-9007199254740991");
+        assert_eq!(
+            run(rt, |rt| pow(rt, two, sixty_four)),
+            "18446744073709551616"
+        );
+        assert_eq!(run(rt, |rt| div(rt, minus_seven, two)), "-3");
+        assert_eq!(run(rt, |rt| rem(rt, minus_seven, two)), "-1");
+        assert_eq!(run(rt, |rt| rshift(rt, minus_seven, two)), "-2");
+        assert_eq!(run(rt, |rt| lshift(rt, minus_seven, two)), "-28");
+        assert_eq!(run(rt, |rt| bitand(rt, minus_one, x)), "9007199254740993");
+        assert_eq!(run(rt, |rt| bitxor(rt, minus_one, two)), "-3");
+        assert_eq!(run(rt, |rt| bitor(rt, minus_seven, two)), "-5");
+        assert_eq!(run(rt, |rt| bitnot(rt, x)), "-9007199254740994");
+        assert_eq!(run(rt, |rt| inc(rt, x)), "9007199254740994");
+        assert_eq!(run(rt, |rt| dec(rt, x)), "9007199254740992");
+        assert_eq!(run(rt, |rt| negate(rt, x)), "-9007199254740993");
+        let s = rt.new_string("n = ");
+        assert_eq!(run(rt, |rt| add(rt, s, x)), "n = 9007199254740993");
+    }
+
+    #[test]
+    fn number_arithmetic() {
+        let mut rt = Runtime::new();
+        let rt = &mut rt;
+        let n = JSValue::new_double;
+        assert_eq!(run(rt, |rt| add(rt, n(0.1), n(0.2))), "0.30000000000000004");
+        assert_eq!(run(rt, |rt| rem(rt, n(-7.0), n(2.0))), "-1");
+        assert_eq!(run(rt, |rt| pow(rt, n(1.0), n(f64::INFINITY))), "NaN");
+        assert_eq!(run(rt, |rt| urshift(rt, n(-1.0), n(0.0))), "4294967295");
+        assert_eq!(run(rt, |rt| lshift(rt, n(1.0), n(33.0))), "2");
+        assert_eq!(run(rt, |rt| bitnot(rt, n(4294967296.5))), "-1");
+        let s = rt.new_string("3");
+        assert_eq!(run(rt, |rt| mul(rt, s, n(2.0))), "6");
+        assert_eq!(run(rt, |rt| add(rt, s, n(2.0))), "32");
+    }
+
+    #[test]
+    fn bigint_errors() {
+        let mut rt = Runtime::new();
+        let rt = &mut rt;
+        let x = bigint(rt, "1n");
+        let minus_x = bigint(rt, "-1n");
+        let zero = bigint(rt, "0n");
+        let one = JSValue::new_int(1);
+        assert_eq!(run(rt, |rt| add(rt, x, one)), "TypeError");
+        assert_eq!(run(rt, |rt| mul(rt, one, x)), "TypeError");
+        assert_eq!(run(rt, |rt| urshift(rt, x, x)), "TypeError");
+        assert_eq!(run(rt, |rt| div(rt, x, zero)), "RangeError");
+        assert_eq!(run(rt, |rt| rem(rt, x, zero)), "RangeError");
+        assert_eq!(run(rt, |rt| pow(rt, x, minus_x)), "RangeError");
+        assert!(x.to_number(rt).is_err());
+    }
+
+    #[test]
+    fn bigint_comparison() {
+        let mut rt = Runtime::new();
+        let rt = &mut rt;
+        let x = bigint(rt, "1n");
+        let also_x = bigint(rt, "1n");
+        assert!(strict_equals(x, also_x));
+        assert!(same_value(x, also_x));
+        assert!(!strict_equals(x, JSValue::new_int(1)));
+        assert!(abstract_equals(rt, x, JSValue::new_int(1)).ok().unwrap());
+        let s = rt.new_string(" 1 ");
+        assert!(abstract_equals(rt, s, x).ok().unwrap());
+        assert!(abstract_equals(rt, x, JSValue::true_()).ok().unwrap());
+        assert!(!abstract_equals(rt, x, JSValue::new_double(1.5))
+            .ok()
+            .unwrap());
+        assert!(less(rt, x, JSValue::new_double(1.5)).ok().unwrap());
+        assert!(greater(rt, x, JSValue::new_double(0.5)).ok().unwrap());
+        assert!(!less(rt, x, JSValue::new_double(f64::NAN)).ok().unwrap());
+        let s = rt.new_string("2");
+        assert!(less(rt, x, s).ok().unwrap());
+        let s = rt.new_string("x");
+        assert!(!less_eq(rt, x, s).ok().unwrap());
+        let big = bigint(rt, "100000000000000000000000000000n");
+        assert!(greater(rt, big, JSValue::new_double(1e28)).ok().unwrap());
+        assert!(less(rt, big, JSValue::new_double(1e30)).ok().unwrap());
+    }
+
+    #[test]
+    fn typeof_and_builtins() {
+        let mut rt = Runtime::new();
+        let rt = &mut rt;
+        let x = bigint(rt, "255n");
+        let undefined = JSValue::undefined();
+        assert_eq!(type_of(x), "bigint");
+        assert_eq!(type_of(JSValue::new_int(1)), "number");
+        let bits = JSValue::new_int(8);
+        assert_eq!(
+            run(rt, |rt| bigint_as_int_n(rt, undefined, &[bits, x])),
+            "-1"
+        );
+        assert_eq!(
+            run(rt, |rt| bigint_as_uint_n(rt, undefined, &[bits, x])),
+            "255"
+        );
+        let radix = JSValue::new_int(16);
+        assert_eq!(run(rt, |rt| bigint_proto_to_string(rt, x, &[radix])), "ff");
+        let s = rt.new_string("0x10");
+        assert_eq!(run(rt, |rt| bigint_constructor(rt, undefined, &[s])), "16");
+        let n = JSValue::new_double(1e21);
+        assert_eq!(
+            run(rt, |rt| bigint_constructor(rt, undefined, &[n])),
+            "1000000000000000000000"
+        );
+        let n = JSValue::new_double(1.5);
+        assert_eq!(
+            run(rt, |rt| bigint_constructor(rt, undefined, &[n])),
+            "RangeError"
+        );
+        let s = rt.new_string("1.5");
+        assert_eq!(
+            run(rt, |rt| bigint_constructor(rt, undefined, &[s])),
+            "SyntaxError"
+        );
+        let wrapper = rt.to_object(x).ok().unwrap();
+        assert_eq!(run(rt, |rt| bigint_proto_value_of(rt, wrapper, &[])), "255");
+    }
+}
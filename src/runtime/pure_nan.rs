@@ -37,12 +37,12 @@ pub fn pure_nan() -> f64 {
 }
 
 pub fn is_impure_nan(value: f64) -> bool {
-    return value.to_bits() >= 0xfffe000000000000u64;
+    value.to_bits() >= 0xfffe000000000000u64
 }
 
 pub fn purify_nan(value: f64) -> f64 {
-    if value != value {
+    if value.is_nan() {
         return pure_nan();
     }
-    return value;
+    value
 }
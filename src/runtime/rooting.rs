@@ -0,0 +1,728 @@
+//! Rooting of values held by native code.
+//!
+//! The collector moves cells, so a [JSValue] kept in a Rust local points to freed memory after
+//! [Runtime::gc] unless something roots it. The runtime roots its own fields, pinned atoms and
+//! code blocks of active frames, native code roots values it holds across allocations with:
+//!
+//! - [HandleScope] and [Rooted] for values on the stack, a scope releases everything rooted in it
+//!   when dropped.
+//! - [Persistent] for long-lived references kept by the embedder, released when dropped.
+//!
+//! In debug builds the runtime remembers cells that survived the last collection or were allocated
+//! after it, [Runtime::assert_live] panics on any other cell so unrooted values used after a GC
+//! are caught where they are used.
+use super::cell::*;
+use super::heap_stats::*;
+use super::jsvalue::*;
+use super::Runtime;
+use cgc::api::{Finalizer, Handle, HeapTrait, Traceable, Tracer};
+#[cfg(debug_assertions)]
+use fxhash::FxHashSet;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Types that can be stored in root slots.
+pub trait Rootable: Copy {
+    fn to_value(self) -> JSValue;
+    fn from_value(value: JSValue) -> Self;
+}
+
+impl Rootable for JSValue {
+    fn to_value(self) -> JSValue {
+        self
+    }
+
+    fn from_value(value: JSValue) -> Self {
+        value
+    }
+}
+
+impl Rootable for Handle<Cell> {
+    fn to_value(self) -> JSValue {
+        JSValue::cell(self)
+    }
+
+    fn from_value(value: JSValue) -> Self {
+        value.as_cell()
+    }
+}
+
+/// Root slots shared by the runtime, handle scopes and persistent handles.
+#[derive(Default)]
+pub struct Roots {
+    /// Slots of open handle scopes, the innermost scope owns the last ones.
+    scopes: Vec<JSValue>,
+    /// Number of open handle scopes.
+    depth: usize,
+    persistent: Vec<Option<JSValue>>,
+    /// Unused slots of `persistent`.
+    free: Vec<usize>,
+    /// Addresses of cells that survived the last collection or were allocated after it.
+    #[cfg(debug_assertions)]
+    live: FxHashSet<usize>,
+}
+
+impl Roots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_persistent(&mut self, value: JSValue) -> usize {
+        self.check_live(value);
+        match self.free.pop() {
+            Some(index) => {
+                self.persistent[index] = Some(value);
+                index
+            }
+            None => {
+                self.persistent.push(Some(value));
+                self.persistent.len() - 1
+            }
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_live(&self, value: JSValue) {
+        if is_heap_value(value) {
+            assert!(
+                self.live.contains(&cell_address(value.as_cell())),
+                "use of a value that was not rooted during garbage collection"
+            );
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn check_live(&self, _: JSValue) {}
+}
+
+#[cfg(debug_assertions)]
+fn is_heap_value(value: JSValue) -> bool {
+    value.is_cell() && !value.is_empty()
+}
+
+/// Address identifying `cell` until the next collection.
+pub(crate) fn cell_address(cell: Handle<Cell>) -> usize {
+    cell.get() as *const Cell as usize
+}
+
+impl Traceable for Roots {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        self.scopes.trace_with(tracer);
+        self.persistent.trace_with(tracer);
+    }
+}
+
+impl Finalizer for Roots {}
+
+/// Roots values for as long as it is alive. Scopes nest and must be dropped in reverse order of
+/// creation, values can only be rooted in the innermost one.
+pub struct HandleScope {
+    roots: Rc<RefCell<Roots>>,
+    /// Length of the slot stack when the scope was opened.
+    base: usize,
+    depth: usize,
+}
+
+impl HandleScope {
+    pub fn new(rt: &Runtime) -> Self {
+        let roots = rt.roots.clone();
+        let (base, depth) = {
+            let mut r = roots.borrow_mut();
+            r.depth += 1;
+            (r.scopes.len(), r.depth)
+        };
+        Self { roots, base, depth }
+    }
+
+    /// Roots `value` until the scope is dropped.
+    pub fn root<T: Rootable>(&self, value: T) -> Rooted<'_, T> {
+        let mut roots = self.roots.borrow_mut();
+        debug_assert_eq!(
+            roots.depth, self.depth,
+            "values can only be rooted in the innermost handle scope"
+        );
+        let value = value.to_value();
+        roots.check_live(value);
+        roots.scopes.push(value);
+        Rooted {
+            roots: &self.roots,
+            index: roots.scopes.len() - 1,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl Drop for HandleScope {
+    fn drop(&mut self) {
+        let mut roots = self.roots.borrow_mut();
+        debug_assert_eq!(
+            roots.depth, self.depth,
+            "handle scopes must be dropped in reverse order of creation"
+        );
+        roots.scopes.truncate(self.base);
+        roots.depth -= 1;
+    }
+}
+
+/// A value rooted in a [HandleScope], it cannot outlive the scope.
+pub struct Rooted<'s, T: Rootable> {
+    roots: &'s RefCell<Roots>,
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: Rootable> Rooted<'_, T> {
+    /// Returns the value, updated to the current location of the cell.
+    pub fn get(&self) -> T {
+        T::from_value(self.roots.borrow().scopes[self.index])
+    }
+
+    pub fn set(&mut self, value: T) {
+        let mut roots = self.roots.borrow_mut();
+        let value = value.to_value();
+        roots.check_live(value);
+        roots.scopes[self.index] = value;
+    }
+}
+
+/// Roots a value until dropped, independent of handle scopes. Cloning roots the value again.
+pub struct Persistent<T: Rootable> {
+    roots: Rc<RefCell<Roots>>,
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: Rootable> Persistent<T> {
+    pub fn new(rt: &Runtime, value: T) -> Self {
+        let roots = rt.roots.clone();
+        let index = roots.borrow_mut().add_persistent(value.to_value());
+        Self {
+            roots,
+            index,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the value, updated to the current location of the cell.
+    pub fn get(&self) -> T {
+        T::from_value(self.roots.borrow().persistent[self.index].unwrap())
+    }
+
+    pub fn set(&mut self, value: T) {
+        let mut roots = self.roots.borrow_mut();
+        let value = value.to_value();
+        roots.check_live(value);
+        roots.persistent[self.index] = Some(value);
+    }
+}
+
+impl<T: Rootable> Clone for Persistent<T> {
+    fn clone(&self) -> Self {
+        let value = self.get().to_value();
+        let index = self.roots.borrow_mut().add_persistent(value);
+        Self {
+            roots: self.roots.clone(),
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Rootable> Drop for Persistent<T> {
+    fn drop(&mut self) {
+        let mut roots = self.roots.borrow_mut();
+        roots.persistent[self.index] = None;
+        roots.free.push(self.index);
+    }
+}
+
+pub(crate) fn traced_cell(pointer: *const dyn HeapTrait) -> Handle<Cell> {
+    // Every GC pointer traced by the runtime is a `Handle<Cell>`.
+    unsafe { *(pointer as *const Handle<Cell>) }
+}
+
+/// Calls `f` with every cell `cell` refers to.
+pub(crate) fn for_each_child(cell: &Cell, mut f: impl FnMut(Handle<Cell>)) {
+    let mut tracer = Tracer::default();
+    cell.trace_with(&mut tracer);
+    tracer.for_each(|pointer| f(traced_cell(pointer)));
+}
+
+/// Cell tracing the runtime roots, one is allocated for every collection.
+struct RootsCell {
+    rt: *const Runtime,
+    /// Cell being moved into the heap when the collection started, null if there is none.
+    pending: *const Cell,
+}
+
+impl Traceable for RootsCell {
+    fn trace_with(&self, tracer: &mut Tracer) {
+        unsafe {
+            // The pending cell is not in the heap yet, it is traced before counting restarts.
+            if let Some(cell) = self.pending.as_ref() {
+                cell.trace_with(tracer);
+            }
+            restart_counting();
+            (*self.rt).trace_roots(tracer);
+            (*self.rt).weak_containers.trace_with(tracer);
+            (*self.rt).atoms.trace_weak(tracer);
+            (*self.rt).host_objects.trace_with(tracer);
+        }
+    }
+}
+
+impl Finalizer for RootsCell {}
+
+impl Runtime {
+    /// Traces every value the runtime keeps alive: its prototypes, the global object and
+    /// environment, pinned atoms, code blocks of active frames, the remembered set, queued jobs,
+    /// objects kept alive for the current job and the values rooted by handle scopes and
+    /// persistent handles. Weak containers and weak atoms are not roots, see `jsweak` and `atom`
+    /// modules.
+    pub(crate) fn trace_roots(&self, tracer: &mut Tracer) {
+        let fields = [
+            &self.number,
+            &self.bigint,
+            &self.boolean,
+            &self.math,
+            &self.object,
+            &self.string,
+            &self.symbol,
+            &self.function,
+            &self.global,
+            &self.global_environment,
+            &self.error,
+            &self.eval_error,
+            &self.range_error,
+            &self.reference_error,
+            &self.syntax_error,
+            &self.type_error,
+            &self.uri_error,
+            &self.weak_map,
+            &self.weak_set,
+            &self.weak_ref,
+            &self.finalization_registry,
+            &self.throw_type_error,
+        ];
+        fields.iter().for_each(|value| value.trace_with(tracer));
+        self.frames.trace_with(tracer);
+        self.atoms.trace_with(tracer);
+        self.remembered_set.trace_with(tracer);
+        self.jobs.iter().for_each(|job| job.trace_with(tracer));
+        self.kept_alive.trace_with(tracer);
+        self.new_targets.trace_with(tracer);
+        self.roots.borrow().trace_with(tracer);
+    }
+
+    /// Collects the young generation, cgc collects the old one too when it fills up. Cells not
+    /// reachable from the roots are freed, surviving cells may move and every root is updated to
+    /// the new location.
+    pub fn gc(&mut self) {
+        self.collect_garbage(None);
+    }
+
+    /// Collects garbage keeping `pending` and the cells it refers to alive, [Runtime::allocate]
+    /// passes the cell it is about to move into the heap.
+    pub(crate) fn collect_garbage(&mut self, pending: Option<&Cell>) {
+        let start = Instant::now();
+        if !self.weak_containers.is_empty()
+            || !self.host_objects.is_empty()
+            || self.atoms.weak_len() != 0
+        {
+            let marked = self.mark_reachable(pending);
+            self.sweep_weak_references(&marked);
+            self.atoms
+                .sweep(|atom| marked.contains(&cell_address(atom.as_cell())));
+            self.finalize_host_objects(&marked);
+        }
+        let pending = pending.map_or(std::ptr::null(), |cell| cell as *const Cell);
+        // The collector relocates the slots reported by tracing its roots, and lists each root
+        // and its direct children for finalization. Rooting a fresh cell that refers to a fresh
+        // `RootsCell` keeps that list free of duplicates.
+        let roots = self.heap.allocate(RootsCell { rt: self, pending });
+        let root = self.heap.allocate(roots.to_heap());
+        drop(roots);
+        let live = count_live_cells(|| self.heap.collect());
+        drop(root);
+        self.rehash_weak_maps();
+        self.prune_remembered_set();
+        #[cfg(debug_assertions)]
+        {
+            let mut counted = HeapStatistics::default();
+            let live_cells = self.walk_heap(pending, |cell| counted.count_cell(cell));
+            debug_assert_eq!(counted.live_bytes(), live.live_bytes());
+            self.roots.borrow_mut().live = live_cells;
+        }
+        self.heap_stats.record_collection(live, start.elapsed());
+    }
+
+    /// Calls `f` with every cell reachable from the roots and `pending`, returns their addresses.
+    /// Debug builds use it to check live counts and find unrooted values, see
+    /// [Runtime::assert_live].
+    #[cfg(debug_assertions)]
+    fn walk_heap(&self, pending: *const Cell, mut f: impl FnMut(&Cell)) -> FxHashSet<usize> {
+        let mut visited = FxHashSet::default();
+        let mut tracer = Tracer::default();
+        RootsCell { rt: self, pending }.trace_with(&mut tracer);
+        let mut stack = vec![];
+        tracer.for_each(|pointer| stack.push(traced_cell(pointer)));
+        while let Some(cell) = stack.pop() {
+            if visited.insert(cell_address(cell)) {
+                f(cell.get());
+                for_each_child(cell.get(), |child| stack.push(child));
+            }
+        }
+        visited
+    }
+
+    #[cfg(debug_assertions)]
+    pub(crate) fn register_cell(&mut self, value: JSValue) {
+        self.roots
+            .borrow_mut()
+            .live
+            .insert(cell_address(value.as_cell()));
+    }
+
+    /// Panics in debug builds if `value` refers to a cell freed by a collection, meaning it was
+    /// not rooted while the collection ran.
+    pub fn assert_live(&self, value: JSValue) {
+        self.roots.borrow().check_live(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: JSValue) -> String {
+        value.as_cell().unchecked_string().to_string()
+    }
+
+    #[test]
+    fn rooted_values_survive_collection() {
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let s = scope.root(rt.new_string("rooted string"));
+        let n = scope.root(rt.new_bigint(crate::runtime::JSBigInt::from_i64(-42)));
+        let cell = scope.root(rt.new_string("handle").as_cell());
+        let atom = scope.root(rt.intern("length"));
+        for i in 0..1000 {
+            rt.new_string(&i.to_string());
+        }
+        rt.gc();
+        rt.gc();
+        assert_eq!(string(s.get()), "rooted string");
+        assert_eq!(n.get().as_bigint().to_i64(), Some(-42));
+        assert_eq!(cell.get().unchecked_string().to_string(), "handle");
+        assert!(rt.intern("length") == atom.get());
+        let o = rt.new_object();
+        assert!(o.is_object());
+    }
+
+    #[test]
+    fn scopes_release_their_slots() {
+        let mut rt = Runtime::new();
+        let outer = HandleScope::new(&rt);
+        let a = outer.root(rt.new_string("a"));
+        {
+            let inner = HandleScope::new(&rt);
+            let mut b = inner.root(JSValue::undefined());
+            b.set(rt.new_string("b"));
+            assert_eq!(rt.roots.borrow().scopes.len(), 2);
+            rt.gc();
+            assert_eq!(string(b.get()), "b");
+        }
+        assert_eq!(rt.roots.borrow().scopes.len(), 1);
+        rt.gc();
+        assert_eq!(string(a.get()), "a");
+        drop(outer);
+        assert_eq!(rt.roots.borrow().depth, 0);
+    }
+
+    #[test]
+    fn persistent_handles() {
+        let mut rt = Runtime::new();
+        let value = rt.new_string("persistent");
+        let p = Persistent::new(&rt, value);
+        let q = p.clone();
+        rt.gc();
+        drop(p);
+        rt.gc();
+        assert_eq!(string(q.get()), "persistent");
+        let mut r = Persistent::new(&rt, JSValue::new_int(1));
+        assert_eq!(rt.roots.borrow().persistent.len(), 2);
+        r.set(q.get());
+        drop(q);
+        rt.gc();
+        assert_eq!(string(r.get()), "persistent");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "not rooted")]
+    fn unrooted_value_after_collection() {
+        let mut rt = Runtime::new();
+        let value = rt.new_string("unrooted");
+        rt.assert_live(value);
+        rt.gc();
+        rt.assert_live(value);
+    }
+
+    fn object<R>(value: JSValue, f: impl FnOnce(&mut crate::runtime::JSObject) -> R) -> R {
+        f(value.as_cell().unchecked_object_mut())
+    }
+
+    fn property(value: JSValue, name: &str) -> crate::runtime::jsproperty::JSProperty {
+        object(value, |o| o._read(name).unwrap())
+    }
+
+    #[test]
+    fn object_fields_are_traced() {
+        use crate::runtime::jsproperty::JSProperty;
+        use crate::runtime::JSObjectKind;
+        let mut rt = Runtime::new();
+        let (obj, inner) = (rt.new_object(), rt.new_object());
+        let (value, get, set) = (
+            rt.new_string("value"),
+            rt.new_string("getter"),
+            rt.new_string("setter"),
+        );
+        object(inner, |o| o._write("s", JSProperty::new().value(value)));
+        object(obj, |o| o._write("inner", JSProperty::new().value(inner)));
+        object(obj, |o| {
+            o._write("accessor", JSProperty::new().getter(get).setter(set))
+        });
+        let internal = rt.new_bigint(crate::runtime::JSBigInt::from_i64(7));
+        object(obj, |o| o.set_internal("slot", internal));
+        let element = rt.new_string("element");
+        let array = rt.allocate(Cell::Object(Box::new(crate::runtime::JSObject::new(
+            JSObjectKind::Array(vec![element]),
+            None,
+            "Array",
+        ))));
+        object(obj, |o| o._write("array", JSProperty::new().value(array)));
+        let child = rt.allocate(Cell::Object(Box::new(crate::runtime::JSObject::new(
+            JSObjectKind::Normal,
+            Some(obj),
+            "Object",
+        ))));
+        let child = Persistent::new(&rt, child);
+        for i in 0..100 {
+            rt.new_string(&i.to_string());
+        }
+        rt.gc();
+        rt.gc();
+
+        let obj = object(child.get(), |o| o.prototype.unwrap());
+        rt.assert_live(obj);
+        let inner = property(obj, "inner").value.unwrap();
+        assert_eq!(string(property(inner, "s").value.unwrap()), "value");
+        let accessor = property(obj, "accessor");
+        assert_eq!(string(accessor.getter.unwrap()), "getter");
+        assert_eq!(string(accessor.setter.unwrap()), "setter");
+        let internal = object(obj, |o| o.get_internal("slot"));
+        assert_eq!(internal.as_bigint().to_i64(), Some(7));
+        let array = property(obj, "array").value.unwrap();
+        object(array, |o| match &o.kind {
+            JSObjectKind::Array(elements) => assert_eq!(string(elements[0]), "element"),
+            _ => unreachable!(),
+        });
+        assert_eq!(
+            string(property(rt.type_error, "name").value.unwrap()),
+            "TypeError"
+        );
+    }
+
+    #[test]
+    fn inline_references_are_relocated() {
+        use crate::runtime::environment::*;
+        use crate::runtime::{JSObject, JSObjectKind};
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let target = scope.root(rt.new_object());
+        let global = rt.global_environment;
+        let env = new_object_environment(&mut rt, Some(global), target.get(), true);
+        let env = scope.root(env);
+        let child = JSObject::new(JSObjectKind::Normal, Some(target.get()), "Object");
+        let child = scope.root(rt.allocate(Cell::Object(Box::new(child))));
+        for _ in 0..3 {
+            for i in 0..100 {
+                rt.new_string(&i.to_string());
+            }
+            rt.gc();
+        }
+        let binding_object = match &env.get().as_cell().unchecked_environment_mut().record {
+            EnvironmentRecord::Object(record) => record.binding_object,
+            _ => unreachable!(),
+        };
+        assert!(binding_object == target.get());
+        assert!(get_parent_environment(env.get()) == Some(rt.global_environment));
+        assert!(object(child.get(), |o| o.prototype) == Some(target.get()));
+    }
+
+    #[test]
+    fn code_blocks_of_frames_are_traced() {
+        use crate::bytecode::codeblock::CodeBlock;
+        use crate::runtime::CallFrame;
+        let mut rt = Runtime::new();
+        let mut code_block = CodeBlock::new("f", "test.js", false);
+        let id = code_block.add_identifier(&mut rt, "foo") as usize;
+        let constant = rt.new_string("constant");
+        let index = code_block.add_constant(constant) as usize;
+        let other = rt.new_string("constant");
+        assert_eq!(code_block.add_constant(other) as usize, index);
+        rt.frames.push(CallFrame {
+            code_block: std::rc::Rc::new(code_block),
+            pc: 0,
+        });
+        rt.gc();
+        let code_block = rt.frames[0].code_block.clone();
+        assert_eq!(string(code_block.constants[index]), "constant");
+        rt.assert_live(code_block.constants[index]);
+        assert!(code_block.identifiers[id] == rt.intern("foo"));
+    }
+
+    #[test]
+    fn stress_mode() {
+        use crate::runtime::error::JSError;
+        use crate::runtime::operations;
+        let mut rt = Runtime::new();
+        rt.gc_stress = true;
+        let scope = HandleScope::new(&rt);
+        let mut s = scope.root(rt.new_string("abc"));
+        for i in 0..20 {
+            let value = operations::add(&mut rt, s.get(), JSValue::new_int(i))
+                .ok()
+                .unwrap();
+            s.set(value);
+        }
+        let s = s.get();
+        assert_eq!(string(s), "abc012345678910111213141516171819");
+        let sub = scope.root(rt.new_substring(s, 3, 20).ok().unwrap());
+        let atom = rt.intern_string(sub.get());
+        assert_eq!(string(atom), "01234567891011121");
+        let wrapper = rt.to_object(sub.get()).ok().unwrap();
+        let primitive = object(wrapper, |o| o.get_internal("PrimitiveValue"));
+        assert_eq!(string(primitive), "01234567891011121");
+        let error = rt.new_type_error("stressed");
+        let error = JSError::from_value(&mut rt, error);
+        assert_eq!(error.name, "TypeError");
+        assert_eq!(error.message, "stressed");
+    }
+
+    /// Object with a `valueOf` that allocates before returning what `result` creates.
+    fn with_value_of(rt: &mut Runtime, result: fn(&mut Runtime) -> JSValue) -> JSValue {
+        let scope = HandleScope::new(rt);
+        let object = scope.root(rt.new_object());
+        rt.define_native_method(object.get(), "valueOf", 0, move |rt, _, _| {
+            rt.new_string("garbage");
+            Ok(result(rt))
+        });
+        object.get()
+    }
+
+    #[test]
+    fn stress_mode_conversions() {
+        use crate::runtime::comparison::*;
+        use crate::runtime::operations::*;
+        use crate::runtime::JSBigInt;
+        let mut rt = Runtime::new();
+        let scope = HandleScope::new(&rt);
+        let five = scope.root(with_value_of(&mut rt, |_| JSValue::new_int(5)));
+        let big = with_value_of(&mut rt, |rt| rt.new_bigint(JSBigInt::from_i64(5)));
+        let big = scope.root(big);
+        rt.gc_stress = true;
+
+        let text = scope.root(rt.new_string("5"));
+        assert_eq!(
+            abstract_equals(&mut rt, text.get(), five.get()).ok(),
+            Some(true)
+        );
+        assert_eq!(
+            abstract_equals(&mut rt, five.get(), text.get()).ok(),
+            Some(true)
+        );
+        let seven = scope.root(rt.new_bigint(JSBigInt::from_i64(7)));
+        let result = less_than(&mut rt, big.get(), seven.get(), true);
+        assert_eq!(result.ok(), Some(Some(true)));
+        let result = less_than(&mut rt, seven.get(), big.get(), false);
+        assert_eq!(result.ok(), Some(Some(false)));
+        let result = less_than(&mut rt, text.get(), five.get(), true);
+        assert_eq!(result.ok(), Some(Some(false)));
+
+        let two = rt.new_bigint(JSBigInt::from_i64(2));
+        let difference = sub(&mut rt, big.get(), two).ok().unwrap();
+        rt.assert_live(difference);
+        assert_eq!(difference.as_bigint().to_i64(), Some(3));
+        let sum = add(&mut rt, text.get(), five.get()).ok().unwrap();
+        assert_eq!(string(sum), "55");
+        let sum = add(&mut rt, five.get(), text.get()).ok().unwrap();
+        assert_eq!(string(sum), "55");
+    }
+
+    #[test]
+    fn stress_mode_bindings() {
+        use crate::bytecode::codeblock::CodeBlock;
+        use crate::runtime::environment::*;
+        use crate::runtime::jsproperty::JSProperty;
+        use crate::runtime::vtable::object_get;
+        let mut rt = Runtime::new();
+        let (sloppy, strict) = (
+            CodeBlock::new("", "main.js", false),
+            CodeBlock::new("", "main.js", true),
+        );
+        let scope = HandleScope::new(&rt);
+        let setter = rt.new_native_function("set", 1, false, |rt, this, args| {
+            let handles = HandleScope::new(rt);
+            let (this, value) = (handles.root(this), handles.root(args[0]));
+            rt.new_string("garbage");
+            rt.define_builtin(this.get(), "stored", value.get());
+            Ok(JSValue::undefined())
+        });
+        let setter = scope.root(setter);
+        let target = scope.root(rt.new_object());
+        let desc = JSProperty::new().setter(setter.get()).configurable(true);
+        object(target.get(), |o| o._write("x", desc));
+        let global = rt.global_environment;
+        let env = new_object_environment(&mut rt, Some(global), target.get(), true);
+        let env = scope.root(env);
+        rt.gc_stress = true;
+
+        let value = rt.new_string("through setter");
+        let put = object(target.get(), |o| o.class_object.put);
+        put(&mut rt, target.get(), "x", value, true).ok().unwrap();
+        let stored = object_get(&mut rt, target.get(), "stored").ok().unwrap();
+        rt.assert_live(stored);
+        assert_eq!(string(stored), "through setter");
+        let value = rt.new_string("through with");
+        put_variable(&mut rt, env.get(), "x", value, &strict)
+            .ok()
+            .unwrap();
+        let stored = object_get(&mut rt, target.get(), "stored").ok().unwrap();
+        rt.assert_live(stored);
+        assert_eq!(string(stored), "through with");
+
+        let function = rt.new_object();
+        let global = rt.global_environment;
+        let env = new_function_environment(
+            &mut rt,
+            Some(global),
+            0,
+            function,
+            JSValue::undefined(),
+            JSValue::undefined(),
+            false,
+        );
+        let env = scope.root(env);
+        let primitive = rt.new_string("this");
+        bind_call_this(&mut rt, env.get(), primitive, &sloppy)
+            .ok()
+            .unwrap();
+        let this = resolve_this_binding(&mut rt, env.get()).ok().unwrap();
+        rt.assert_live(this);
+        let primitive = object(this, |o| o.get_internal("PrimitiveValue"));
+        assert_eq!(string(primitive), "this");
+    }
+}
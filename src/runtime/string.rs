@@ -0,0 +1,84 @@
+//! `String.prototype` methods indexing code units.
+//!
+//! Like the `number` module these are written as native functions installed on
+//! `String.prototype`.
+use super::jsvalue::*;
+use super::Runtime;
+
+fn argument(args: &[JSValue], index: usize) -> JSValue {
+    args.get(index).copied().unwrap_or_else(JSValue::undefined)
+}
+
+/// RequireObjectCoercible(this) followed by ToString(this).
+fn this_string(rt: &mut Runtime, this: JSValue, method: &str) -> Result<JSValue, JSValue> {
+    if this.is_undefined_or_null() {
+        let msg = format!("String.prototype.{} called on null or undefined", method);
+        return Err(rt.new_type_error(&msg));
+    }
+    this.to_js_string(rt)
+}
+
+/// Converts position argument, returns `None` if it is outside of `s`.
+fn position(rt: &mut Runtime, s: JSValue, args: &[JSValue]) -> Result<Option<usize>, JSValue> {
+    let position = argument(args, 0).to_integer_or_infinity(rt)?;
+    let length = s.as_cell().unchecked_string().len();
+    if position < 0.0 || position >= length as f64 {
+        return Ok(None);
+    }
+    Ok(Some(position as usize))
+}
+
+/// [String.prototype.charAt](https://tc39.es/ecma262/#sec-string.prototype.charat)
+pub fn string_proto_char_at(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let s = this_string(rt, this, "charAt")?;
+    match position(rt, s, args)? {
+        Some(index) => rt.new_substring(s, index, index + 1),
+        None => Ok(rt.new_string("")),
+    }
+}
+
+/// [String.prototype.charCodeAt](https://tc39.es/ecma262/#sec-string.prototype.charcodeat)
+///
+/// Returns a single UTF-16 code unit, so a character outside the BMP gives its two surrogates at
+/// consecutive positions.
+pub fn string_proto_char_code_at(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let s = this_string(rt, this, "charCodeAt")?;
+    match position(rt, s, args)? {
+        Some(index) => {
+            let unit = s.as_cell().unchecked_string_mut().code_unit_at(index);
+            Ok(JSValue::new_int(unit as i32))
+        }
+        None => Ok(JSValue::new_double(f64::NAN)),
+    }
+}
+
+/// [String.prototype.codePointAt](https://tc39.es/ecma262/#sec-string.prototype.codepointat)
+pub fn string_proto_code_point_at(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let s = this_string(rt, this, "codePointAt")?;
+    match position(rt, s, args)? {
+        Some(index) => {
+            let code_point = s.as_cell().unchecked_string_mut().code_point_at(index);
+            Ok(JSValue::new_int(code_point as i32))
+        }
+        None => Ok(JSValue::undefined()),
+    }
+}
+
+pub(crate) fn install(rt: &mut Runtime) {
+    let proto = rt.string;
+    rt.define_native_method(proto, "charAt", 1, string_proto_char_at);
+    rt.define_native_method(proto, "charCodeAt", 1, string_proto_char_code_at);
+    rt.define_native_method(proto, "codePointAt", 1, string_proto_code_point_at);
+}
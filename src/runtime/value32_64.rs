@@ -0,0 +1,244 @@
+//! 32-bit tag and 32-bit payload encoding, meant for 32-bit targets.
+//!
+//! On 64-bit targets pointers do not fit into the payload, cells are kept in an extra field and
+//! their payload is 0. Values are twice as big there, but the tag and payload encoding stays the
+//! same, so it can be tested on 64-bit hosts.
+use super::cell::*;
+use super::pure_nan::*;
+use super::value_repr::*;
+use cgc::api::Handle;
+
+pub const INT32_TAG: u32 = 0xffffffff;
+pub const BOOL_TAG: u32 = 0xfffffffe;
+pub const UNDEFINED_TAG: u32 = 0xfffffffd;
+pub const NULL_TAG: u32 = 0xfffffffc;
+pub const CELL_TAG: u32 = 0xfffffffb;
+pub const EMPTY_TAG: u32 = 0xfffffffa;
+pub const DELETED_TAG: u32 = 0xfffffff9;
+pub const LOWEST_TAG: u32 = DELETED_TAG;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+#[cfg(target_endian = "big")]
+pub struct AsBits {
+    pub tag: i32,
+    pub payload: i32,
+}
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+#[cfg(target_endian = "little")]
+pub struct AsBits {
+    pub payload: i32,
+    pub tag: i32,
+}
+
+pub fn tag_offset() -> usize {
+    offset_of!(AsBits, tag)
+}
+pub fn payload_offset() -> usize {
+    offset_of!(AsBits, payload)
+}
+
+#[derive(Copy, Clone)]
+#[repr(C, align(8))]
+union EncodedValueDescriptor {
+    as_int64: i64,
+    as_double: f64,
+    as_bits: AsBits,
+}
+
+#[derive(Copy, Clone)]
+pub struct Value32_64 {
+    u: EncodedValueDescriptor,
+    #[cfg(target_pointer_width = "64")]
+    cell: Option<Handle<Cell>>,
+}
+
+impl Value32_64 {
+    /*
+     * On 32-bit platforms `jsvalue32-64` feature should be enabled, and we use a NaN-encoded
+     * form for immediates.
+     *
+     * The encoding makes use of unused NaN space in the IEEE754 representation.  Any value
+     * with the top 13 bits set represents a QNaN (with the sign bit set).  QNaN values
+     * can encode a 51-bit payload.  Hardware produced and C-library payloads typically
+     * have a payload of zero.  We assume that non-zero payloads are available to encode
+     * pointer and integer values.  Since any 64-bit bit pattern where the top 15 bits are
+     * all set represents a NaN with a non-zero payload, we can use this space in the NaN
+     * ranges to encode other values (however there are also other ranges of NaN space that
+     * could have been selected).
+     *
+     * For JSValues that do not contain a double value, the high 32 bits contain the tag
+     * values listed in the enums below, which all correspond to NaN-space. In the case of
+     * cell, integer and bool values the lower 32 bits (the 'payload') contain the pointer
+     * integer or boolean value; in the case of all other tags the payload is 0.
+     */
+    pub fn tag(self) -> u32 {
+        unsafe { self.u.as_bits.tag as u32 }
+    }
+    pub fn payload(self) -> i32 {
+        unsafe { self.u.as_bits.payload }
+    }
+
+    pub(crate) fn with_tag_payload(tag: u32, payload: i32) -> Self {
+        Self {
+            u: EncodedValueDescriptor {
+                as_bits: AsBits {
+                    tag: tag as i32,
+                    payload,
+                },
+            },
+            #[cfg(target_pointer_width = "64")]
+            cell: None,
+        }
+    }
+
+    pub fn as_boolean(&self) -> bool {
+        assert!(self.is_boolean());
+        self.payload() != 0
+    }
+}
+
+impl PartialEq for Value32_64 {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(target_pointer_width = "64")]
+        if self.is_cell() && other.is_cell() {
+            return std::ptr::eq(self.as_cell().get(), other.as_cell().get());
+        }
+        unsafe { self.u.as_int64 == other.u.as_int64 }
+    }
+}
+
+impl Eq for Value32_64 {}
+
+impl ValueRepr for Value32_64 {
+    fn empty() -> Self {
+        Self::with_tag_payload(EMPTY_TAG, 0)
+    }
+
+    fn undefined() -> Self {
+        Self::with_tag_payload(UNDEFINED_TAG, 0)
+    }
+
+    fn null() -> Self {
+        Self::with_tag_payload(NULL_TAG, 0)
+    }
+
+    fn boolean(value: bool) -> Self {
+        Self::with_tag_payload(BOOL_TAG, value as i32)
+    }
+
+    fn int32(value: i32) -> Self {
+        Self::with_tag_payload(INT32_TAG, value)
+    }
+
+    fn double(value: f64) -> Self {
+        Self {
+            u: EncodedValueDescriptor {
+                as_double: purify_nan(value),
+            },
+            #[cfg(target_pointer_width = "64")]
+            cell: None,
+        }
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    fn cell(cell: Handle<Cell>) -> Self {
+        // sizeof(void*) == sizeof(i32) on 32 bit machines, this cast is safe.
+        Self::with_tag_payload(CELL_TAG, unsafe {
+            std::mem::transmute::<Handle<Cell>, i32>(cell)
+        })
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    fn cell(cell: Handle<Cell>) -> Self {
+        let mut value = Self::with_tag_payload(CELL_TAG, 0);
+        value.cell = Some(cell);
+        value
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tag() == EMPTY_TAG
+    }
+
+    fn is_undefined(&self) -> bool {
+        self.tag() == UNDEFINED_TAG
+    }
+
+    fn is_null(&self) -> bool {
+        self.tag() == NULL_TAG
+    }
+
+    fn is_boolean(&self) -> bool {
+        self.tag() == BOOL_TAG
+    }
+
+    fn is_true(&self) -> bool {
+        self.tag() == BOOL_TAG && self.payload() != 0
+    }
+
+    fn is_false(&self) -> bool {
+        self.tag() == BOOL_TAG && self.payload() == 0
+    }
+
+    fn is_int32(&self) -> bool {
+        self.tag() == INT32_TAG
+    }
+
+    fn is_double(&self) -> bool {
+        self.tag() < LOWEST_TAG
+    }
+
+    fn is_cell(&self) -> bool {
+        self.tag() == CELL_TAG
+    }
+
+    fn as_int32(&self) -> i32 {
+        assert!(self.is_int32());
+        self.payload()
+    }
+
+    fn as_double(&self) -> f64 {
+        assert!(self.is_double());
+        unsafe { self.u.as_double }
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    fn as_cell_ref(&self) -> &Handle<Cell> {
+        assert!(self.is_cell(), "Value payload is not a cell!");
+        unsafe { &*(&self.u.as_bits.payload as *const i32 as *const Handle<Cell>) }
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    fn as_cell_ref(&self) -> &Handle<Cell> {
+        assert!(self.is_cell(), "Value payload is not a cell!");
+        self.cell.as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    crate::value_repr_tests!(super::Value32_64);
+
+    #[test]
+    fn tag_and_payload() {
+        use super::*;
+        let int = Value32_64::int32(-5);
+        assert_eq!((int.tag(), int.payload()), (INT32_TAG, -5));
+        assert_eq!(Value32_64::boolean(true).tag(), BOOL_TAG);
+        assert!(Value32_64::boolean(true).as_boolean());
+        assert!(!Value32_64::boolean(false).as_boolean());
+        assert_eq!(Value32_64::undefined().tag(), UNDEFINED_TAG);
+        assert_eq!(Value32_64::null().tag(), NULL_TAG);
+        assert_eq!(Value32_64::empty().tag(), EMPTY_TAG);
+        // Tags are compared unsigned, negative doubles have high word above 0x80000000.
+        let negative = Value32_64::double(-1.5);
+        assert!(negative.tag() > 0x8000_0000 && negative.tag() < LOWEST_TAG);
+        assert!(negative.is_double());
+        let nan = Value32_64::double(f64::from_bits(0xffff_fffb_0000_0001));
+        assert!(nan.is_double() && !nan.is_cell());
+        let mut rt = Runtime::new();
+        let object = Value32_64::cell(rt.new_object().as_cell());
+        assert_eq!(object.tag(), CELL_TAG);
+    }
+}
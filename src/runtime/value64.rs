@@ -0,0 +1,225 @@
+//! 64-bit NaN-boxing, the default JSValue encoding.
+use super::cell::*;
+use super::pure_nan::*;
+use super::value_repr::*;
+use cgc::api::Handle;
+
+#[derive(Copy, Clone)]
+#[repr(C, align(8))]
+union EncodedValueDescriptor {
+    as_int64: i64,
+    cell: Handle<Cell>,
+}
+
+#[derive(Copy, Clone)]
+pub struct Value64 {
+    u: EncodedValueDescriptor,
+}
+
+impl Value64 {
+    /*
+     * On 64-bit platforms `jsvalue64` feature should be enabled, and we use a NaN-encoded
+     * form for immediates.
+     *
+     * The encoding makes use of unused NaN space in the IEEE754 representation.  Any value
+     * with the top 13 bits set represents a QNaN (with the sign bit set).  QNaN values
+     * can encode a 51-bit payload.  Hardware produced and C-library payloads typically
+     * have a payload of zero.  We assume that non-zero payloads are available to encode
+     * pointer and integer values.  Since any 64-bit bit pattern where the top 15 bits are
+     * all set represents a NaN with a non-zero payload, we can use this space in the NaN
+     * ranges to encode other values (however there are also other ranges of NaN space that
+     * could have been selected).
+     *
+     * This range of NaN space is represented by 64-bit numbers begining with the 15-bit
+     * hex patterns 0xFFFC and 0xFFFE - we rely on the fact that no valid double-precision
+     * numbers will fall in these ranges.
+     *
+     * The top 15-bits denote the type of the encoded JSValue:
+     *
+     *     Pointer {  0000:PPPP:PPPP:PPPP
+     *              / 0002:****:****:****
+     *     Double  {         ...
+     *              \ FFFC:****:****:****
+     *     Integer {  FFFE:0000:IIII:IIII
+     *
+     * The scheme we have implemented encodes double precision values by performing a
+     * 64-bit integer addition of the value 2^49 to the number. After this manipulation
+     * no encoded double-precision value will begin with the pattern 0x0000 or 0xFFFE.
+     * Values must be decoded by reversing this operation before subsequent floating point
+     * operations may be peformed.
+     *
+     * 32-bit signed integers are marked with the 16-bit tag 0xFFFE.
+     *
+     * The tag 0x0000 denotes a pointer, or another form of tagged immediate. Boolean,
+     * null and undefined values are represented by specific, invalid pointer values:
+     *
+     *     False:     0x06
+     *     True:      0x07
+     *     Undefined: 0x0a
+     *     Null:      0x02
+     *
+     * These values have the following properties:
+     * - Bit 1 (OtherTag) is set for all four values, allowing real pointers to be
+     *   quickly distinguished from all immediate values, including these invalid pointers.
+     * - With bit 3 masked out (UndefinedTag), Undefined and Null share the
+     *   same value, allowing null & undefined to be quickly detected.
+     *
+     * No valid JSValue will have the bit pattern 0x0, this is used to represent array
+     * holes, and as a C++ 'no value' result (e.g. JSValue() has an internal value of 0).
+     *
+     * This representation works because of the following things:
+     * - It cannot be confused with a Double or Integer thanks to the top bits
+     * - It cannot be confused with a pointer to a Cell, thanks to bit 1 which is set to true
+     * - It cannot be confused with a pointer to wasm thanks to bit 0 which is set to false
+     * - It cannot be confused with true/false because bit 2 is set to false
+     * - It cannot be confused for null/undefined because bit 4 is set to true
+     */
+
+    /// This value is 2^49, used to encode doubles such that the encoded value will begin
+    /// with a 15-bit pattern within the range 0x0002..0xFFFC.
+    pub const DOUBLE_ENCODE_OFFSET_BIT: i64 = 49;
+    pub const DOUBLE_ENCODE_OFFSET: i64 = 1 << Self::DOUBLE_ENCODE_OFFSET_BIT;
+    /// If all bits in the mask are set, this indicates an integer number,
+    /// if any but not all are set this value is a double precision number.
+    pub const NUMBER_TAG: i64 = 0xfffe000000000000u64 as i64;
+    /// The following constant is used for a trick in the implementation of strictEq, to detect if either of the arguments is a double
+    pub const LOWEST_OF_HIGH_BITS: i64 = 1 << 49;
+    /// All non-numeric (bool, null, undefined) immediates have bit 2 set.
+    pub const OTHER_TAG: i64 = 0x2;
+    pub const BOOL_TAG: i64 = 0x4;
+    pub const UNDEFINED_TAG: i64 = 0x8;
+    pub const VALUE_FALSE: i64 = Self::OTHER_TAG | Self::BOOL_TAG; // `0` stands for `false`.
+    pub const VALUE_TRUE: i64 = Self::OTHER_TAG | Self::BOOL_TAG | 1; // `1` stands for `true`.
+    pub const VALUE_UNDEFINED: i64 = Self::OTHER_TAG | Self::UNDEFINED_TAG;
+    pub const VALUE_NULL: i64 = Self::OTHER_TAG;
+    pub const MISC_TAG: i64 = Self::OTHER_TAG | Self::BOOL_TAG | Self::UNDEFINED_TAG;
+    /// NOT_CELL_MASK is used to check for all types of immediate values (either number or 'other').
+    pub const NOT_CELL_MASK: i64 = Self::NUMBER_TAG | Self::OTHER_TAG;
+    /// These special values are never visible to JavaScript code; Empty is used to represent
+    /// Array holes, and for uninitialized JSValues. Deleted is used in hash table code.
+    /// These values would map to cell types in the JSValue encoding, but not valid GC cell
+    /// pointer should have either of these values (Empty is null, deleted is at an invalid
+    /// alignment for a GC cell, and in the zero page).
+    pub const VALUE_EMPTY: i64 = 0x0;
+    pub const VALUE_DELETED: i64 = 0x4;
+
+    fn from_bits(as_int64: i64) -> Self {
+        Self {
+            u: EncodedValueDescriptor { as_int64 },
+        }
+    }
+
+    pub fn bits(&self) -> i64 {
+        unsafe { self.u.as_int64 }
+    }
+}
+
+impl PartialEq for Value64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits() == other.bits()
+    }
+}
+
+impl Eq for Value64 {}
+
+impl ValueRepr for Value64 {
+    fn empty() -> Self {
+        // 0x0 can never occur naturally because it has a tag of 00, indicating a pointer value, but a payload of 0x0, which is in the (invalid) zero page.
+        Self::from_bits(Self::VALUE_EMPTY)
+    }
+
+    fn undefined() -> Self {
+        Self::from_bits(Self::VALUE_UNDEFINED)
+    }
+
+    fn null() -> Self {
+        Self::from_bits(Self::VALUE_NULL)
+    }
+
+    fn boolean(value: bool) -> Self {
+        Self::from_bits(if value {
+            Self::VALUE_TRUE
+        } else {
+            Self::VALUE_FALSE
+        })
+    }
+
+    fn int32(value: i32) -> Self {
+        Self::from_bits(Self::NUMBER_TAG | (value as u32 as i64))
+    }
+
+    fn double(value: f64) -> Self {
+        Self::from_bits(purify_nan(value).to_bits() as i64 + Self::DOUBLE_ENCODE_OFFSET)
+    }
+
+    fn cell(cell: Handle<Cell>) -> Self {
+        Self {
+            u: EncodedValueDescriptor { cell },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bits() == Self::VALUE_EMPTY
+    }
+
+    fn is_undefined(&self) -> bool {
+        self.bits() == Self::VALUE_UNDEFINED
+    }
+
+    fn is_null(&self) -> bool {
+        self.bits() == Self::VALUE_NULL
+    }
+
+    fn is_undefined_or_null(&self) -> bool {
+        (self.bits() & !Self::UNDEFINED_TAG) == Self::VALUE_NULL
+    }
+
+    fn is_boolean(&self) -> bool {
+        // `true` and `false` differ only in the lowest bit.
+        self.bits() & !1 == Self::VALUE_FALSE
+    }
+
+    fn is_true(&self) -> bool {
+        self.bits() == Self::VALUE_TRUE
+    }
+
+    fn is_false(&self) -> bool {
+        self.bits() == Self::VALUE_FALSE
+    }
+
+    fn is_int32(&self) -> bool {
+        (self.bits() & Self::NUMBER_TAG) == Self::NUMBER_TAG
+    }
+
+    fn is_double(&self) -> bool {
+        !self.is_int32() && self.is_number()
+    }
+
+    fn is_number(&self) -> bool {
+        self.bits() & Self::NUMBER_TAG != 0
+    }
+
+    fn is_cell(&self) -> bool {
+        (self.bits() & Self::NOT_CELL_MASK) == 0
+    }
+
+    fn as_int32(&self) -> i32 {
+        assert!(self.is_int32());
+        self.bits() as i32
+    }
+
+    fn as_double(&self) -> f64 {
+        assert!(self.is_double());
+        f64::from_bits((self.bits() - Self::DOUBLE_ENCODE_OFFSET) as u64)
+    }
+
+    fn as_cell_ref(&self) -> &Handle<Cell> {
+        assert!(self.is_cell());
+        unsafe { &self.u.cell }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    crate::value_repr_tests!(super::Value64);
+}
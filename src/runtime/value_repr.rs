@@ -0,0 +1,158 @@
+//! Interface of JSValue encodings.
+//!
+//! Both encodings are always compiled, the `jsvalue64` and `jsvalue32-64` features only select
+//! which one [JSValue](super::jsvalue::JSValue) wraps. This keeps the unused encoding building and
+//! lets the same test suite run against both on a 64-bit host.
+use super::cell::*;
+use cgc::api::Handle;
+
+pub trait ValueRepr: Copy + Eq {
+    /// Value that is never visible to JavaScript, marks holes and uninitialized bindings.
+    fn empty() -> Self;
+    fn undefined() -> Self;
+    fn null() -> Self;
+    fn boolean(value: bool) -> Self;
+    fn int32(value: i32) -> Self;
+    /// NaNs are purified, an impure NaN could look like some other value.
+    fn double(value: f64) -> Self;
+    fn cell(cell: Handle<Cell>) -> Self;
+
+    fn is_empty(&self) -> bool;
+    fn is_undefined(&self) -> bool;
+    fn is_null(&self) -> bool;
+    fn is_undefined_or_null(&self) -> bool {
+        self.is_undefined() || self.is_null()
+    }
+    fn is_boolean(&self) -> bool;
+    fn is_true(&self) -> bool;
+    fn is_false(&self) -> bool;
+    fn is_int32(&self) -> bool;
+    fn is_double(&self) -> bool;
+    fn is_number(&self) -> bool {
+        self.is_int32() || self.is_double()
+    }
+    /// May be true for the empty value, check [ValueRepr::is_empty] before using the cell.
+    fn is_cell(&self) -> bool;
+
+    fn as_int32(&self) -> i32;
+    fn as_double(&self) -> f64;
+    /// The GC updates the handle in place when it moves the cell, so this must point into the
+    /// value itself.
+    fn as_cell_ref(&self) -> &Handle<Cell>;
+    fn as_cell(&self) -> Handle<Cell> {
+        *self.as_cell_ref()
+    }
+}
+
+/// Test suite shared by all encodings, `value_repr_tests!(Type)` instantiates it.
+#[cfg(test)]
+#[macro_export]
+macro_rules! value_repr_tests {
+    ($repr: ty) => {
+        use proptest::prelude::*;
+        use $crate::runtime::pure_nan::*;
+        use $crate::runtime::value_repr::ValueRepr;
+        use $crate::runtime::Runtime;
+
+        type Repr = $repr;
+
+        /// Type predicates of `value` in order: int32, double, cell, boolean, undefined or null.
+        fn predicates(value: Repr) -> [bool; 5] {
+            [
+                value.is_int32(),
+                value.is_double(),
+                value.is_cell(),
+                value.is_boolean(),
+                value.is_undefined_or_null(),
+            ]
+        }
+
+        const INT32: [bool; 5] = [true, false, false, false, false];
+        const DOUBLE: [bool; 5] = [false, true, false, false, false];
+        const CELL: [bool; 5] = [false, false, true, false, false];
+        const BOOLEAN: [bool; 5] = [false, false, false, true, false];
+        const UNDEFINED_OR_NULL: [bool; 5] = [false, false, false, false, true];
+
+        proptest! {
+            #[test]
+            fn int32_round_trips(x in any::<i32>()) {
+                let value = Repr::int32(x);
+                prop_assert_eq!(predicates(value), INT32);
+                prop_assert!(value.is_number() && !value.is_empty());
+                prop_assert_eq!(value.as_int32(), x);
+            }
+
+            #[test]
+            fn double_round_trips(bits in any::<u64>()) {
+                let x = f64::from_bits(bits);
+                let value = Repr::double(x);
+                prop_assert_eq!(predicates(value), DOUBLE);
+                prop_assert!(value.is_number() && !value.is_empty());
+                if x.is_nan() {
+                    prop_assert!(value.as_double().is_nan());
+                    prop_assert!(!is_impure_nan(value.as_double()));
+                } else {
+                    prop_assert_eq!(value.as_double().to_bits(), bits);
+                }
+            }
+
+            #[test]
+            fn impure_nans_are_purified(payload in 0u64..(1 << 49)) {
+                let x = f64::from_bits(0xfffe000000000000 | payload);
+                prop_assert!(is_impure_nan(x));
+                let value = Repr::double(x);
+                prop_assert_eq!(predicates(value), DOUBLE);
+                prop_assert_eq!(value.as_double().to_bits(), pure_nan().to_bits());
+            }
+
+            #[test]
+            fn equal_encodings_are_equal_values(x in any::<i32>(), y in any::<i32>()) {
+                prop_assert_eq!(Repr::int32(x) == Repr::int32(y), x == y);
+                prop_assert!(Repr::int32(x) != Repr::undefined());
+            }
+        }
+
+        #[test]
+        fn immediates() {
+            let true_ = Repr::boolean(true);
+            let false_ = Repr::boolean(false);
+            assert_eq!(predicates(true_), BOOLEAN);
+            assert_eq!(predicates(false_), BOOLEAN);
+            assert!(true_.is_true() && !true_.is_false());
+            assert!(false_.is_false() && !false_.is_true());
+            assert!(true_ != false_);
+            for value in [Repr::undefined(), Repr::null()] {
+                assert_eq!(predicates(value), UNDEFINED_OR_NULL);
+                assert!(!value.is_number() && !value.is_empty());
+                assert!(!value.is_true() && !value.is_false());
+            }
+            assert!(Repr::undefined().is_undefined() && !Repr::undefined().is_null());
+            assert!(Repr::null().is_null() && !Repr::null().is_undefined());
+            let empty = Repr::empty();
+            assert!(empty.is_empty());
+            assert!(!empty.is_number() && !empty.is_boolean() && !empty.is_undefined_or_null());
+            for value in [true_, false_, Repr::undefined(), Repr::null()] {
+                assert!(!value.is_empty());
+            }
+        }
+
+        #[test]
+        fn cells_round_trip() {
+            let mut rt = Runtime::new();
+            let mut cells = vec![rt.new_object().as_cell()];
+            for i in 0..64 {
+                cells.push(rt.new_string(&i.to_string()).as_cell());
+            }
+            for (i, cell) in cells.iter().enumerate() {
+                let value = Repr::cell(*cell);
+                assert_eq!(predicates(value), CELL);
+                assert!(!value.is_number() && !value.is_empty());
+                assert!(std::ptr::eq(value.as_cell().get(), cell.get()));
+                assert!(value == Repr::cell(*cell));
+                for (j, other) in cells.iter().enumerate() {
+                    assert_eq!(value == Repr::cell(*other), i == j);
+                }
+            }
+        }
+    };
+}
@@ -1,56 +1,77 @@
 use super::*;
-use jsobject::*;
+use comparison::*;
 use jsproperty::*;
+
+/// Callback invoked for every property name visited by `enumerate`, returning `false` stops the enumeration.
+pub type EnumerateCallback = fn(&mut Runtime, &str) -> bool;
+
 pub struct VTable {
     pub get_own_property: fn(rt: &mut Runtime, _: JSValue, name: &str) -> Option<JSProperty>,
     pub get_property: fn(rt: &mut Runtime, _: JSValue, name: &str) -> Option<JSProperty>,
     pub get: fn(rt: &mut Runtime, _: JSValue, name: &str) -> Result<JSValue, JSValue>,
     pub can_put: fn(rt: &mut Runtime, _: JSValue, name: &str) -> bool,
-    pub put: fn(rt: &mut Runtime, _: JSValue, name: &str, val: JSValue, s: bool),
+    /// Sets property, when `s` (strict) is set failed assignments throw TypeError instead of being ignored.
+    pub put:
+        fn(rt: &mut Runtime, _: JSValue, name: &str, val: JSValue, s: bool) -> Result<(), JSValue>,
     pub has_property: fn(rt: &mut Runtime, _: JSValue, name: &str) -> bool,
     pub has_own_property: fn(rt: &mut Runtime, _: JSValue, name: &str) -> bool,
+    /// Returns `Ok(false)` when property can't be defined, or throws TypeError if the last argument is `true`.
     pub define_own_property:
-        fn(rt: &mut Runtime, _: JSValue, _: &str, _: JSProperty, _: bool) -> bool,
-    pub delete: fn(rt: &mut Runtime, _: JSValue, _: &str, _: bool) -> bool,
-    pub enumerate: fn(rt: &mut Runtime, _: JSValue, _: bool, _: fn(&mut Runtime, &str) -> bool),
+        fn(rt: &mut Runtime, _: JSValue, _: &str, _: JSProperty, _: bool) -> Result<bool, JSValue>,
+    /// Returns `Ok(false)` for non-configurable properties, or throws TypeError if the last argument is `true`.
+    pub delete: fn(rt: &mut Runtime, _: JSValue, _: &str, _: bool) -> Result<bool, JSValue>,
+    pub enumerate: fn(rt: &mut Runtime, _: JSValue, _: bool, _: EnumerateCallback),
+    /// Returns `false` if the prototype can't be changed, `proto` is an object or null.
+    pub set_prototype_of: fn(rt: &mut Runtime, _: JSValue, proto: JSValue) -> bool,
 }
 
-pub fn object_enumerate(
-    rt: &mut Runtime,
-    this: &mut JSObject,
-    all: bool,
-    each: fn(&mut Runtime, &str) -> bool,
-) {
+/// VTable used by ordinary objects.
+pub static OBJECT_VTABLE: VTable = VTable {
+    get_own_property: object_get_own_property,
+    get_property: object_get_property,
+    get: object_get,
+    can_put: object_can_put,
+    put: object_put,
+    has_property: object_has_property,
+    has_own_property: object_has_own_property,
+    define_own_property: object_define_own_property,
+    delete: object_delete,
+    enumerate: object_enumerate,
+    set_prototype_of: object_set_prototype_of,
+};
+
+pub fn object_enumerate(rt: &mut Runtime, object: JSValue, all: bool, each: EnumerateCallback) {
+    let mut c = object.get_object(rt).as_cell();
+    let this = c.unchecked_object_mut();
     for (name, prop) in this.property.iter() {
-        if all || prop.enumerable.unwrap_or(false) {
-            if !each(rt, name) {
-                return;
-            }
+        if (all || prop.enumerable.unwrap_or(false)) && !each(rt, name) {
+            return;
         }
     }
 }
 
 pub fn object_get_own_property(
-    _rt: &mut Runtime,
-    object: &mut JSObject,
+    rt: &mut Runtime,
+    object: JSValue,
     name: &str,
 ) -> Option<JSProperty> {
-    object._read(name)
+    let mut c = object.get_object(rt).as_cell();
+    c.unchecked_object_mut()._read(name)
 }
 
 pub fn object_get_property(rt: &mut Runtime, object: JSValue, name: &str) -> Option<JSProperty> {
     let mut c = object.get_object(rt).as_cell();
     let obj = c.unchecked_object_mut();
     if let Some(p) = (obj.class_object.get_own_property)(rt, object, name) {
-        return Some(p);
+        Some(p)
     } else if let Some(proto) = obj.prototype {
-        return (proto
+        (proto
             .get_object(rt)
             .as_cell()
             .get_mut()
             .unchecked_object_mut()
             .class_object
-            .get_property)(rt, proto.get_object(rt), name);
+            .get_property)(rt, proto.get_object(rt), name)
     } else {
         None
     }
@@ -62,7 +83,10 @@ pub fn object_get(rt: &mut Runtime, object: JSValue, name: &str) -> Result<JSVal
     let prop = (obj.class_object.get_property)(rt, object, name);
     if let Some(prop) = prop {
         if prop.is_accessor() {
-            return rt.execute(prop.getter.unwrap(), object, &[]);
+            match prop.getter {
+                Some(getter) if !getter.is_undefined() => rt.execute(getter, object, &[]),
+                _ => Ok(JSValue::undefined()),
+            }
         } else {
             Ok(prop.value.unwrap_or(JSValue::undefined()))
         }
@@ -70,3 +94,283 @@ pub fn object_get(rt: &mut Runtime, object: JSValue, name: &str) -> Result<JSVal
         Ok(JSValue::undefined())
     }
 }
+
+/// [[CanPut]](https://262.ecma-international.org/5.1/#sec-8.12.4)
+pub fn object_can_put(rt: &mut Runtime, object: JSValue, name: &str) -> bool {
+    let mut c = object.get_object(rt).as_cell();
+    let obj = c.unchecked_object_mut();
+    if let Some(desc) = (obj.class_object.get_own_property)(rt, object, name) {
+        if desc.is_accessor() {
+            return desc.setter.map(|s| !s.is_undefined()).unwrap_or(false);
+        }
+        return desc.writable.unwrap_or(false);
+    }
+    let proto = match obj.prototype {
+        Some(proto) if proto.is_cell() => proto,
+        _ => return obj.extensible,
+    };
+    let inherited = (proto
+        .as_cell()
+        .get_mut()
+        .unchecked_object_mut()
+        .class_object
+        .get_property)(rt, proto, name);
+    match inherited {
+        None => obj.extensible,
+        Some(inherited) if inherited.is_accessor() => {
+            inherited.setter.map(|s| !s.is_undefined()).unwrap_or(false)
+        }
+        Some(inherited) => obj.extensible && inherited.writable.unwrap_or(false),
+    }
+}
+
+fn reject(rt: &mut Runtime, throw: bool, msg: &str) -> Result<bool, JSValue> {
+    if throw {
+        return Err(rt.new_type_error(msg));
+    }
+    Ok(false)
+}
+
+/// [[Put]](https://262.ecma-international.org/5.1/#sec-8.12.5)
+pub fn object_put(
+    rt: &mut Runtime,
+    object: JSValue,
+    name: &str,
+    val: JSValue,
+    throw: bool,
+) -> Result<(), JSValue> {
+    let class = object
+        .get_object(rt)
+        .as_cell()
+        .unchecked_object_mut()
+        .class_object;
+    // Exotic `get_own_property` may allocate, so `object` and `val` are re-read after every call.
+    let scope = HandleScope::new(rt);
+    let object = scope.root(object);
+    let val = scope.root(val);
+    if !(class.can_put)(rt, object.get(), name) {
+        if throw {
+            let msg = format!("Cannot assign to read only property '{}' of object", name);
+            return Err(rt.new_type_error(&msg));
+        }
+        return Ok(());
+    }
+    if let Some(own) = (class.get_own_property)(rt, object.get(), name) {
+        if own.is_data() {
+            let desc = JSProperty::new().value(val.get());
+            (class.define_own_property)(rt, object.get(), name, desc, throw)?;
+            return Ok(());
+        }
+    }
+    match (class.get_property)(rt, object.get(), name) {
+        Some(desc) if desc.is_accessor() => {
+            // `can_put` returned true so there is a setter.
+            rt.execute(desc.setter.unwrap(), object.get(), &[val.get()])?;
+        }
+        _ => {
+            (class.define_own_property)(
+                rt,
+                object.get(),
+                name,
+                JSProperty::new()
+                    .value(val.get())
+                    .writable(true)
+                    .enumerable(true)
+                    .configurable(true),
+                throw,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn object_has_property(rt: &mut Runtime, object: JSValue, name: &str) -> bool {
+    let mut c = object.get_object(rt).as_cell();
+    (c.unchecked_object_mut().class_object.get_property)(rt, object, name).is_some()
+}
+
+pub fn object_has_own_property(rt: &mut Runtime, object: JSValue, name: &str) -> bool {
+    let mut c = object.get_object(rt).as_cell();
+    (c.unchecked_object_mut().class_object.get_own_property)(rt, object, name).is_some()
+}
+
+/// [[DefineOwnProperty]](https://262.ecma-international.org/5.1/#sec-8.12.9)
+pub fn object_define_own_property(
+    rt: &mut Runtime,
+    object: JSValue,
+    name: &str,
+    desc: JSProperty,
+    throw: bool,
+) -> Result<bool, JSValue> {
+    let owner = object.get_object(rt);
+    let mut c = owner.as_cell();
+    let obj = c.unchecked_object_mut();
+    let current = match (obj.class_object.get_own_property)(rt, object, name) {
+        Some(current) => current,
+        None => {
+            if !obj.extensible {
+                let msg = format!(
+                    "Cannot define property '{}', object is not extensible",
+                    name
+                );
+                return reject(rt, throw, &msg);
+            }
+            let prop = if desc.is_accessor() {
+                JSProperty::new()
+                    .getter(desc.getter.unwrap_or(JSValue::undefined()))
+                    .setter(desc.setter.unwrap_or(JSValue::undefined()))
+                    .enumerable(desc.enumerable.unwrap_or(false))
+                    .configurable(desc.configurable.unwrap_or(false))
+            } else {
+                JSProperty::new()
+                    .value(desc.value.unwrap_or(JSValue::undefined()))
+                    .writable(desc.writable.unwrap_or(false))
+                    .enumerable(desc.enumerable.unwrap_or(false))
+                    .configurable(desc.configurable.unwrap_or(false))
+            };
+            rt.property_write_barrier(owner, &prop);
+            obj._write(name, prop);
+            return Ok(true);
+        }
+    };
+    if desc.is_empty() && desc.value.is_none() {
+        return Ok(true);
+    }
+    let msg = format!("Cannot redefine property: {}", name);
+    let configurable = current.configurable.unwrap_or(false);
+    if !configurable {
+        if desc.configurable == Some(true) {
+            return reject(rt, throw, &msg);
+        }
+        if let Some(enumerable) = desc.enumerable {
+            if enumerable != current.enumerable.unwrap_or(false) {
+                return reject(rt, throw, &msg);
+            }
+        }
+    }
+    let mut new = current.clone();
+    if desc.is_generic() {
+        // Only attributes are changed.
+    } else if current.is_data() != desc.is_data() {
+        if !configurable {
+            return reject(rt, throw, &msg);
+        }
+        new = if current.is_data() {
+            JSProperty::new()
+                .getter(JSValue::undefined())
+                .setter(JSValue::undefined())
+        } else {
+            JSProperty::new()
+                .value(JSValue::undefined())
+                .writable(false)
+        };
+        new.configurable = current.configurable;
+        new.enumerable = current.enumerable;
+    } else if current.is_data() {
+        if !configurable && !current.writable.unwrap_or(false) {
+            if desc.writable == Some(true) {
+                return reject(rt, throw, &msg);
+            }
+            if let Some(value) = desc.value {
+                if !same_value(value, current.value.unwrap_or(JSValue::undefined())) {
+                    return reject(rt, throw, &msg);
+                }
+            }
+        }
+    } else if !configurable {
+        if let Some(setter) = desc.setter {
+            if !same_value(setter, current.setter.unwrap_or(JSValue::undefined())) {
+                return reject(rt, throw, &msg);
+            }
+        }
+        if let Some(getter) = desc.getter {
+            if !same_value(getter, current.getter.unwrap_or(JSValue::undefined())) {
+                return reject(rt, throw, &msg);
+            }
+        }
+    }
+    if let Some(value) = desc.value {
+        new.value = Some(value);
+    }
+    if let Some(writable) = desc.writable {
+        new.writable = Some(writable);
+    }
+    if let Some(getter) = desc.getter {
+        new.getter = Some(getter);
+    }
+    if let Some(setter) = desc.setter {
+        new.setter = Some(setter);
+    }
+    if let Some(enumerable) = desc.enumerable {
+        new.enumerable = Some(enumerable);
+    }
+    if let Some(configurable) = desc.configurable {
+        new.configurable = Some(configurable);
+    }
+    rt.property_write_barrier(owner, &new);
+    obj._write(name, new);
+    Ok(true)
+}
+
+/// [OrdinarySetPrototypeOf](https://tc39.es/ecma262/#sec-ordinarysetprototypeof)
+pub fn object_set_prototype_of(rt: &mut Runtime, object: JSValue, proto: JSValue) -> bool {
+    let mut c = object.as_cell();
+    let obj = c.unchecked_object_mut();
+    if obj.prototype.unwrap_or_else(JSValue::null) == proto {
+        return true;
+    }
+    if !obj.extensible {
+        return false;
+    }
+    let mut p = proto;
+    while p.is_cell() {
+        if p == object {
+            return false;
+        }
+        p = p
+            .as_cell()
+            .unchecked_object_mut()
+            .prototype
+            .unwrap_or_else(JSValue::null);
+    }
+    obj.prototype = if proto.is_null() { None } else { Some(proto) };
+    rt.write_barrier(object, proto);
+    true
+}
+
+/// Stores `value` at `index` of array `array`, growing it with `undefined` up to `index`.
+pub fn array_set_element(rt: &mut Runtime, array: JSValue, index: usize, value: JSValue) {
+    let mut c = array.as_cell();
+    match &mut c.unchecked_object_mut().kind {
+        JSObjectKind::Array(elements) => {
+            if index >= elements.len() {
+                elements.resize(index + 1, JSValue::undefined());
+            }
+            elements[index] = value;
+        }
+        _ => unreachable!("array_set_element requires an array"),
+    }
+    rt.write_barrier(array, value);
+}
+
+/// [[Delete]](https://262.ecma-international.org/5.1/#sec-8.12.7)
+pub fn object_delete(
+    rt: &mut Runtime,
+    object: JSValue,
+    name: &str,
+    throw: bool,
+) -> Result<bool, JSValue> {
+    let mut c = object.get_object(rt).as_cell();
+    let obj = c.unchecked_object_mut();
+    match (obj.class_object.get_own_property)(rt, object, name) {
+        None => Ok(true),
+        Some(desc) if desc.configurable.unwrap_or(false) => {
+            obj.property.remove(name);
+            Ok(true)
+        }
+        _ => {
+            let msg = format!("Cannot delete property '{}' of object", name);
+            reject(rt, throw, &msg)
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! `WeakMap`, `WeakSet`, `WeakRef` and `FinalizationRegistry` constructors and prototype methods.
+//!
+//! Like the `number` and `bigint` modules these are written as native functions.
+//!
+//! TODO: WeakMap and WeakSet constructors should add entries of their iterable argument.
+use super::cell::*;
+use super::comparison::*;
+use super::jsobject::*;
+use super::jsvalue::*;
+use super::jsweak::*;
+use super::Runtime;
+
+fn argument(args: &[JSValue], index: usize) -> JSValue {
+    args.get(index).copied().unwrap_or_else(JSValue::undefined)
+}
+
+/// Throws TypeError unless the constructor was called with `new`.
+fn require_new(rt: &mut Runtime, constructor: &str) -> Result<(), JSValue> {
+    if rt.new_target().is_undefined() {
+        let msg = format!("Constructor {} requires 'new'", constructor);
+        return Err(rt.new_type_error(&msg));
+    }
+    Ok(())
+}
+
+/// Throws TypeError unless `this` is an object of the kind `is_kind` accepts.
+fn check_receiver(
+    rt: &mut Runtime,
+    this: JSValue,
+    is_kind: fn(&JSObjectKind) -> bool,
+    method: &str,
+) -> Result<(), JSValue> {
+    if this.is_object() {
+        if let Cell::Object(object) = &*this.as_cell() {
+            if is_kind(&object.kind) {
+                return Ok(());
+            }
+        }
+    }
+    let msg = format!("Method {} called on incompatible receiver", method);
+    Err(rt.new_type_error(&msg))
+}
+
+fn is_weak_map(kind: &JSObjectKind) -> bool {
+    matches!(kind, JSObjectKind::WeakMap(_))
+}
+
+fn is_weak_set(kind: &JSObjectKind) -> bool {
+    matches!(kind, JSObjectKind::WeakSet(_))
+}
+
+fn is_weak_ref(kind: &JSObjectKind) -> bool {
+    matches!(kind, JSObjectKind::WeakRef(_))
+}
+
+fn is_finalization_registry(kind: &JSObjectKind) -> bool {
+    matches!(kind, JSObjectKind::FinalizationRegistry(_))
+}
+
+/// Calls `f` with entries of WeakMap or WeakSet `this`.
+fn with_entries<R>(this: JSValue, f: impl FnOnce(&mut WeakMapData) -> R) -> R {
+    match &mut this.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::WeakMap(map) | JSObjectKind::WeakSet(map) => f(map),
+        _ => unreachable!(),
+    }
+}
+
+fn with_registry<R>(this: JSValue, f: impl FnOnce(&mut FinalizationRegistryData) -> R) -> R {
+    match &mut this.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::FinalizationRegistry(registry) => f(registry),
+        _ => unreachable!(),
+    }
+}
+
+/// [WeakMap ( [ iterable ] )](https://tc39.es/ecma262/#sec-weakmap-iterable)
+pub fn weak_map_constructor(
+    rt: &mut Runtime,
+    _this: JSValue,
+    _args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    require_new(rt, "WeakMap")?;
+    Ok(rt.new_weak_container(JSObjectKind::WeakMap(WeakMapData::new())))
+}
+
+/// [WeakMap.prototype.get](https://tc39.es/ecma262/#sec-weakmap.prototype.get)
+pub fn weak_map_proto_get(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_map, "WeakMap.prototype.get")?;
+    let key = argument(args, 0);
+    Ok(with_entries(this, |map| map.get(key)).unwrap_or_else(JSValue::undefined))
+}
+
+/// [WeakMap.prototype.set](https://tc39.es/ecma262/#sec-weakmap.prototype.set)
+pub fn weak_map_proto_set(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_map, "WeakMap.prototype.set")?;
+    let (key, value) = (argument(args, 0), argument(args, 1));
+    if !can_be_held_weakly(key) {
+        return Err(rt.new_type_error("Invalid value used as weak map key"));
+    }
+    with_entries(this, |map| map.set(key, value));
+    rt.write_barrier(this, key);
+    rt.write_barrier(this, value);
+    Ok(this)
+}
+
+/// [WeakMap.prototype.has](https://tc39.es/ecma262/#sec-weakmap.prototype.has)
+pub fn weak_map_proto_has(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_map, "WeakMap.prototype.has")?;
+    let key = argument(args, 0);
+    Ok(JSValue::new_bool(with_entries(this, |map| map.has(key))))
+}
+
+/// [WeakMap.prototype.delete](https://tc39.es/ecma262/#sec-weakmap.prototype.delete)
+pub fn weak_map_proto_delete(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_map, "WeakMap.prototype.delete")?;
+    let key = argument(args, 0);
+    Ok(JSValue::new_bool(with_entries(this, |map| map.delete(key))))
+}
+
+/// [WeakSet ( [ iterable ] )](https://tc39.es/ecma262/#sec-weakset-iterable)
+pub fn weak_set_constructor(
+    rt: &mut Runtime,
+    _this: JSValue,
+    _args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    require_new(rt, "WeakSet")?;
+    Ok(rt.new_weak_container(JSObjectKind::WeakSet(WeakMapData::new())))
+}
+
+/// [WeakSet.prototype.add](https://tc39.es/ecma262/#sec-weakset.prototype.add)
+pub fn weak_set_proto_add(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_set, "WeakSet.prototype.add")?;
+    let value = argument(args, 0);
+    if !can_be_held_weakly(value) {
+        return Err(rt.new_type_error("Invalid value used in weak set"));
+    }
+    with_entries(this, |set| set.set(value, JSValue::undefined()));
+    rt.write_barrier(this, value);
+    Ok(this)
+}
+
+/// [WeakSet.prototype.has](https://tc39.es/ecma262/#sec-weakset.prototype.has)
+pub fn weak_set_proto_has(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_set, "WeakSet.prototype.has")?;
+    let value = argument(args, 0);
+    Ok(JSValue::new_bool(with_entries(this, |set| set.has(value))))
+}
+
+/// [WeakSet.prototype.delete](https://tc39.es/ecma262/#sec-weakset.prototype.delete)
+pub fn weak_set_proto_delete(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_set, "WeakSet.prototype.delete")?;
+    let value = argument(args, 0);
+    Ok(JSValue::new_bool(with_entries(this, |set| {
+        set.delete(value)
+    })))
+}
+
+/// [WeakRef ( target )](https://tc39.es/ecma262/#sec-weak-ref-target)
+pub fn weak_ref_constructor(
+    rt: &mut Runtime,
+    _this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    require_new(rt, "WeakRef")?;
+    let target = argument(args, 0);
+    if !can_be_held_weakly(target) {
+        return Err(rt.new_type_error("WeakRef: target must be an object"));
+    }
+    rt.add_to_kept_objects(target);
+    Ok(rt.new_weak_container(JSObjectKind::WeakRef(target)))
+}
+
+/// [WeakRef.prototype.deref](https://tc39.es/ecma262/#sec-weak-ref.prototype.deref), the target
+/// stays alive until the current job finishes.
+pub fn weak_ref_proto_deref(
+    rt: &mut Runtime,
+    this: JSValue,
+    _args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    check_receiver(rt, this, is_weak_ref, "WeakRef.prototype.deref")?;
+    let target = match this.as_cell().unchecked_object_mut().kind {
+        JSObjectKind::WeakRef(target) => target,
+        _ => unreachable!(),
+    };
+    if !target.is_undefined() {
+        rt.add_to_kept_objects(target);
+    }
+    Ok(target)
+}
+
+/// [FinalizationRegistry ( cleanupCallback )](https://tc39.es/ecma262/#sec-finalization-registry-cleanup-callback)
+pub fn finalization_registry_constructor(
+    rt: &mut Runtime,
+    _this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    require_new(rt, "FinalizationRegistry")?;
+    let cleanup = argument(args, 0);
+    if !cleanup.is_callable() {
+        return Err(rt.new_type_error("FinalizationRegistry: cleanup must be callable"));
+    }
+    let registry = FinalizationRegistryData::new(cleanup);
+    Ok(rt.new_weak_container(JSObjectKind::FinalizationRegistry(registry)))
+}
+
+/// [FinalizationRegistry.prototype.register](https://tc39.es/ecma262/#sec-finalization-registry.prototype.register)
+pub fn finalization_registry_proto_register(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let method = "FinalizationRegistry.prototype.register";
+    check_receiver(rt, this, is_finalization_registry, method)?;
+    let (target, holdings, token) = (argument(args, 0), argument(args, 1), argument(args, 2));
+    if !can_be_held_weakly(target) {
+        return Err(rt.new_type_error("FinalizationRegistry.prototype.register: invalid target"));
+    }
+    if same_value(target, holdings) {
+        return Err(rt.new_type_error(
+            "FinalizationRegistry.prototype.register: target and holdings must not be same",
+        ));
+    }
+    let unregister_token = if can_be_held_weakly(token) {
+        Some(token)
+    } else if token.is_undefined() {
+        None
+    } else {
+        return Err(
+            rt.new_type_error("FinalizationRegistry.prototype.register: invalid unregister token")
+        );
+    };
+    with_registry(this, |registry| {
+        registry.cells.push(FinalizationCell {
+            target,
+            holdings,
+            unregister_token,
+        })
+    });
+    rt.write_barrier(this, target);
+    rt.write_barrier(this, holdings);
+    rt.write_barrier(this, token);
+    Ok(JSValue::undefined())
+}
+
+/// [FinalizationRegistry.prototype.unregister](https://tc39.es/ecma262/#sec-finalization-registry.prototype.unregister)
+pub fn finalization_registry_proto_unregister(
+    rt: &mut Runtime,
+    this: JSValue,
+    args: &[JSValue],
+) -> Result<JSValue, JSValue> {
+    let method = "FinalizationRegistry.prototype.unregister";
+    check_receiver(rt, this, is_finalization_registry, method)?;
+    let token = argument(args, 0);
+    if !can_be_held_weakly(token) {
+        return Err(rt.new_type_error(
+            "FinalizationRegistry.prototype.unregister: invalid unregister token",
+        ));
+    }
+    Ok(JSValue::new_bool(with_registry(this, |registry| {
+        registry.unregister(token)
+    })))
+}
+
+pub(crate) fn install(rt: &mut Runtime) {
+    let proto = rt.weak_map;
+    rt.define_native_constructor("WeakMap", 0, proto, weak_map_constructor);
+    rt.define_native_method(proto, "get", 1, weak_map_proto_get);
+    rt.define_native_method(proto, "set", 2, weak_map_proto_set);
+    rt.define_native_method(proto, "has", 1, weak_map_proto_has);
+    rt.define_native_method(proto, "delete", 1, weak_map_proto_delete);
+
+    let proto = rt.weak_set;
+    rt.define_native_constructor("WeakSet", 0, proto, weak_set_constructor);
+    rt.define_native_method(proto, "add", 1, weak_set_proto_add);
+    rt.define_native_method(proto, "has", 1, weak_set_proto_has);
+    rt.define_native_method(proto, "delete", 1, weak_set_proto_delete);
+
+    let proto = rt.weak_ref;
+    rt.define_native_constructor("WeakRef", 1, proto, weak_ref_constructor);
+    rt.define_native_method(proto, "deref", 0, weak_ref_proto_deref);
+
+    let proto = rt.finalization_registry;
+    let constructor = finalization_registry_constructor;
+    rt.define_native_constructor("FinalizationRegistry", 1, proto, constructor);
+    rt.define_native_method(proto, "register", 2, finalization_registry_proto_register);
+    rt.define_native_method(
+        proto,
+        "unregister",
+        1,
+        finalization_registry_proto_unregister,
+    );
+}